@@ -0,0 +1,163 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use crate::db;
+use crate::models::RestoreMode;
+
+/*---------------------------------------------------------------------------
+    Command line.
+
+    Without arguments the service starts. Otherwise:
+
+    backup <file>                          write an encrypted backup to <file>
+    restore <file> [--dry-run] [--replace] [--force]
+                                           restore the backup in <file>
+
+    Both open the storage backend configured for the service and read the
+    backup passphrase from [ECS_BACKUP_PASSPHRASE]. A restore merges the
+    backup into the vault unless --replace is given, and is refused when
+    the master keys do not match the backup unless --force is given.
+---------------------------------------------------------------------------*/
+
+pub const USAGE: &str =
+    "usage: ec_secrets_management [backup <file> | restore <file> [--dry-run] [--replace] [--force]]";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Backup {
+        path: String,
+    },
+    Restore {
+        path: String,
+        mode: RestoreMode,
+        dry_run: bool,
+        force: bool,
+    },
+}
+
+/// Reads the command from the arguments following the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let Some(command) = args.next() else {
+        return Ok(Command::Serve);
+    };
+    let path = args.next().ok_or_else(|| USAGE.to_string())?;
+
+    match command.as_str() {
+        "backup" => match args.next() {
+            None => Ok(Command::Backup { path }),
+            Some(_) => Err(USAGE.to_string()),
+        },
+        "restore" => {
+            let mut mode = RestoreMode::Merge;
+            let mut dry_run = false;
+            let mut force = false;
+            for flag in args {
+                match flag.as_str() {
+                    "--dry-run" => dry_run = true,
+                    "--replace" => mode = RestoreMode::Replace,
+                    "--force" => force = true,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            Ok(Command::Restore {
+                path,
+                mode,
+                dry_run,
+                force,
+            })
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Runs a backup or restore command, returning the message to exit with on failure
+pub async fn run(command: Command) -> Result<(), String> {
+    dotenvy::dotenv().ok();
+
+    let passphrase = std::env::var("ECS_BACKUP_PASSPHRASE")
+        .map_err(|_| "[ECS_BACKUP_PASSPHRASE] must be set...".to_string())?;
+    let backup = db::connect()
+        .await
+        .map_err(|e| format!("Cannot connect to instance:: {:?}", e))?
+        .backup;
+
+    match command {
+        Command::Serve => Ok(()),
+        Command::Backup { path } => {
+            let bundle = backup
+                .backup(&passphrase)
+                .await
+                .map_err(|e| e.to_string())?;
+            // Never overwrite an earlier backup
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(&bundle))
+                .map_err(|e| format!("Cannot write {}: {}", path, e))?;
+            println!("Backup written to {}", path);
+            Ok(())
+        }
+        Command::Restore {
+            path,
+            mode,
+            dry_run,
+            force,
+        } => {
+            let bundle =
+                std::fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+            let report = backup
+                .restore(&bundle, &passphrase, mode, dry_run, force)
+                .await
+                .map_err(|e| e.to_string())?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+            );
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(parse(args("")), Ok(Command::Serve));
+        assert_eq!(
+            parse(args("backup vault.ecsb")),
+            Ok(Command::Backup {
+                path: "vault.ecsb".to_string()
+            })
+        );
+        assert_eq!(
+            parse(args("restore vault.ecsb --replace --dry-run")),
+            Ok(Command::Restore {
+                path: "vault.ecsb".to_string(),
+                mode: RestoreMode::Replace,
+                dry_run: true,
+                force: false,
+            })
+        );
+        assert_eq!(
+            parse(args("restore vault.ecsb --force")),
+            Ok(Command::Restore {
+                path: "vault.ecsb".to_string(),
+                mode: RestoreMode::Merge,
+                dry_run: false,
+                force: true,
+            })
+        );
+        assert!(parse(args("backup")).is_err());
+        assert!(parse(args("restore vault.ecsb --overwrite")).is_err());
+        assert!(parse(args("export vault.ecsb")).is_err());
+    }
+}
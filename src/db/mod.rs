@@ -1,25 +1,73 @@
-#![allow(unused)]
 use dotenvy::dotenv;
-use mongodb::{options::ClientOptions, Client, Database};
+use mongodb::{options::ClientOptions, Client};
 use rocket::fairing::AdHoc;
-use std::{env, sync::Arc};
+use std::sync::Arc;
 
 /*-------------
 Custom modules
 ---------------*/
+use crate::repositories::backup::BackupRepository;
+use crate::repositories::generator::GeneratorRepository;
 use crate::repositories::key::KeyRepository;
+use crate::repositories::seal::{KeyCheckFailure, SealRepository};
+use crate::repositories::signing::SigningRepository;
+use crate::repositories::transit::TransitRepository;
 use crate::repositories::users::UserRepository;
 use crate::repositories::vault::VaultRepository;
+use crate::storage::{
+    memory::MemoryStore, mongo::MongoStore, sqlite::SqliteStore, AuditStore, BackupStore,
+    GeneratorStore, KeyStore, SigningStore, TransitStore, UserStore, VaultStore,
+};
 
+/// Every repository, all sharing one storage backend
+pub struct Repositories {
+    pub user: Arc<UserRepository>,
+    pub vault: Arc<VaultRepository>,
+    pub keys: Arc<KeyRepository>,
+    pub seal: Arc<SealRepository>,
+    pub transit: Arc<TransitRepository>,
+    pub signing: Arc<SigningRepository>,
+    pub generator: Arc<GeneratorRepository>,
+    pub backup: Arc<BackupRepository>,
+}
+
+/*---------------------------------------------------------------------------
+    The storage backend is selected through [ECS_STORAGE_BACKEND]:
+
+    - mongodb (default): requires [ECS_DATABASE_URL] and [ECS_DATABASE_NAME]
+    - sqlite: embedded database stored at [ECS_SQLITE_PATH] (defaults to
+      ecs_vault.db), the schema is created on first start
+    - memory: keeps everything in process memory, nothing is persisted
+
+    The master key comes from the key provider selected through
+    [ECS_KEY_PROVIDER], see utils::key_provider. Without a key the vault
+    starts sealed, see repositories::seal. A key that does not match its
+    key check value stops the service here, unless [ECS_KEY_CHECK_FAILURE]
+    is `seal`.
+---------------------------------------------------------------------------*/
 pub fn init() -> AdHoc {
     AdHoc::on_ignite(
         "Establish connection with Database cluster",
         |rocket| async {
             match connect().await {
-                Ok((user_repository, vault_repository, key_repository)) => rocket
-                    .manage(user_repository)
-                    .manage(vault_repository)
-                    .manage(key_repository),
+                Ok(repositories) => {
+                    if let Err(error) = repositories
+                        .seal
+                        .check_master_keys(KeyCheckFailure::from_env())
+                        .await
+                    {
+                        panic!("Refusing to start:: {}", error)
+                    }
+                    rocket
+                        .manage(repositories.user)
+                        .manage(repositories.vault)
+                        .manage(repositories.keys)
+                        .manage(repositories.seal)
+                        .manage(repositories.transit)
+                        .manage(repositories.signing)
+                        .manage(repositories.generator)
+                        .manage(repositories.backup)
+                }
                 Err(error) => {
                     panic!("Cannot connect to instance:: {:?}", error)
                 }
@@ -28,34 +76,88 @@ pub fn init() -> AdHoc {
     )
 }
 
-async fn connect() -> mongodb::error::Result<(
-    Arc<UserRepository>,
-    Arc<VaultRepository>,
-    Arc<KeyRepository>,
-)> {
+/// Opens the configured backend, as the server and the command line both do
+pub async fn connect() -> mongodb::error::Result<Repositories> {
     dotenv().ok();
 
-    let database_url = std::env::var_os("ECS_DATABASE_URL")
-        .expect("[ECS_DATABASE_URL] must be set...")
-        .into_string()
-        .unwrap();
+    let backend = std::env::var("ECS_STORAGE_BACKEND").unwrap_or_else(|_| "mongodb".to_string());
+
+    match backend.as_str() {
+        "memory" => {
+            dbg!("Using in-memory vault storage, nothing will be persisted...");
+            Ok(repositories(Arc::new(MemoryStore::new())))
+        }
+        "sqlite" => {
+            let path =
+                std::env::var("ECS_SQLITE_PATH").unwrap_or_else(|_| "ecs_vault.db".to_string());
+            let store = SqliteStore::open(&path)
+                .unwrap_or_else(|error| panic!("Cannot open sqlite database {}:: {:?}", path, error));
+
+            dbg!("Successfully initialized sqlite vault database...");
+
+            Ok(repositories(Arc::new(store)))
+        }
+        "mongodb" => {
+            let database_url = std::env::var_os("ECS_DATABASE_URL")
+                .expect("[ECS_DATABASE_URL] must be set...")
+                .into_string()
+                .unwrap();
+
+            let database_name = std::env::var_os("ECS_DATABASE_NAME")
+                .expect("[ECS_DATABASE_NAME] must be set...")
+                .into_string()
+                .unwrap();
+
+            let client_options = ClientOptions::parse(database_url).await?;
+            let client = Client::with_options(client_options)?;
+            let store = MongoStore::new(&client, &database_name);
+            store.create_indexes().await?;
+
+            dbg!("Successfully initialized vault database...");
+
+            Ok(repositories(Arc::new(store)))
+        }
+        other => panic!("[ECS_STORAGE_BACKEND] '{}' is not supported...", other),
+    }
+}
+
+/// Builds the repositories on top of a single backend implementing every store
+pub fn repositories<S>(store: Arc<S>) -> Repositories
+where
+    S: UserStore
+        + VaultStore
+        + KeyStore
+        + TransitStore
+        + SigningStore
+        + AuditStore
+        + GeneratorStore
+        + BackupStore
+        + 'static,
+{
+    let user_repo = Arc::new(UserRepository::new(store.clone()));
+
+    let vault_repo = Arc::new(VaultRepository::new(store.clone()));
 
-    let database_name = std::env::var_os("ECS_DATABASE_NAME")
-        .expect("[ECS_DATABASE_NAME] must be set...")
-        .into_string()
-        .unwrap();
+    let seal_repo = Arc::new(SealRepository::new(store.clone(), vault_repo.clone()));
 
-    let client_options = ClientOptions::parse(database_url).await?;
-    let client = Client::with_options(client_options)?;
-    let database = client.database(&database_name);
+    let transit_repo = Arc::new(TransitRepository::new(store.clone(), vault_repo.clone()));
 
-    dbg!("Successfully initialized vault database...");
+    let signing_repo = Arc::new(SigningRepository::new(store.clone(), vault_repo.clone()));
 
-    let user_repo = Arc::new(UserRepository::new(&client, &database_name, "users"));
+    let generator_repo = Arc::new(GeneratorRepository::new(store.clone(), vault_repo.clone()));
 
-    let vault_repo = Arc::new(VaultRepository::new(&client, &database_name, "vault"));
+    let backup_repo = Arc::new(BackupRepository::new(store.clone(), vault_repo.clone()));
 
-    let keys_repo = Arc::new(KeyRepository::new(&client, &database_name, "keys"));
+    let keys_repo = Arc::new(KeyRepository::new(store));
 
-    Ok((user_repo, vault_repo, keys_repo))
+    Repositories {
+        user: user_repo,
+        vault: vault_repo,
+        keys: keys_repo,
+        seal: seal_repo,
+        transit: transit_repo,
+        signing: signing_repo,
+        generator: generator_repo,
+        backup: backup_repo,
+    }
 }
@@ -1,3 +1,5 @@
+pub mod reaper;
+
 /*--------------------
 Rocket modules
 ---------------------*/
@@ -5,6 +7,7 @@ use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Header;
 use rocket::{Request, Response};
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CORS;
 
 #[rocket::async_trait]
@@ -0,0 +1,82 @@
+use log::{error, info};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use std::{sync::Arc, time::Duration};
+
+/*-------------
+Custom modules
+-------------*/
+use crate::repositories::vault::VaultRepository;
+
+/// Seconds between two runs when [ECS_REAPER_INTERVAL_SECS] is not set
+pub const DEFAULT_REAPER_INTERVAL_SECS: u64 = 60;
+
+/*---------------------------------------------------------------------------
+    Background task, started once Rocket has launched, that periodically
+    purges expired vault entries and entries whose trash retention has
+    elapsed, logging every entry it removed.
+---------------------------------------------------------------------------*/
+pub struct Reaper;
+
+#[rocket::async_trait]
+impl Fairing for Reaper {
+    fn info(&self) -> Info {
+        Info {
+            name: "Purge expired and trashed vault entries in the background",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let Some(repo) = rocket.state::<Arc<VaultRepository>>().cloned() else {
+            error!("Vault repository is not managed, the reaper will not run.");
+            return;
+        };
+
+        let interval_secs = std::env::var("ECS_REAPER_INTERVAL_SECS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_REAPER_INTERVAL_SECS)
+            .max(1);
+
+        rocket::tokio::spawn(async move {
+            let mut interval = rocket::tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                interval.tick().await;
+                reap(&repo).await;
+            }
+        });
+    }
+}
+
+async fn reap(repo: &VaultRepository) {
+    match repo.purge_expired().await {
+        Ok(purged) => {
+            for secret in &purged {
+                info!(
+                    "Reaper removed expired vault entry {} ({}) owned by {}, expired at {:?}",
+                    secret.id, secret.key, secret.created_by, secret.expires_at
+                );
+            }
+            if !purged.is_empty() {
+                info!("Reaper removed {} expired vault entries.", purged.len());
+            }
+        }
+        Err(e) => error!("Reaper failed to purge expired vault entries: {:?}", e),
+    }
+
+    match repo.purge_trash().await {
+        Ok(purged) => {
+            for secret in &purged {
+                info!(
+                    "Reaper removed trashed vault entry {} ({}) owned by {}, deleted at {:?}",
+                    secret.id, secret.key, secret.created_by, secret.deleted_at
+                );
+            }
+            if !purged.is_empty() {
+                info!("Reaper removed {} trashed vault entries.", purged.len());
+            }
+        }
+        Err(e) => error!("Reaper failed to purge trashed vault entries: {:?}", e),
+    }
+}
@@ -1,3 +1,4 @@
+pub mod cli;
 pub mod custom_catchers;
 pub mod db;
 pub mod fairings;
@@ -5,4 +6,5 @@ pub mod models;
 pub mod repositories;
 pub mod request_guards;
 pub mod routes;
+pub mod storage;
 pub mod utils;
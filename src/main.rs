@@ -2,13 +2,14 @@
 
 use std::path::PathBuf;
 
-use rocket::{fs::FileServer, serde::json::Json};
+use rocket::{fs::FileServer, serde::json::Json, Build, Rocket};
 
 #[macro_use]
 extern crate rocket;
 extern crate crypto;
 extern crate log;
 
+mod cli;
 mod custom_catchers;
 mod db;
 mod fairings;
@@ -16,9 +17,16 @@ mod models;
 mod repositories;
 mod request_guards;
 mod routes;
+mod storage;
 mod utils;
 
 use custom_catchers::*;
+use routes::admin::admin_routes;
+use routes::backup::backup_routes;
+use routes::generator::generator_routes;
+use routes::seal::seal_routes;
+use routes::signing::signing_routes;
+use routes::transit::transit_routes;
 use routes::users::user_routes;
 use routes::vault::vault_routes;
 
@@ -32,8 +40,29 @@ fn _options() -> &'static str {
     ""
 }
 
-#[launch]
-fn rocket() -> _ {
+#[rocket::main]
+async fn main() {
+    match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Serve) => {
+            if let Err(error) = rocket().launch().await {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        Ok(command) => {
+            if let Err(message) = cli::run(command).await {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn rocket() -> Rocket<Build> {
     dotenvy::dotenv().ok();
 
     let current_dir = std::env::current_dir().expect("Failed to get current directory");
@@ -42,9 +71,16 @@ fn rocket() -> _ {
     rocket::build()
         .attach(db::init())
         .attach(fairings::CORS)
+        .attach(fairings::reaper::Reaper)
         .mount("/", routes![health_check, _options])
         .mount("/", user_routes())
         .mount("/", vault_routes())
+        .mount("/", admin_routes())
+        .mount("/", seal_routes())
+        .mount("/", transit_routes())
+        .mount("/", signing_routes())
+        .mount("/", generator_routes())
+        .mount("/", backup_routes())
         .mount("/", FileServer::from(public_path))
         .register(
             "/",
@@ -1,8 +1,12 @@
 use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
+use rocket::http::Status;
 use rocket::response::Responder;
+use rocket::serde::json::Json;
+use rocket::FromForm;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /*------------
  User models
@@ -30,6 +34,35 @@ pub struct KeyPairDocument {
     pub created_at: DateTime<Utc>,
 }
 
+/// Written once when the vault is initialized with key shares
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SealConfigDocument {
+    pub shares: u8,
+    pub threshold: u8,
+    // Digest of the master key, to tell a reconstructed key from a wrong one
+    #[serde(rename = "keyCheck")]
+    pub key_check: String,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Recorded the first time a master key id is used, see repositories::seal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KeyCheckDocument {
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    // A random data key wrapped by the master key: only the same key unwraps it
+    pub check: String,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct User {
     #[serde(rename = "_id")]
@@ -55,6 +88,88 @@ pub struct VaultDocument {
     pub id: ObjectId,
     pub key: String,
     pub value: String,
+    // Data key of the entry, wrapped by the key-encryption key. Entries written before
+    // envelope encryption have none and their values are encrypted with the master key.
+    #[serde(default, rename = "dataKey", skip_serializing_if = "Option::is_none")]
+    pub data_key: Option<String>,
+    // Id of the master key wrapping the data key, see utils::keyring
+    #[serde(default, rename = "keyId", skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    // Whether the values are bound to the id, owner and key of the entry as associated data.
+    // Entries written before the binding are bound when next written or re-encrypted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub bound: bool,
+    // Binary values are returned base64 encoded
+    #[serde(default, skip_serializing_if = "ValueEncoding::is_utf8")]
+    pub encoding: ValueEncoding,
+    pub created_by: String,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional",
+        rename = "expiresAt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<DateTime<Utc>>,
+    // Set while the entry is in the trash
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional",
+        rename = "deletedAt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deleted_at: Option<DateTime<Utc>>,
+    // Entries written before versioning was introduced are treated as version 1
+    #[serde(default = "first_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_by: Option<String>,
+    #[serde(
+        default,
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime_optional",
+        rename = "updatedAt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub updated_at: Option<DateTime<Utc>>,
+    // Previous versions, oldest first. The current version lives in the fields above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<SecretVersion>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+/// How a value is carried in JSON: as text, or base64 encoded for binary data such as
+/// keystores or certificates. Values are stored as the raw bytes either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl ValueEncoding {
+    pub fn is_utf8(&self) -> bool {
+        *self == ValueEncoding::Utf8
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecretVersion {
+    pub version: u32,
+    pub value: String,
+    #[serde(default, skip_serializing_if = "ValueEncoding::is_utf8")]
+    pub encoding: ValueEncoding,
     pub created_by: String,
     #[serde(
         with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
@@ -63,6 +178,15 @@ pub struct VaultDocument {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecretVersionSummary {
+    pub version: u32,
+    pub created_by: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    pub current: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
 pub struct Vault {
     #[serde(rename = "_id")]
@@ -78,6 +202,107 @@ pub struct Vault {
 pub struct Secret {
     pub key: String,
     pub value: String,
+    #[serde(default)]
+    pub encoding: ValueEncoding,
+    // Either a lifetime in seconds or an RFC 3339 timestamp, not both
+    pub ttl: Option<u64>,
+    pub expires_at: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Optional attributes of a new secret
+#[derive(Debug, Default, Clone)]
+pub struct SecretOptions {
+    pub encoding: ValueEncoding,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub description: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Descriptive attributes changed alongside the value of a secret, `None` keeps the current one
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SecretMetadata {
+    // Encoding of the submitted value, the entry keeps its current one when omitted
+    pub encoding: Option<ValueEncoding>,
+    // An empty description removes it
+    pub description: Option<String>,
+    // Replaces every label of the secret
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+impl VaultDocument {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PathSecret {
+    pub value: String,
+    // Required when the path already exists, omitted to create a new entry
+    pub version: Option<u32>,
+    #[serde(flatten)]
+    pub metadata: SecretMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretUpdate {
+    pub value: String,
+    // The version the caller last read, the update is rejected if the entry has moved on
+    pub version: u32,
+    #[serde(flatten)]
+    pub metadata: SecretMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedSecret {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub key: String,
+    pub version: u32,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: String,
+    // When the reaper permanently removes the entry
+    #[serde(rename = "purgeAt")]
+    pub purge_at: String,
+}
+
+/// Query string of `/retrieve/vault/entries`
+#[derive(Debug, FromForm)]
+pub struct ListEntriesQuery {
+    pub limit: Option<u64>,
+    pub page_token: Option<String>,
+    // `key` or `createdAt`
+    pub sort: Option<String>,
+    // `asc` or `desc`
+    pub order: Option<String>,
+    pub prefix: Option<String>,
+    // RFC 3339, inclusive
+    pub created_after: Option<String>,
+    // RFC 3339, exclusive
+    pub created_before: Option<String>,
+    // Label selector such as `env=prod,team!=legacy`
+    pub labels: Option<String>,
+}
+
+/// Query string of paginated listings without filters
+#[derive(Debug, FromForm)]
+pub struct PageQuery {
+    pub limit: Option<u64>,
+    pub page_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PathListing {
+    pub prefix: String,
+    pub folders: Vec<String>,
+    pub keys: Vec<String>,
 }
 
 /*----------
@@ -89,6 +314,632 @@ pub struct CreateSecretResponse {
     pub message: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateSecretResponse {
+    pub status: u16,
+    pub message: String,
+    pub version: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RollbackSecretResponse {
+    pub status: u16,
+    pub message: String,
+    pub version: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReencryptionState {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of the job moving every entry onto the active master key
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReencryptionProgress {
+    pub state: ReencryptionState,
+    pub active_key_id: String,
+    pub migrated: u64,
+    // Entries written concurrently, which moves them onto the active key anyway
+    pub skipped: u64,
+    pub failed: u64,
+    // Entries not yet on the active key
+    pub remaining: u64,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyringStatus {
+    pub active_key_id: String,
+    pub key_ids: Vec<String>,
+}
+
+/*------------
+ Seal models
+-------------*/
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealStatus {
+    pub initialized: bool,
+    pub sealed: bool,
+    pub shares: Option<u8>,
+    pub threshold: Option<u8>,
+    // Shares submitted towards the next unseal
+    pub progress: usize,
+    // Id of the master key that did not match its key check value at startup. The vault
+    // stays sealed until the service is restarted with the right key.
+    pub key_mismatch: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitRequest {
+    pub shares: u8,
+    pub threshold: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InitResponse {
+    pub status: u16,
+    pub threshold: u8,
+    pub shares: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsealRequest {
+    pub share: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealResponse {
+    pub status: u16,
+    pub message: String,
+}
+
+/*---------------
+ Transit models
+----------------*/
+/// A named key held by the server to encrypt data that is not stored in the vault. Only
+/// its owner and the subjects in `allowed_subjects` may use it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransitKeyDocument {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub created_by: String,
+    #[serde(
+        default,
+        rename = "allowedSubjects",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allowed_subjects: Vec<String>,
+    // Version new ciphertexts are encrypted with, always the last of `versions`
+    #[serde(rename = "latestVersion")]
+    pub latest_version: u32,
+    // Every version, oldest first, so that older ciphertexts stay readable
+    pub versions: Vec<TransitKeyVersion>,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+impl TransitKeyDocument {
+    pub fn is_allowed(&self, subject: &str) -> bool {
+        self.created_by == subject
+            || self
+                .allowed_subjects
+                .iter()
+                .any(|allowed| allowed == subject)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransitKeyVersion {
+    pub version: u32,
+    // The key of this version, wrapped by the master key `key_id`
+    #[serde(rename = "dataKey")]
+    pub data_key: String,
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A transit key as shown to its users, without any key material
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitKeyInfo {
+    pub name: String,
+    pub created_by: String,
+    pub allowed_subjects: Vec<String>,
+    pub latest_version: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<TransitKeyDocument> for TransitKeyInfo {
+    fn from(key: TransitKeyDocument) -> Self {
+        Self {
+            name: key.name,
+            created_by: key.created_by,
+            allowed_subjects: key.allowed_subjects,
+            latest_version: key.latest_version,
+            created_at: key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateTransitKey {
+    pub name: String,
+    // Subjects besides the owner allowed to encrypt and decrypt with the key
+    #[serde(default)]
+    pub allowed_subjects: Vec<String>,
+}
+
+/// One value to encrypt, decrypt or rewrap. Plaintexts and contexts are base64 encoded so
+/// that any bytes can be sent.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TransitItem {
+    pub plaintext: Option<String>,
+    pub ciphertext: Option<String>,
+    // Associated data: a ciphertext only decrypts with the context it was encrypted with
+    pub context: Option<String>,
+}
+
+/// Either a single item, or several in `batch`. Batch items fail independently.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitRequest {
+    #[serde(flatten)]
+    pub item: TransitItem,
+    #[serde(default)]
+    pub batch: Vec<TransitItem>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TransitResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plaintext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ciphertext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_version: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitResponse {
+    pub status: u16,
+    #[serde(flatten)]
+    pub result: TransitResult,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch_results: Option<Vec<TransitResult>>,
+}
+
+/*---------------
+ Signing models
+----------------*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SigningAlgorithm {
+    #[serde(rename = "hmac-sha256")]
+    HmacSha256,
+    #[serde(rename = "ed25519")]
+    Ed25519,
+}
+
+/// A named key held by the server to sign payloads. Only its owner and the subjects in
+/// `allowed_subjects` may use it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SigningKeyDocument {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub algorithm: SigningAlgorithm,
+    pub created_by: String,
+    #[serde(
+        default,
+        rename = "allowedSubjects",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allowed_subjects: Vec<String>,
+    // Version new signatures are made with, always the last of `versions`
+    #[serde(rename = "latestVersion")]
+    pub latest_version: u32,
+    // Every version, oldest first, so that older signatures can still be verified
+    pub versions: Vec<SigningKeyVersion>,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+impl SigningKeyDocument {
+    pub fn is_allowed(&self, subject: &str) -> bool {
+        self.created_by == subject
+            || self
+                .allowed_subjects
+                .iter()
+                .any(|allowed| allowed == subject)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SigningKeyVersion {
+    pub version: u32,
+    // The HMAC key or Ed25519 seed of this version, wrapped by the master key `key_id`
+    #[serde(rename = "dataKey")]
+    pub data_key: String,
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    // Base64 Ed25519 public key
+    #[serde(default, rename = "publicKey", skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A signing key as shown to its users, without any key material
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SigningKeyInfo {
+    pub name: String,
+    pub algorithm: SigningAlgorithm,
+    pub created_by: String,
+    pub allowed_subjects: Vec<String>,
+    pub latest_version: u32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<SigningKeyDocument> for SigningKeyInfo {
+    fn from(key: SigningKeyDocument) -> Self {
+        Self {
+            name: key.name,
+            algorithm: key.algorithm,
+            created_by: key.created_by,
+            allowed_subjects: key.allowed_subjects,
+            latest_version: key.latest_version,
+            created_at: key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSigningKey {
+    pub name: String,
+    pub algorithm: SigningAlgorithm,
+    // Subjects besides the owner allowed to sign and verify with the key
+    #[serde(default)]
+    pub allowed_subjects: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignRequest {
+    // Base64 encoded payload
+    pub input: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignResponse {
+    pub status: u16,
+    pub signature: String,
+    pub key_version: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyRequest {
+    // Base64 encoded payload
+    pub input: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyResponse {
+    pub status: u16,
+    pub valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKey {
+    pub version: u32,
+    // Raw 32-byte key, base64 encoded
+    pub public_key: String,
+    pub pem: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeys {
+    pub name: String,
+    pub algorithm: SigningAlgorithm,
+    pub latest_version: u32,
+    pub keys: Vec<PublicKey>,
+}
+
+/*-------------
+ Audit models
+--------------*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    // The operation ran but did not succeed, such as a signature that does not verify
+    Failure,
+    // The subject is not allowed to use the key
+    Denied,
+}
+
+/// One use of a key, recorded before the result is returned to the caller
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEventDocument {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    // What was used, such as `signing/webhooks`
+    pub resource: String,
+    pub operation: String,
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    pub outcome: AuditOutcome,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub events: Vec<AuditEventDocument>,
+    pub total: u64,
+    pub next_page_token: Option<String>,
+}
+
+/*-----------------
+ Generator models
+------------------*/
+/// How a secret value is generated
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum GeneratorSpec {
+    Password(PasswordPolicy),
+    // Random bytes, hex or base64 encoded
+    Token(TokenPolicy),
+    // Random (version 4) UUID
+    Uuid,
+    // Symmetric key, stored as a binary entry
+    Key(KeyPolicy),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub length: u32,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    // Least number of characters of each class, only for enabled classes
+    pub min_lowercase: u32,
+    pub min_uppercase: u32,
+    pub min_digits: u32,
+    pub min_symbols: u32,
+    // Characters never used, such as the look-alikes `0O1lI`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub exclude: String,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            length: 24,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            min_lowercase: 0,
+            min_uppercase: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            exclude: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenPolicy {
+    pub bytes: u32,
+    pub format: TokenFormat,
+}
+
+impl Default for TokenPolicy {
+    fn default() -> Self {
+        Self {
+            bytes: 32,
+            format: TokenFormat::Hex,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenFormat {
+    #[default]
+    Hex,
+    Base64,
+    // URL safe base64 without padding
+    Base64url,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyPolicy {
+    pub bits: u32,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> Self {
+        Self { bits: 256 }
+    }
+}
+
+/// A named generator specification shared by every user, managed by admins
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeneratorPolicyDocument {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub spec: GeneratorSpec,
+    pub created_by: String,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGeneratorPolicy {
+    pub name: String,
+    pub description: Option<String>,
+    pub spec: GeneratorSpec,
+}
+
+/// Either a named policy or an inline specification, not both
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateRequest {
+    pub policy: Option<String>,
+    pub spec: Option<GeneratorSpec>,
+    // Stores the value as a new vault entry instead of returning it
+    pub store: Option<StoreGenerated>,
+}
+
+/// Attributes of the vault entry a generated value is stored as
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoreGenerated {
+    pub key: String,
+    // Either a lifetime in seconds or an RFC 3339 timestamp, not both
+    pub ttl: Option<u64>,
+    pub expires_at: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateResponse {
+    pub status: u16,
+    // The generated value, only returned when it is not stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    pub encoding: ValueEncoding,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePolicyResponse {
+    pub status: u16,
+    pub message: String,
+}
+
+/*--------------
+ Backup models
+---------------*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    // Adds the records missing from the vault and keeps every existing one
+    #[default]
+    Merge,
+    // Removes every existing record before restoring the backup
+    Replace,
+}
+
+impl std::str::FromStr for RestoreMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "merge" => Ok(RestoreMode::Merge),
+            "replace" => Ok(RestoreMode::Replace),
+            other => Err(format!("unknown restore mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct RestoreQuery {
+    pub mode: Option<String>,
+    pub dry_run: Option<bool>,
+    pub force: Option<bool>,
+}
+
+/// What a restore did, or would do on a dry run, to one collection
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreCounts {
+    pub in_backup: u64,
+    pub restored: u64,
+    // Records of the backup already in the vault, left untouched by a merge
+    pub skipped: u64,
+    // Existing records removed by a replace
+    pub removed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreReport {
+    pub mode: RestoreMode,
+    pub dry_run: bool,
+    pub backup_created_at: DateTime<Utc>,
+    pub collections: BTreeMap<String, RestoreCounts>,
+    // Master keys the restored data is wrapped with that the keyring does not hold,
+    // None when the vault is sealed and the keyring cannot be checked
+    pub missing_key_ids: Option<Vec<String>>,
+    // Master keys of the keyring that do not match their key check value in the backup,
+    // a restore is refused unless forced. None when the vault is sealed.
+    pub mismatched_key_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreResponse {
+    pub status: u16,
+    #[serde(flatten)]
+    pub report: RestoreReport,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretPage {
+    pub entries: Vec<VaultDocument>,
+    pub total: u64,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserPage {
+    pub users: Vec<UserDocument>,
+    pub total: u64,
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RestoreSecretResponse {
+    pub status: u16,
+    pub message: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteSecretResponse {
     pub status: u16,
@@ -101,6 +952,13 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
+/// Conflicts are answered with a bare status so they reach the `conflict` catcher
+#[derive(Debug, Responder)]
+pub enum UpdateSecretError {
+    Conflict(Status),
+    Failed(Json<ErrorResponse>),
+}
+
 #[derive(Debug, Deserialize, Responder, Serialize)]
 pub struct AuthModuleResponse {
     pub message: String,
@@ -0,0 +1,629 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::models::{RestoreCounts, RestoreMode, RestoreReport};
+use crate::repositories::seal::matches_key_check;
+use crate::repositories::vault::VaultRepository;
+use crate::storage::{BackupStore, Snapshot, StoreError};
+use crate::utils::keyring::DEFAULT_KEY_ID;
+use crate::utils::secret::SecretBytes;
+use crate::utils::vault::{self, EncryptError};
+
+/*---------------------------------------------------------------------------
+    Backup and restore.
+
+    A backup is a single bundle holding every record of every store,
+    serialized as BSON and encrypted with a passphrase by
+    utils::vault::encrypt, so that it is authenticated as well as secret.
+    Values and keys stay wrapped by the master keys inside the bundle:
+    restoring one is only useful to a vault holding those keys.
+
+    A restore either merges the bundle into the vault, adding the records
+    it lacks, or replaces everything the vault holds. Both can be run dry
+    to see what they would do. A bundle whose key check values the master
+    keys of the vault do not match is only restored when forced.
+---------------------------------------------------------------------------*/
+
+/// Version of the bundle layout written by this release
+pub const BUNDLE_FORMAT: u32 = 1;
+
+/// Shortest passphrase a backup is encrypted with
+pub const MIN_PASSPHRASE_LENGTH: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("the backup passphrase must be at least {0} characters long")]
+    WeakPassphrase(usize),
+    #[error("failed to encrypt the backup: {0}")]
+    Encrypt(#[from] EncryptError),
+    #[error("the backup cannot be decrypted: the passphrase is wrong or the file is damaged")]
+    Decrypt,
+    #[error("unsupported backup format {0}")]
+    UnsupportedFormat(u32),
+    #[error("invalid backup: {0}")]
+    Invalid(String),
+    #[error("master keys {} do not match the key check values of the backup", .0.join(", "))]
+    KeyMismatch(Vec<String>),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bundle {
+    format: u32,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    created_at: DateTime<Utc>,
+    snapshot: Snapshot,
+}
+
+// Read first, so that a bundle of another format is reported as such
+#[derive(Deserialize)]
+struct BundleHeader {
+    format: u32,
+}
+
+pub struct BackupRepository {
+    store: Arc<dyn BackupStore>,
+    // Tells which master keys the restored records need
+    vault: Arc<VaultRepository>,
+}
+
+impl BackupRepository {
+    pub fn new(store: Arc<dyn BackupStore>, vault: Arc<VaultRepository>) -> Self {
+        Self { store, vault }
+    }
+
+    /*---------------------------------------------------------------
+    BACKUP every store into a bundle encrypted with `passphrase`
+    ----------------------------------------------------------------*/
+    pub async fn backup(&self, passphrase: &str) -> Result<Vec<u8>, BackupError> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+            return Err(BackupError::WeakPassphrase(MIN_PASSPHRASE_LENGTH));
+        }
+
+        let bundle = Bundle {
+            format: BUNDLE_FORMAT,
+            created_at: Utc::now(),
+            snapshot: self.store.export().await?,
+        };
+        let plaintext = SecretBytes::new(
+            bson::to_vec(&bundle).map_err(|e| StoreError::Serialization(e.to_string()))?,
+        );
+        let encrypted = vault::encrypt(plaintext.expose(), passphrase.as_bytes())?;
+
+        info!(
+            "Backup created with {} users and {} vault entries",
+            bundle.snapshot.users.len(),
+            bundle.snapshot.vault.len()
+        );
+        Ok(encrypted)
+    }
+
+    /*---------------------------------------------------------------
+    RESTORE a bundle. Nothing is written when the bundle does not
+    decrypt or validate, nor on a dry run. Unless `force` is set,
+    nothing is written either when the master keys do not match the
+    key check values of the bundle, or cannot be checked because the
+    vault is sealed.
+    ----------------------------------------------------------------*/
+    pub async fn restore(
+        &self,
+        bundle: &[u8],
+        passphrase: &str,
+        mode: RestoreMode,
+        dry_run: bool,
+        force: bool,
+    ) -> Result<RestoreReport, BackupError> {
+        let plaintext = SecretBytes::new(
+            vault::decrypt(bundle, passphrase.as_bytes()).map_err(|_| BackupError::Decrypt)?,
+        );
+        let header: BundleHeader = bson::from_slice(plaintext.expose())
+            .map_err(|e| BackupError::Invalid(e.to_string()))?;
+        if header.format != BUNDLE_FORMAT {
+            return Err(BackupError::UnsupportedFormat(header.format));
+        }
+        let bundle: Bundle = bson::from_slice(plaintext.expose())
+            .map_err(|e| BackupError::Invalid(e.to_string()))?;
+        validate(&bundle.snapshot)?;
+
+        let missing_key_ids = self.missing_key_ids(&bundle.snapshot);
+        let mismatched_key_ids = self.mismatched_key_ids(&bundle.snapshot);
+        if !dry_run && !force {
+            match &mismatched_key_ids {
+                Some(key_ids) if !key_ids.is_empty() => {
+                    return Err(BackupError::KeyMismatch(key_ids.clone()))
+                }
+                None if !bundle.snapshot.key_checks.is_empty() => {
+                    return Err(StoreError::Sealed.into())
+                }
+                _ => {}
+            }
+        }
+
+        let current = self.store.export().await?;
+        let (plan, collections) = plan(&bundle.snapshot, &current, mode);
+
+        if !dry_run {
+            self.store
+                .import(&plan, mode == RestoreMode::Replace)
+                .await?;
+            info!(
+                "Backup of {} restored ({:?})",
+                bundle.created_at.to_rfc3339(),
+                mode
+            );
+        }
+
+        Ok(RestoreReport {
+            mode,
+            dry_run,
+            backup_created_at: bundle.created_at,
+            collections,
+            missing_key_ids,
+            mismatched_key_ids,
+        })
+    }
+
+    /// The master keys of the keyring that do not match their key check value in the backup
+    fn mismatched_key_ids(&self, snapshot: &Snapshot) -> Option<Vec<String>> {
+        let keyring = self.vault.keyring().ok()?;
+        Some(
+            snapshot
+                .key_checks
+                .iter()
+                .filter(|check| {
+                    keyring
+                        .kek(Some(&check.key_id))
+                        .is_some_and(|kek| !matches_key_check(kek, check))
+                })
+                .map(|check| check.key_id.clone())
+                .collect(),
+        )
+    }
+
+    /// The master keys wrapping records of the backup that the keyring lacks
+    fn missing_key_ids(&self, snapshot: &Snapshot) -> Option<Vec<String>> {
+        let status = self.vault.keyring_status().ok()?;
+        let used: BTreeSet<&str> = snapshot
+            .vault
+            .iter()
+            .map(|secret| secret.key_id.as_deref().unwrap_or(DEFAULT_KEY_ID))
+            .chain(
+                snapshot
+                    .transit
+                    .iter()
+                    .flat_map(|key| key.versions.iter().map(|version| version.key_id.as_str())),
+            )
+            .chain(
+                snapshot
+                    .signing
+                    .iter()
+                    .flat_map(|key| key.versions.iter().map(|version| version.key_id.as_str())),
+            )
+            .collect();
+        Some(
+            used.into_iter()
+                .filter(|id| !status.key_ids.iter().any(|known| known == id))
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+}
+
+/// Rejects a bundle whose records could not all be stored together
+fn validate(snapshot: &Snapshot) -> Result<(), BackupError> {
+    unique("user id", &snapshot.users, |user| user.id)?;
+    unique("user email", &snapshot.users, |user| user.email.clone())?;
+    unique("vault entry id", &snapshot.vault, |secret| secret.id)?;
+    unique(
+        "vault path",
+        snapshot.vault.iter().filter(|secret| !secret.is_trashed()),
+        |secret| (secret.created_by.clone(), secret.key.clone()),
+    )?;
+    unique("key check", &snapshot.key_checks, |check| {
+        check.key_id.clone()
+    })?;
+    unique("transit key", &snapshot.transit, |key| key.name.clone())?;
+    unique("signing key", &snapshot.signing, |key| key.name.clone())?;
+    unique("audit event id", &snapshot.audit, |event| event.id)?;
+    unique("generator policy", &snapshot.generator, |policy| {
+        policy.name.clone()
+    })?;
+    if snapshot.keys.len() > 1 {
+        return Err(BackupError::Invalid("more than one key pair".to_string()));
+    }
+
+    let versions = snapshot
+        .transit
+        .iter()
+        .map(|key| {
+            (
+                &key.name,
+                key.latest_version,
+                key.versions.last().map(|v| v.version),
+            )
+        })
+        .chain(snapshot.signing.iter().map(|key| {
+            (
+                &key.name,
+                key.latest_version,
+                key.versions.last().map(|v| v.version),
+            )
+        }));
+    for (name, latest, last) in versions {
+        if last != Some(latest) {
+            return Err(BackupError::Invalid(format!(
+                "the versions of key {} do not end with its latest version",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn unique<'a, T: 'a, K: Eq + Hash>(
+    what: &str,
+    records: impl IntoIterator<Item = &'a T>,
+    key: impl Fn(&T) -> K,
+) -> Result<(), BackupError> {
+    let mut seen = HashSet::new();
+    for record in records {
+        if !seen.insert(key(record)) {
+            return Err(BackupError::Invalid(format!("duplicate {}", what)));
+        }
+    }
+    Ok(())
+}
+
+/// The records to write for `mode`, with what happens to each collection
+fn plan(
+    backup: &Snapshot,
+    current: &Snapshot,
+    mode: RestoreMode,
+) -> (Snapshot, BTreeMap<String, RestoreCounts>) {
+    let mut counts = BTreeMap::new();
+
+    if mode == RestoreMode::Replace {
+        for (collection, in_backup, removed) in [
+            ("users", backup.users.len(), current.users.len()),
+            ("vault", backup.vault.len(), current.vault.len()),
+            ("keys", backup.keys.len(), current.keys.len()),
+            (
+                "seal",
+                backup.seal.iter().count(),
+                current.seal.iter().count(),
+            ),
+            (
+                "key_checks",
+                backup.key_checks.len(),
+                current.key_checks.len(),
+            ),
+            ("transit", backup.transit.len(), current.transit.len()),
+            ("signing", backup.signing.len(), current.signing.len()),
+            ("audit", backup.audit.len(), current.audit.len()),
+            ("generator", backup.generator.len(), current.generator.len()),
+        ] {
+            counts.insert(
+                collection.to_string(),
+                RestoreCounts {
+                    in_backup: in_backup as u64,
+                    restored: in_backup as u64,
+                    skipped: 0,
+                    removed: removed as u64,
+                },
+            );
+        }
+        return (backup.clone(), counts);
+    }
+
+    let user_ids: HashSet<_> = current.users.iter().map(|user| user.id).collect();
+    let emails: HashSet<_> = current.users.iter().map(|user| &user.email).collect();
+    let entry_ids: HashSet<_> = current.vault.iter().map(|secret| secret.id).collect();
+    let paths: HashSet<_> = current
+        .vault
+        .iter()
+        .filter(|secret| !secret.is_trashed())
+        .map(|secret| (&secret.created_by, &secret.key))
+        .collect();
+    let checked: HashSet<_> = current
+        .key_checks
+        .iter()
+        .map(|check| &check.key_id)
+        .collect();
+    let transit: HashSet<_> = current.transit.iter().map(|key| &key.name).collect();
+    let signing: HashSet<_> = current.signing.iter().map(|key| &key.name).collect();
+    let events: HashSet<_> = current.audit.iter().map(|event| event.id).collect();
+    let policies: HashSet<_> = current
+        .generator
+        .iter()
+        .map(|policy| &policy.name)
+        .collect();
+
+    let plan = Snapshot {
+        users: missing(&mut counts, "users", &backup.users, |user| {
+            user_ids.contains(&user.id) || emails.contains(&user.email)
+        }),
+        vault: missing(&mut counts, "vault", &backup.vault, |secret| {
+            entry_ids.contains(&secret.id)
+                || (!secret.is_trashed() && paths.contains(&(&secret.created_by, &secret.key)))
+        }),
+        // The key pair signs every token and the seal configuration
+        // describes the master key: an existing one is never swapped out
+        keys: missing(&mut counts, "keys", &backup.keys, |_| {
+            !current.keys.is_empty()
+        }),
+        seal: missing(&mut counts, "seal", backup.seal.as_slice(), |_| {
+            current.seal.is_some()
+        })
+        .pop(),
+        key_checks: missing(&mut counts, "key_checks", &backup.key_checks, |check| {
+            checked.contains(&check.key_id)
+        }),
+        transit: missing(&mut counts, "transit", &backup.transit, |key| {
+            transit.contains(&key.name)
+        }),
+        signing: missing(&mut counts, "signing", &backup.signing, |key| {
+            signing.contains(&key.name)
+        }),
+        audit: missing(&mut counts, "audit", &backup.audit, |event| {
+            events.contains(&event.id)
+        }),
+        generator: missing(&mut counts, "generator", &backup.generator, |policy| {
+            policies.contains(&policy.name)
+        }),
+    };
+    (plan, counts)
+}
+
+/// The records of the backup that do not `exist` yet, counted under `collection`
+fn missing<T: Clone>(
+    counts: &mut BTreeMap<String, RestoreCounts>,
+    collection: &str,
+    records: &[T],
+    exists: impl Fn(&T) -> bool,
+) -> Vec<T> {
+    let restored: Vec<T> = records
+        .iter()
+        .filter(|record| !exists(record))
+        .cloned()
+        .collect();
+    counts.insert(
+        collection.to_string(),
+        RestoreCounts {
+            in_backup: records.len() as u64,
+            restored: restored.len() as u64,
+            skipped: (records.len() - restored.len()) as u64,
+            removed: 0,
+        },
+    );
+    restored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{SecretOptions, UserDocument};
+    use crate::repositories::seal::{KeyCheckFailure, SealRepository};
+    use crate::storage::memory::MemoryStore;
+    use crate::storage::{KeyStore, UserStore};
+    use crate::utils::keyring::Keyring;
+    use mongodb::bson::oid::ObjectId;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn repositories() -> (BackupRepository, Arc<VaultRepository>, Arc<MemoryStore>) {
+        let store = Arc::new(MemoryStore::new());
+        let keyring = Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap();
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        (
+            BackupRepository::new(store.clone(), vault.clone()),
+            vault,
+            store,
+        )
+    }
+
+    fn user(email: &str) -> UserDocument {
+        UserDocument {
+            id: ObjectId::new(),
+            email: email.to_string(),
+            password: "hash".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn bundles_need_a_strong_passphrase_and_the_right_one() {
+        let (repo, _, _) = repositories();
+        assert!(matches!(
+            repo.backup("short").await,
+            Err(BackupError::WeakPassphrase(_))
+        ));
+
+        let mut bundle = repo.backup(PASSPHRASE).await.unwrap();
+        assert!(matches!(
+            repo.restore(
+                &bundle,
+                "another long passphrase",
+                RestoreMode::Merge,
+                true,
+                false
+            )
+            .await,
+            Err(BackupError::Decrypt)
+        ));
+
+        let last = bundle.len() - 1;
+        bundle[last] ^= 1;
+        assert!(matches!(
+            repo.restore(&bundle, PASSPHRASE, RestoreMode::Merge, true, false)
+                .await,
+            Err(BackupError::Decrypt)
+        ));
+    }
+
+    #[tokio::test]
+    async fn merges_add_missing_records_and_replaces_start_over() {
+        let (repo, vault, store) = repositories();
+        store.insert_user(&user("alice@example.com")).await.unwrap();
+        vault
+            .create_secret(
+                "db/password",
+                "s3cret",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let bundle = repo.backup(PASSPHRASE).await.unwrap();
+
+        store.insert_user(&user("bob@example.com")).await.unwrap();
+        let entry = vault
+            .list_secrets("alice@example.com")
+            .await
+            .unwrap()
+            .remove(0);
+        vault
+            .delete_secret(&entry.id.to_hex(), "alice@example.com")
+            .await
+            .unwrap();
+        vault
+            .purge_secret(&entry.id.to_hex(), "alice@example.com")
+            .await
+            .unwrap();
+
+        let report = repo
+            .restore(&bundle, PASSPHRASE, RestoreMode::Merge, true, false)
+            .await
+            .unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.missing_key_ids, Some(Vec::new()));
+        assert_eq!(report.collections["users"].skipped, 1);
+        assert_eq!(report.collections["vault"].restored, 1);
+        assert!(vault
+            .list_secrets("alice@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+
+        repo.restore(&bundle, PASSPHRASE, RestoreMode::Merge, false, false)
+            .await
+            .unwrap();
+        let secrets = vault.list_secrets("alice@example.com").await.unwrap();
+        assert_eq!(secrets[0].value, "s3cret");
+        assert!(store
+            .get_user_by_email("bob@example.com")
+            .await
+            .unwrap()
+            .is_some());
+
+        let report = repo
+            .restore(&bundle, PASSPHRASE, RestoreMode::Replace, false, false)
+            .await
+            .unwrap();
+        assert_eq!(report.collections["users"].removed, 2);
+        assert!(store
+            .get_user_by_email("bob@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get_user_by_email("alice@example.com")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn keys_the_keyring_lacks_are_reported() {
+        let (repo, vault, _) = repositories();
+        vault
+            .create_secret(
+                "api/token",
+                "value",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let bundle = repo.backup(PASSPHRASE).await.unwrap();
+
+        let (other, other_vault) = {
+            let store = Arc::new(MemoryStore::new());
+            let keyring = Keyring::new("2026", "another", Vec::new()).unwrap();
+            let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+            (BackupRepository::new(store, vault.clone()), vault)
+        };
+        let report = other
+            .restore(&bundle, PASSPHRASE, RestoreMode::Merge, true, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            report.missing_key_ids,
+            Some(vec![DEFAULT_KEY_ID.to_string()])
+        );
+
+        other_vault.seal();
+        let report = other
+            .restore(&bundle, PASSPHRASE, RestoreMode::Merge, true, false)
+            .await
+            .unwrap();
+        assert_eq!(report.missing_key_ids, None);
+    }
+
+    #[tokio::test]
+    async fn backups_of_other_master_keys_are_only_restored_when_forced() {
+        let (repo, vault, store) = repositories();
+        SealRepository::new(store, vault)
+            .check_master_keys(KeyCheckFailure::Refuse)
+            .await
+            .unwrap();
+        let bundle = repo.backup(PASSPHRASE).await.unwrap();
+        repo.restore(&bundle, PASSPHRASE, RestoreMode::Replace, false, false)
+            .await
+            .unwrap();
+
+        // Another key under the same id
+        let store = Arc::new(MemoryStore::new());
+        let keyring = Keyring::new(DEFAULT_KEY_ID, "another", Vec::new()).unwrap();
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        let other = BackupRepository::new(store.clone(), vault.clone());
+
+        let report = other
+            .restore(&bundle, PASSPHRASE, RestoreMode::Replace, true, false)
+            .await
+            .unwrap();
+        assert_eq!(report.missing_key_ids, Some(Vec::new()));
+        assert_eq!(
+            report.mismatched_key_ids,
+            Some(vec![DEFAULT_KEY_ID.to_string()])
+        );
+        assert!(matches!(
+            other
+                .restore(&bundle, PASSPHRASE, RestoreMode::Replace, false, false)
+                .await,
+            Err(BackupError::KeyMismatch(_))
+        ));
+        assert!(store.list_key_checks().await.unwrap().is_empty());
+
+        vault.seal();
+        assert!(matches!(
+            other
+                .restore(&bundle, PASSPHRASE, RestoreMode::Replace, false, false)
+                .await,
+            Err(BackupError::Store(StoreError::Sealed))
+        ));
+        other
+            .restore(&bundle, PASSPHRASE, RestoreMode::Replace, false, true)
+            .await
+            .unwrap();
+        assert_eq!(store.list_key_checks().await.unwrap().len(), 1);
+    }
+}
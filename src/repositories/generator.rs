@@ -0,0 +1,228 @@
+use chrono::Utc;
+use log::info;
+use mongodb::bson::oid::ObjectId;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::models::{GeneratorPolicyDocument, GeneratorSpec, SecretOptions, VaultDocument};
+use crate::repositories::vault::VaultRepository;
+use crate::storage::{GeneratorStore, StoreError};
+use crate::utils::generator::{self, Generated, InvalidPolicy};
+use crate::utils::paths::is_valid_name;
+
+/*---------------------------------------------------------------------------
+    Secret generation.
+
+    Values are generated from an inline specification or from a named
+    policy, so that teams share one definition of, say, a database
+    password. A generated value is either handed back once or stored
+    straight into the vault, in which case it never leaves the server.
+---------------------------------------------------------------------------*/
+
+#[derive(Error, Debug)]
+pub enum GeneratorError {
+    #[error("invalid generator policy name: {0}")]
+    InvalidName(String),
+    #[error(transparent)]
+    InvalidPolicy(#[from] InvalidPolicy),
+    #[error("generator policy {0} already exists")]
+    Duplicate(String),
+    #[error("generator policy {0} does not exist")]
+    NotFound(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+pub struct GeneratorRepository {
+    store: Arc<dyn GeneratorStore>,
+    // Stores the generated values that are kept
+    vault: Arc<VaultRepository>,
+}
+
+impl GeneratorRepository {
+    pub fn new(store: Arc<dyn GeneratorStore>, vault: Arc<VaultRepository>) -> Self {
+        Self { store, vault }
+    }
+
+    /*------------------------------------
+    CREATE a named generator policy
+    -------------------------------------*/
+    pub async fn create_policy(
+        &self,
+        name: &str,
+        description: Option<String>,
+        spec: GeneratorSpec,
+        created_by: &str,
+    ) -> Result<GeneratorPolicyDocument, GeneratorError> {
+        if !is_valid_name(name) {
+            return Err(GeneratorError::InvalidName(name.to_string()));
+        }
+        generator::validate(&spec)?;
+
+        let policy = GeneratorPolicyDocument {
+            id: ObjectId::new(),
+            name: name.to_string(),
+            description: description.filter(|description| !description.is_empty()),
+            spec,
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+        };
+
+        match self.store.insert_generator_policy(&policy).await {
+            Ok(()) => Ok(policy),
+            Err(StoreError::Duplicate(_)) => Err(GeneratorError::Duplicate(name.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn get_policy(&self, name: &str) -> Result<GeneratorPolicyDocument, GeneratorError> {
+        self.store
+            .get_generator_policy(name)
+            .await?
+            .ok_or_else(|| GeneratorError::NotFound(name.to_string()))
+    }
+
+    pub async fn list_policies(&self) -> Result<Vec<GeneratorPolicyDocument>, GeneratorError> {
+        Ok(self.store.list_generator_policies().await?)
+    }
+
+    /*---------------------------------------------------------------
+    DELETE a generator policy. Entries generated with it are kept.
+    ----------------------------------------------------------------*/
+    pub async fn delete_policy(&self, name: &str) -> Result<(), GeneratorError> {
+        if !self.store.delete_generator_policy(name).await? {
+            return Err(GeneratorError::NotFound(name.to_string()));
+        }
+        info!("Generator policy {} deleted", name);
+        Ok(())
+    }
+
+    /*---------------------------------------------------------------
+    GENERATE a value from either a named policy or an inline
+    specification
+    ----------------------------------------------------------------*/
+    pub async fn generate(
+        &self,
+        policy: Option<&str>,
+        spec: Option<GeneratorSpec>,
+    ) -> Result<Generated, GeneratorError> {
+        let spec = match (policy, spec) {
+            (Some(name), None) => self.get_policy(name).await?.spec,
+            (None, Some(spec)) => spec,
+            _ => {
+                return Err(GeneratorError::InvalidInput(
+                    "provide either a policy or a spec".to_string(),
+                ))
+            }
+        };
+        Ok(generator::generate(&spec)?)
+    }
+
+    /*---------------------------------------------------------------
+    GENERATE a value and store it as a new vault entry of `owner`.
+    The entry takes the encoding of the generated value.
+    ----------------------------------------------------------------*/
+    pub async fn generate_entry(
+        &self,
+        policy: Option<&str>,
+        spec: Option<GeneratorSpec>,
+        key: &str,
+        owner: &str,
+        options: SecretOptions,
+    ) -> Result<VaultDocument, GeneratorError> {
+        let generated = self.generate(policy, spec).await?;
+        let options = SecretOptions {
+            encoding: generated.encoding,
+            ..options
+        };
+        Ok(self
+            .vault
+            .create_secret(key, generated.value.expose(), owner, options)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PasswordPolicy, TokenPolicy, ValueEncoding};
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::keyring::{Keyring, DEFAULT_KEY_ID};
+
+    fn repositories() -> (GeneratorRepository, Arc<VaultRepository>) {
+        let store = Arc::new(MemoryStore::new());
+        let keyring = Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap();
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        (GeneratorRepository::new(store, vault.clone()), vault)
+    }
+
+    #[tokio::test]
+    async fn policies_are_validated_and_reused() {
+        let (repo, _) = repositories();
+        let spec = GeneratorSpec::Password(PasswordPolicy {
+            length: 40,
+            symbols: false,
+            ..PasswordPolicy::default()
+        });
+        repo.create_policy("db-passwords", None, spec.clone(), "admin@example.com")
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.create_policy("db-passwords", None, spec, "admin@example.com")
+                .await,
+            Err(GeneratorError::Duplicate(_))
+        ));
+        assert!(matches!(
+            repo.create_policy(
+                "short",
+                None,
+                GeneratorSpec::Token(TokenPolicy {
+                    bytes: 2,
+                    ..TokenPolicy::default()
+                }),
+                "admin@example.com"
+            )
+            .await,
+            Err(GeneratorError::InvalidPolicy(_))
+        ));
+
+        let generated = repo.generate(Some("db-passwords"), None).await.unwrap();
+        assert_eq!(generated.value.expose().len(), 40);
+        assert!(matches!(
+            repo.generate(Some("db-passwords"), Some(GeneratorSpec::Uuid))
+                .await,
+            Err(GeneratorError::InvalidInput(_))
+        ));
+
+        repo.delete_policy("db-passwords").await.unwrap();
+        assert!(matches!(
+            repo.generate(Some("db-passwords"), None).await,
+            Err(GeneratorError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn generated_keys_are_stored_as_binary_entries() {
+        let (repo, vault) = repositories();
+        let entry = repo
+            .generate_entry(
+                None,
+                Some(GeneratorSpec::Key(Default::default())),
+                "payments/key",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(entry.encoding, ValueEncoding::Base64);
+
+        let raw = vault
+            .get_secret_bytes_by_id(&entry.id.to_hex(), "alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(raw.len(), 32);
+    }
+}
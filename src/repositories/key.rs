@@ -1,13 +1,13 @@
 use crate::models::KeyPairDocument;
+use crate::storage::KeyStore;
 
 use base64::{engine::general_purpose, Engine as _};
-use bson::doc;
-use chrono::{Duration, Utc};
-use mongodb::{Client, Collection};
+use chrono::Utc;
 use pasetors::{
     keys::{Generate, SymmetricKey},
     version4::V4,
 };
+use std::sync::Arc;
 
 /*---------------------------------------------------------------------------
     The KeyRepository provides a basic secrets management mechanism that
@@ -18,38 +18,30 @@ use pasetors::{
     it currently only stores a symmetric key.
 ---------------------------------------------------------------------------*/
 pub struct KeyRepository {
-    collection: Collection<KeyPairDocument>,
+    store: Arc<dyn KeyStore>,
 }
 
 impl KeyRepository {
-    pub fn new(client: &Client, db_name: &str, collection_name: &str) -> Self {
-        let collection = client
-            .database(db_name)
-            .collection::<KeyPairDocument>(collection_name);
-        Self { collection }
+    pub fn new(store: Arc<dyn KeyStore>) -> Self {
+        Self { store }
     }
 
     pub async fn get_or_create_key_pair(&self) -> Result<KeyPairDocument, String> {
+        if let Some(doc) = self.store.get_key_pair().await.map_err(|e| e.to_string())? {
+            return Ok(doc);
+        }
+
         let kp = SymmetricKey::<V4>::generate().map_err(|e| e.to_string())?;
         let private_key = general_purpose::STANDARD.encode(kp.as_bytes());
         let key_pair = KeyPairDocument {
             private_key,
             created_at: Utc::now(),
         };
-        let valid_age = Utc::now() - Duration::days(1);
-        let mut cursor = self
-            .collection
-            .find_one(doc! {})
+        self.store
+            .insert_key_pair(&key_pair)
             .await
             .map_err(|e| e.to_string())?;
-        if let Some(doc) = cursor {
-            return Ok(doc);
-        } else {
-            self.collection
-                .insert_one(key_pair.clone())
-                .await
-                .map_err(|e| e.to_string())?;
-        }
+
         Ok(key_pair)
     }
 }
@@ -1,3 +1,8 @@
+pub mod backup;
+pub mod generator;
 pub mod key;
+pub mod seal;
+pub mod signing;
+pub mod transit;
 pub mod users;
 pub mod vault;
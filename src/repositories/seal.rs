@@ -0,0 +1,517 @@
+use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use chrono::Utc;
+use log::{error, info, warn};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex, MutexGuard};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+use crate::models::{KeyCheckDocument, SealConfigDocument, SealStatus};
+use crate::repositories::vault::VaultRepository;
+use crate::storage::{KeyStore, SigningStore, StoreError, TransitStore};
+use crate::utils::envelope::KeyEncryptionKey;
+use crate::utils::keyring::{Keyring, KeyringError};
+use crate::utils::secret::{Secret, SecretBytes, SecretString};
+use crate::utils::shamir::{self, ShamirError};
+use crate::utils::vault::KEY_LENGTH;
+
+/*---------------------------------------------------------------------------
+    Seal and unseal.
+
+    Instead of fetching the master key from a key provider, the vault
+    can generate it once and hand it out as Shamir key shares. The key is
+    never stored: the service starts sealed and the master key only
+    exists in memory once enough shares have been submitted. Sealing
+    drops it again.
+
+    Deployments whose key provider supplies a key (ECS_ENCRYPTION_KEY,
+    a key file or a key service) start unsealed and can be neither
+    initialized nor sealed.
+
+    Whichever way it is supplied, every master key is compared with the
+    key check value recorded the first time its id was used: a random
+    data key wrapped by the master key. A different key cannot unwrap it,
+    so a misconfigured key is caught before secrets fail to decrypt.
+    Before the value is recorded, the key must open the data already
+    stored under its id, if any.
+    [ECS_KEY_CHECK_FAILURE] tells whether the service then refuses to
+    start (`refuse`, the default) or starts sealed (`seal`).
+---------------------------------------------------------------------------*/
+
+#[derive(Error, Debug)]
+pub enum SealError {
+    #[error("the master key is supplied by a key provider")]
+    ManagedByEnvironment,
+    #[error("the vault is already initialized")]
+    AlreadyInitialized,
+    #[error("the vault is not initialized")]
+    NotInitialized,
+    #[error("invalid key share: {0}")]
+    InvalidShare(String),
+    #[error("invalid share configuration: {0}")]
+    InvalidParameters(ShamirError),
+    #[error("the key shares do not reconstruct the master key")]
+    WrongKey,
+    #[error("master key {0} does not match its key check value, it is not the key the data was written with")]
+    KeyMismatch(String),
+    #[error(transparent)]
+    Keyring(#[from] KeyringError),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// What happens when a master key does not match its key check value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCheckFailure {
+    Refuse,
+    Seal,
+}
+
+impl KeyCheckFailure {
+    /// Reads [ECS_KEY_CHECK_FAILURE], refusing to start unless it is `seal`
+    pub fn from_env() -> Self {
+        match std::env::var("ECS_KEY_CHECK_FAILURE") {
+            Ok(value) if value.trim().eq_ignore_ascii_case("seal") => KeyCheckFailure::Seal,
+            _ => KeyCheckFailure::Refuse,
+        }
+    }
+}
+
+pub struct SealRepository {
+    store: Arc<dyn KeyStore>,
+    // Read to check a new master key against the keys already wrapped with it
+    transit: Arc<dyn TransitStore>,
+    signing: Arc<dyn SigningStore>,
+    vault: Arc<VaultRepository>,
+    managed_by_environment: bool,
+    // Shares submitted since the last unseal attempt
+    pending: Mutex<Vec<Vec<u8>>>,
+    // Set when the vault was sealed at startup because of a wrong master key
+    key_mismatch: Mutex<Option<String>>,
+}
+
+impl SealRepository {
+    /// A vault that is already unsealed when the repository is created has its master
+    /// key supplied by a key provider
+    pub fn new<S>(store: Arc<S>, vault: Arc<VaultRepository>) -> Self
+    where
+        S: KeyStore + TransitStore + SigningStore + 'static,
+    {
+        let managed_by_environment = !vault.is_sealed();
+        Self {
+            store: store.clone(),
+            transit: store.clone(),
+            signing: store,
+            vault,
+            managed_by_environment,
+            pending: Mutex::new(Vec::new()),
+            key_mismatch: Mutex::new(None),
+        }
+    }
+
+    /*--------------------------
+    GET the state of the seal
+    ---------------------------*/
+    pub async fn status(&self) -> Result<SealStatus, SealError> {
+        let config = self.store.get_seal_config().await?;
+        Ok(SealStatus {
+            initialized: config.is_some() || self.managed_by_environment,
+            sealed: self.vault.is_sealed(),
+            shares: config.as_ref().map(|config| config.shares),
+            threshold: config.as_ref().map(|config| config.threshold),
+            progress: self.pending().len(),
+            key_mismatch: self.key_mismatch().clone(),
+        })
+    }
+
+    /*---------------------------------------------------------------
+    INITIALIZE the vault: generate the master key and split it into
+    `shares` key shares, `threshold` of which unseal the vault. The
+    shares are only returned here; the vault stays sealed.
+    ----------------------------------------------------------------*/
+    pub async fn initialize(&self, shares: u8, threshold: u8) -> Result<Vec<String>, SealError> {
+        if let Some(key_id) = self.key_mismatch().clone() {
+            return Err(SealError::KeyMismatch(key_id));
+        }
+        if self.managed_by_environment {
+            return Err(SealError::ManagedByEnvironment);
+        }
+        if self.store.get_seal_config().await?.is_some() {
+            return Err(SealError::AlreadyInitialized);
+        }
+
+        let mut master_key = Secret::new([0u8; KEY_LENGTH]);
+        OsRng.fill_bytes(master_key.expose_mut());
+        let key_shares = shamir::split(master_key.expose(), shares, threshold)
+            .map_err(SealError::InvalidParameters)?;
+
+        let config = SealConfigDocument {
+            shares,
+            threshold,
+            key_check: key_check(master_key.expose()),
+            created_at: Utc::now(),
+        };
+        drop(master_key);
+        match self.store.insert_seal_config(&config).await {
+            Ok(()) => {}
+            Err(StoreError::Duplicate(_)) => return Err(SealError::AlreadyInitialized),
+            Err(e) => return Err(e.into()),
+        }
+
+        info!(
+            "Vault initialized with {} key shares, {} required to unseal",
+            shares, threshold
+        );
+        Ok(key_shares
+            .iter()
+            .map(|share| general_purpose::STANDARD.encode(share))
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    UNSEAL: submit one key share. Once `threshold` shares have been
+    submitted the master key is reconstructed and, if it matches the
+    key the vault was initialized with, the vault is unsealed.
+    Otherwise the submitted shares are discarded.
+    ----------------------------------------------------------------*/
+    pub async fn unseal(&self, share: &str) -> Result<SealStatus, SealError> {
+        if let Some(key_id) = self.key_mismatch().clone() {
+            return Err(SealError::KeyMismatch(key_id));
+        }
+        if !self.vault.is_sealed() {
+            return self.status().await;
+        }
+        let config = self
+            .store
+            .get_seal_config()
+            .await?
+            .ok_or(SealError::NotInitialized)?;
+
+        let share = general_purpose::STANDARD
+            .decode(share.trim())
+            .ok()
+            .filter(|share| share.len() == KEY_LENGTH + 1)
+            .ok_or_else(|| SealError::InvalidShare("malformed share".to_string()))?;
+
+        let master_key = {
+            let mut pending = self.pending();
+            if pending.iter().any(|submitted| submitted[0] == share[0]) {
+                return Err(SealError::InvalidShare(
+                    "this share was already submitted".to_string(),
+                ));
+            }
+            pending.push(share);
+            if pending.len() < usize::from(config.threshold) {
+                None
+            } else {
+                let combined = shamir::combine(&pending).map(SecretBytes::new);
+                pending.zeroize();
+                Some(combined.map_err(|e| SealError::InvalidShare(e.to_string()))?)
+            }
+        };
+
+        if let Some(master_key) = master_key {
+            if key_check(master_key.expose()) != config.key_check {
+                warn!("Unseal failed, the key shares do not match");
+                return Err(SealError::WrongKey);
+            }
+            let encoded = SecretString::new(general_purpose::STANDARD.encode(master_key.expose()));
+            let keyring = Keyring::with_active_key(encoded.expose())?;
+            self.check_keyring(&keyring).await?;
+            self.vault.unseal(keyring);
+            info!("Vault unsealed");
+        }
+
+        self.status().await
+    }
+
+    /*----------------------------------------------
+    SEAL the vault, dropping the master key from memory
+    -----------------------------------------------*/
+    pub fn seal(&self) -> Result<(), SealError> {
+        if self.managed_by_environment {
+            return Err(SealError::ManagedByEnvironment);
+        }
+        self.pending().zeroize();
+        self.vault.seal();
+        info!("Vault sealed");
+        Ok(())
+    }
+
+    /*---------------------------------------------------------------
+    CHECK the master keys the vault started with. Run once at startup;
+    a sealed vault is checked when it is unsealed instead. A vault
+    sealed here reports the key in its status and cannot be unsealed:
+    only a restart with the right key recovers it.
+    ----------------------------------------------------------------*/
+    pub async fn check_master_keys(&self, on_failure: KeyCheckFailure) -> Result<(), SealError> {
+        let Ok(keyring) = self.vault.keyring() else {
+            return Ok(());
+        };
+
+        match self.check_keyring(&keyring).await {
+            Err(SealError::KeyMismatch(key_id)) if on_failure == KeyCheckFailure::Seal => {
+                error!(
+                    "Master key {} does not match its key check value, starting sealed",
+                    key_id
+                );
+                self.vault.seal();
+                *self.key_mismatch() = Some(key_id);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Verifies every key of `keyring`, recording the key check value of new key ids
+    async fn check_keyring(&self, keyring: &Keyring) -> Result<(), SealError> {
+        let checks = self.store.list_key_checks().await?;
+
+        for key_id in keyring.key_ids() {
+            let Some(kek) = keyring.kek(Some(&key_id)) else {
+                continue;
+            };
+            let check = match checks.iter().find(|check| check.key_id == key_id) {
+                Some(check) => check.clone(),
+                None => {
+                    // Data written before key check values existed must open with the key
+                    if !self.opens_existing_data(keyring, &key_id, kek).await? {
+                        return Err(SealError::KeyMismatch(key_id));
+                    }
+                    match self.record_key_check(&key_id, kek).await? {
+                        Some(check) => check,
+                        None => continue,
+                    }
+                }
+            };
+            if !matches_key_check(kek, &check) {
+                return Err(SealError::KeyMismatch(key_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the master key `key_id` opens a vault entry, a transit key and a signing key
+    /// stored under it, as far as there are any
+    async fn opens_existing_data(
+        &self,
+        keyring: &Keyring,
+        key_id: &str,
+        kek: &KeyEncryptionKey,
+    ) -> Result<bool, SealError> {
+        if self.vault.opens_entries_of(keyring, key_id).await? == Some(false) {
+            return Ok(false);
+        }
+
+        let transit_keys = self.transit.list_transit_keys().await?;
+        let transit = transit_keys
+            .iter()
+            .flat_map(|key| &key.versions)
+            .find(|version| version.key_id == key_id)
+            .map(|version| &version.data_key);
+        let signing_keys = self.signing.list_signing_keys().await?;
+        let signing = signing_keys
+            .iter()
+            .flat_map(|key| &key.versions)
+            .find(|version| version.key_id == key_id)
+            .map(|version| &version.data_key);
+
+        Ok(transit
+            .into_iter()
+            .chain(signing)
+            .all(|wrapped| unwraps(kek, wrapped)))
+    }
+
+    /// Returns the value recorded concurrently by another instance, if any
+    async fn record_key_check(
+        &self,
+        key_id: &str,
+        kek: &KeyEncryptionKey,
+    ) -> Result<Option<KeyCheckDocument>, SealError> {
+        let (_, wrapped) = kek
+            .generate_data_key()
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        let check = KeyCheckDocument {
+            key_id: key_id.to_string(),
+            check: general_purpose::STANDARD.encode(wrapped),
+            created_at: Utc::now(),
+        };
+
+        match self.store.insert_key_check(&check).await {
+            Ok(()) => {
+                info!("Recorded the key check value of master key {}", key_id);
+                Ok(None)
+            }
+            Err(StoreError::Duplicate(_)) => Ok(self
+                .store
+                .list_key_checks()
+                .await?
+                .into_iter()
+                .find(|check| check.key_id == key_id)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn pending(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn key_mismatch(&self) -> MutexGuard<'_, Option<String>> {
+        self.key_mismatch
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Whether `kek` is the master key `check` was recorded for
+pub(crate) fn matches_key_check(kek: &KeyEncryptionKey, check: &KeyCheckDocument) -> bool {
+    unwraps(kek, &check.check)
+}
+
+/// Whether `kek` unwraps the base64 encoded data key `wrapped`
+fn unwraps(kek: &KeyEncryptionKey, wrapped: &str) -> bool {
+    general_purpose::STANDARD
+        .decode(wrapped)
+        .is_ok_and(|wrapped| kek.unwrap(&wrapped).is_ok())
+}
+
+/// Digest of the master key, safe to store since the key itself is random
+fn key_check(master_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ec_secrets_management/seal/key-check");
+    hasher.update(master_key);
+    general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::SecretOptions;
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::keyring::DEFAULT_KEY_ID;
+
+    #[tokio::test]
+    async fn threshold_shares_unseal_the_vault() {
+        let store = Arc::new(MemoryStore::new());
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), None));
+        let seal = SealRepository::new(store, vault.clone());
+        assert!(!seal.status().await.unwrap().initialized);
+        assert!(matches!(
+            seal.unseal("share").await,
+            Err(SealError::NotInitialized)
+        ));
+
+        let shares = seal.initialize(3, 2).await.unwrap();
+        assert!(matches!(
+            seal.initialize(3, 2).await,
+            Err(SealError::AlreadyInitialized)
+        ));
+        assert!(vault.is_sealed());
+
+        let status = seal.unseal(&shares[2]).await.unwrap();
+        assert!(status.sealed);
+        assert_eq!(status.progress, 1);
+        assert!(seal.unseal(&shares[2]).await.is_err());
+
+        let status = seal.unseal(&shares[0]).await.unwrap();
+        assert!(!status.sealed);
+        assert_eq!(status.progress, 0);
+
+        seal.seal().unwrap();
+        assert!(vault.is_sealed());
+
+        // A share of another vault reconstructs the wrong key
+        let other_store = Arc::new(MemoryStore::new());
+        let other = SealRepository::new(
+            other_store.clone(),
+            Arc::new(VaultRepository::with_keyring(other_store, None)),
+        );
+        let other_shares = other.initialize(2, 2).await.unwrap();
+        seal.unseal(&shares[1]).await.unwrap();
+        assert!(matches!(
+            seal.unseal(&other_shares[0]).await,
+            Err(SealError::WrongKey)
+        ));
+        assert!(vault.is_sealed());
+    }
+
+    #[tokio::test]
+    async fn master_keys_must_match_their_key_check_values() {
+        let store = Arc::new(MemoryStore::new());
+        let start = |key: &str| {
+            let keyring = Keyring::new(DEFAULT_KEY_ID, key, Vec::new()).unwrap();
+            let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+            (SealRepository::new(store.clone(), vault.clone()), vault)
+        };
+
+        let (seal, _) = start("first key");
+        seal.check_master_keys(KeyCheckFailure::Refuse)
+            .await
+            .unwrap();
+        assert_eq!(store.list_key_checks().await.unwrap().len(), 1);
+        // Restarting with the same key passes
+        let (seal, _) = start("first key");
+        seal.check_master_keys(KeyCheckFailure::Refuse)
+            .await
+            .unwrap();
+
+        let (seal, vault) = start("another key");
+        assert!(matches!(
+            seal.check_master_keys(KeyCheckFailure::Refuse).await,
+            Err(SealError::KeyMismatch(key_id)) if key_id == DEFAULT_KEY_ID
+        ));
+        assert!(!vault.is_sealed());
+        seal.check_master_keys(KeyCheckFailure::Seal).await.unwrap();
+        assert!(vault.is_sealed());
+        assert_eq!(store.list_key_checks().await.unwrap().len(), 1);
+        let status = seal.status().await.unwrap();
+        assert!(status.sealed);
+        assert_eq!(status.key_mismatch.as_deref(), Some(DEFAULT_KEY_ID));
+        assert!(matches!(
+            seal.unseal("share").await,
+            Err(SealError::KeyMismatch(_))
+        ));
+        assert!(matches!(
+            seal.initialize(3, 2).await,
+            Err(SealError::KeyMismatch(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn data_written_before_key_checks_must_open_with_the_key() {
+        let store = Arc::new(MemoryStore::new());
+        let start = |key: &str| {
+            let keyring = Keyring::new(DEFAULT_KEY_ID, key, Vec::new()).unwrap();
+            let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+            (SealRepository::new(store.clone(), vault.clone()), vault)
+        };
+
+        // Written by a release that did not record key check values yet
+        let (_, vault) = start("key A");
+        vault
+            .create_secret(
+                "db/password",
+                "hunter2",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let (seal, _) = start("key B");
+        assert!(matches!(
+            seal.check_master_keys(KeyCheckFailure::Refuse).await,
+            Err(SealError::KeyMismatch(key_id)) if key_id == DEFAULT_KEY_ID
+        ));
+        assert!(store.list_key_checks().await.unwrap().is_empty());
+
+        let (seal, _) = start("key A");
+        seal.check_master_keys(KeyCheckFailure::Refuse)
+            .await
+            .unwrap();
+        assert_eq!(store.list_key_checks().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,743 @@
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use ed25519_compact::{KeyPair, Seed, Signature};
+use hmac::{Hmac, Mac};
+use log::info;
+use mongodb::bson::oid::ObjectId;
+use sha2::Sha256;
+use std::sync::Arc;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use crate::models::{
+    AuditEventDocument, AuditOutcome, PublicKey, PublicKeys, SigningAlgorithm, SigningKeyDocument,
+    SigningKeyVersion,
+};
+use crate::repositories::vault::VaultRepository;
+use crate::storage::{AuditStore, Page, PageRequest, SigningStore, StoreError};
+use crate::utils::paths::is_valid_name;
+use crate::utils::secret::SecretKey;
+
+/*---------------------------------------------------------------------------
+    Signing keys.
+
+    Named keys that sign payloads such as webhooks and build artifacts
+    without the key ever leaving the server. A key is either an HMAC-SHA256
+    key, verified by the vault itself, or an Ed25519 key whose public half
+    can be exported so that anyone can verify its signatures.
+
+    Signatures look like `ecs:v<version>:<base64>`. Rotating a key adds a
+    version; older signatures keep verifying against the version that made
+    them. Every use of a key is recorded as an audit event before the
+    result is returned, so a use that cannot be recorded fails.
+---------------------------------------------------------------------------*/
+
+/// Prefix of every signature
+pub const SIGNATURE_PREFIX: &str = "ecs";
+
+/// DER prefix of an Ed25519 SubjectPublicKeyInfo, followed by the 32-byte key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+#[derive(Error, Debug)]
+pub enum SigningError {
+    #[error("invalid signing key name: {0}")]
+    InvalidName(String),
+    #[error("signing key {0} already exists")]
+    Duplicate(String),
+    #[error("signing key {0} does not exist")]
+    NotFound(String),
+    #[error("not allowed to use signing key {0}")]
+    Forbidden(String),
+    #[error("signing key {0} was rotated concurrently")]
+    Conflict(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("signing key {0} has no public key")]
+    NoPublicKey(String),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+pub struct SigningRepository {
+    store: Arc<dyn SigningStore>,
+    // Records every use of a signing key
+    audit: Arc<dyn AuditStore>,
+    // Holds the master keys the signing keys are wrapped with
+    vault: Arc<VaultRepository>,
+}
+
+impl SigningRepository {
+    pub fn new<S>(store: Arc<S>, vault: Arc<VaultRepository>) -> Self
+    where
+        S: SigningStore + AuditStore + 'static,
+    {
+        Self {
+            store: store.clone(),
+            audit: store,
+            vault,
+        }
+    }
+
+    /*---------------------------------------------------------------
+    CREATE a signing key owned by `owner`. Its first version is
+    generated right away.
+    ----------------------------------------------------------------*/
+    pub async fn create_key(
+        &self,
+        name: &str,
+        algorithm: SigningAlgorithm,
+        owner: &str,
+        allowed_subjects: Vec<String>,
+    ) -> Result<SigningKeyDocument, SigningError> {
+        if !is_valid_name(name) {
+            return Err(SigningError::InvalidName(name.to_string()));
+        }
+        let mut allowed_subjects: Vec<String> = allowed_subjects
+            .into_iter()
+            .map(|subject| subject.trim().to_string())
+            .filter(|subject| !subject.is_empty() && subject != owner)
+            .collect();
+        allowed_subjects.sort();
+        allowed_subjects.dedup();
+
+        let key = SigningKeyDocument {
+            id: ObjectId::new(),
+            name: name.to_string(),
+            algorithm,
+            created_by: owner.to_string(),
+            allowed_subjects,
+            latest_version: 1,
+            versions: vec![self.new_version(algorithm, 1)?],
+            created_at: Utc::now(),
+        };
+
+        match self.store.insert_signing_key(&key).await {
+            Ok(()) => {}
+            Err(StoreError::Duplicate(_)) => return Err(SigningError::Duplicate(name.to_string())),
+            Err(e) => return Err(e.into()),
+        }
+        self.audit(&key, "create", owner, Some(1), AuditOutcome::Success)
+            .await?;
+        Ok(key)
+    }
+
+    /*---------------------------------------------------------------
+    GET a signing key `subject` is allowed to use
+    ----------------------------------------------------------------*/
+    pub async fn get_key(
+        &self,
+        name: &str,
+        subject: &str,
+    ) -> Result<SigningKeyDocument, SigningError> {
+        let key = self.find_key(name).await?;
+        if !key.is_allowed(subject) {
+            return Err(SigningError::Forbidden(name.to_string()));
+        }
+        Ok(key)
+    }
+
+    /*---------------------------------------------------------------
+    LIST the signing keys `subject` is allowed to use
+    ----------------------------------------------------------------*/
+    pub async fn list_keys(&self, subject: &str) -> Result<Vec<SigningKeyDocument>, SigningError> {
+        let keys = self.store.list_signing_keys().await?;
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.is_allowed(subject))
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    ROTATE a signing key: new signatures use a new version, older
+    ones keep verifying. Only the owner may rotate a key.
+    ----------------------------------------------------------------*/
+    pub async fn rotate_key(
+        &self,
+        name: &str,
+        subject: &str,
+    ) -> Result<SigningKeyDocument, SigningError> {
+        let mut key = self.find_key(name).await?;
+        if key.created_by != subject {
+            self.audit(&key, "rotate", subject, None, AuditOutcome::Denied)
+                .await?;
+            return Err(SigningError::Forbidden(name.to_string()));
+        }
+
+        let expected_version = key.latest_version;
+        key.latest_version += 1;
+        key.versions
+            .push(self.new_version(key.algorithm, key.latest_version)?);
+
+        if !self
+            .store
+            .replace_signing_key(&key, expected_version)
+            .await?
+        {
+            return Err(SigningError::Conflict(name.to_string()));
+        }
+        self.audit(
+            &key,
+            "rotate",
+            subject,
+            Some(key.latest_version),
+            AuditOutcome::Success,
+        )
+        .await?;
+        info!(
+            "Signing key {} rotated to version {}",
+            name, key.latest_version
+        );
+        Ok(key)
+    }
+
+    /*---------------------------------------------------------------
+    SIGN a base64 payload with the latest version of the key,
+    returning the signature and the version that made it
+    ----------------------------------------------------------------*/
+    pub async fn sign(
+        &self,
+        name: &str,
+        subject: &str,
+        input: &str,
+    ) -> Result<(String, u32), SigningError> {
+        let key = self.authorize(name, subject, "sign").await?;
+        let input = decode(input, "input")?;
+        let version = latest(&key);
+
+        let secret = self.vault.unwrap_key(&version.key_id, &version.data_key)?;
+        let signature = match key.algorithm {
+            SigningAlgorithm::HmacSha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose())
+                    .expect("HMAC accepts keys of any length");
+                mac.update(&input);
+                mac.finalize().into_bytes().to_vec()
+            }
+            SigningAlgorithm::Ed25519 => key_pair(&secret).sk.sign(&input, None).to_vec(),
+        };
+
+        self.audit(
+            &key,
+            "sign",
+            subject,
+            Some(version.version),
+            AuditOutcome::Success,
+        )
+        .await?;
+        Ok((
+            format!(
+                "{}:v{}:{}",
+                SIGNATURE_PREFIX,
+                version.version,
+                general_purpose::STANDARD.encode(signature)
+            ),
+            version.version,
+        ))
+    }
+
+    /*---------------------------------------------------------------
+    VERIFY a signature of a base64 payload with the version of the
+    key that made it
+    ----------------------------------------------------------------*/
+    pub async fn verify(
+        &self,
+        name: &str,
+        subject: &str,
+        input: &str,
+        signature: &str,
+    ) -> Result<bool, SigningError> {
+        let key = self.authorize(name, subject, "verify").await?;
+        let input = decode(input, "input")?;
+        let (version_number, signature) = parse_signature(signature)?;
+
+        let valid = match key
+            .versions
+            .iter()
+            .find(|version| version.version == version_number)
+        {
+            Some(version) => self.check(&key, version, &input, &signature)?,
+            None => false,
+        };
+
+        let outcome = if valid {
+            AuditOutcome::Success
+        } else {
+            AuditOutcome::Failure
+        };
+        self.audit(&key, "verify", subject, Some(version_number), outcome)
+            .await?;
+        Ok(valid)
+    }
+
+    /*---------------------------------------------------------------
+    EXPORT the public keys of every version of an Ed25519 key
+    ----------------------------------------------------------------*/
+    pub async fn public_keys(&self, name: &str, subject: &str) -> Result<PublicKeys, SigningError> {
+        let key = self.authorize(name, subject, "export").await?;
+        if key.algorithm != SigningAlgorithm::Ed25519 {
+            return Err(SigningError::NoPublicKey(name.to_string()));
+        }
+
+        let keys = key
+            .versions
+            .iter()
+            .filter_map(|version| {
+                let public_key = version.public_key.clone()?;
+                let raw = general_purpose::STANDARD.decode(&public_key).ok()?;
+                Some(PublicKey {
+                    version: version.version,
+                    pem: pem(&raw),
+                    public_key,
+                })
+            })
+            .collect();
+
+        self.audit(&key, "export", subject, None, AuditOutcome::Success)
+            .await?;
+        Ok(PublicKeys {
+            name: key.name,
+            algorithm: key.algorithm,
+            latest_version: key.latest_version,
+            keys,
+        })
+    }
+
+    /*---------------------------------------------------------------
+    LIST the audit events of a key. Only the owner may read them.
+    ----------------------------------------------------------------*/
+    pub async fn audit_events(
+        &self,
+        name: &str,
+        subject: &str,
+        page: PageRequest,
+    ) -> Result<Page<AuditEventDocument>, SigningError> {
+        let key = self.find_key(name).await?;
+        if key.created_by != subject {
+            return Err(SigningError::Forbidden(name.to_string()));
+        }
+        Ok(self
+            .audit
+            .list_audit_events(&resource(&key.name), page)
+            .await?)
+    }
+
+    /*---------------------------------------------------------------
+    WRAP every version of every signing key with the active master
+    key, so that retired master keys can be removed. Called when
+    the keyring is rotated. Returns the number of keys rewritten.
+    ----------------------------------------------------------------*/
+    pub async fn rewrap_keys(&self) -> Result<usize, SigningError> {
+        let mut rewritten = 0;
+
+        for mut key in self.store.list_signing_keys().await? {
+            let mut changed = false;
+            for version in key.versions.iter_mut() {
+                if let Some((wrapped, key_id)) =
+                    self.vault.rewrap_key(&version.key_id, &version.data_key)?
+                {
+                    version.data_key = wrapped;
+                    version.key_id = key_id;
+                    changed = true;
+                }
+            }
+
+            // A key rotated meanwhile is picked up by the next run
+            if changed
+                && self
+                    .store
+                    .replace_signing_key(&key, key.latest_version)
+                    .await?
+            {
+                rewritten += 1;
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    async fn find_key(&self, name: &str) -> Result<SigningKeyDocument, SigningError> {
+        self.store
+            .get_signing_key(name)
+            .await?
+            .ok_or_else(|| SigningError::NotFound(name.to_string()))
+    }
+
+    /// Looks up a key `subject` wants to use, recording a refusal in the audit log
+    async fn authorize(
+        &self,
+        name: &str,
+        subject: &str,
+        operation: &str,
+    ) -> Result<SigningKeyDocument, SigningError> {
+        let key = self.find_key(name).await?;
+        if !key.is_allowed(subject) {
+            self.audit(&key, operation, subject, None, AuditOutcome::Denied)
+                .await?;
+            return Err(SigningError::Forbidden(name.to_string()));
+        }
+        Ok(key)
+    }
+
+    async fn audit(
+        &self,
+        key: &SigningKeyDocument,
+        operation: &str,
+        subject: &str,
+        version: Option<u32>,
+        outcome: AuditOutcome,
+    ) -> Result<(), SigningError> {
+        let event = AuditEventDocument {
+            id: ObjectId::new(),
+            resource: resource(&key.name),
+            operation: operation.to_string(),
+            subject: subject.to_string(),
+            version,
+            outcome,
+            created_at: Utc::now(),
+        };
+        Ok(self.audit.insert_audit_event(&event).await?)
+    }
+
+    /// Generates the key of a new version, wrapped with the active master key
+    fn new_version(
+        &self,
+        algorithm: SigningAlgorithm,
+        version: u32,
+    ) -> Result<SigningKeyVersion, SigningError> {
+        let (secret, data_key, key_id) = self.vault.generate_wrapped_key()?;
+        let public_key = match algorithm {
+            SigningAlgorithm::HmacSha256 => None,
+            SigningAlgorithm::Ed25519 => {
+                Some(general_purpose::STANDARD.encode(key_pair(&secret).pk.as_ref()))
+            }
+        };
+
+        Ok(SigningKeyVersion {
+            version,
+            data_key,
+            key_id,
+            public_key,
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Whether `signature` was made by `version` over `input`
+    fn check(
+        &self,
+        key: &SigningKeyDocument,
+        version: &SigningKeyVersion,
+        input: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, SigningError> {
+        match key.algorithm {
+            SigningAlgorithm::HmacSha256 => {
+                let secret = self.vault.unwrap_key(&version.key_id, &version.data_key)?;
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose())
+                    .expect("HMAC accepts keys of any length");
+                mac.update(input);
+                // Constant time comparison
+                Ok(mac.verify_slice(signature).is_ok())
+            }
+            SigningAlgorithm::Ed25519 => {
+                // The stored public key is not authenticated, so it is derived from the seed
+                let secret = self.vault.unwrap_key(&version.key_id, &version.data_key)?;
+                let public_key = key_pair(&secret).pk;
+                Ok(Signature::from_slice(signature)
+                    .is_ok_and(|signature| public_key.verify(input, &signature).is_ok()))
+            }
+        }
+    }
+}
+
+/// The Ed25519 key pair of an unwrapped seed. The secret half is wiped when the pair is
+/// dropped, the copy of the seed handed to ed25519-compact when it goes out of scope.
+fn key_pair(secret: &SecretKey) -> KeyPair {
+    let seed = Zeroizing::new(*secret.expose());
+    KeyPair::from_seed(Seed::new(*seed))
+}
+
+fn latest(key: &SigningKeyDocument) -> &SigningKeyVersion {
+    key.versions
+        .last()
+        .expect("signing keys are created with a version")
+}
+
+fn resource(name: &str) -> String {
+    format!("signing/{}", name)
+}
+
+fn decode(value: &str, field: &str) -> Result<Vec<u8>, SigningError> {
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|_| SigningError::InvalidInput(format!("{} is not valid base64", field)))
+}
+
+/// Splits `ecs:v<version>:<base64>`
+fn parse_signature(signature: &str) -> Result<(u32, Vec<u8>), SigningError> {
+    let invalid = || SigningError::InvalidInput("not a vault signature".to_string());
+    let mut parts = signature.splitn(3, ':');
+    if parts.next() != Some(SIGNATURE_PREFIX) {
+        return Err(invalid());
+    }
+    let version = parts
+        .next()
+        .and_then(|version| version.strip_prefix('v'))
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or_else(invalid)?;
+    let encoded = parts.next().ok_or_else(invalid)?;
+    Ok((version, decode(encoded, "signature")?))
+}
+
+/// PEM encoded SubjectPublicKeyInfo, as read by OpenSSL and most libraries
+fn pem(public_key: &[u8]) -> String {
+    let mut der = ED25519_SPKI_PREFIX.to_vec();
+    der.extend_from_slice(public_key);
+    format!(
+        "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+        general_purpose::STANDARD.encode(der)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::keyring::{Keyring, DEFAULT_KEY_ID};
+    use ed25519_compact::PublicKey as Ed25519PublicKey;
+
+    fn repository() -> SigningRepository {
+        let store = Arc::new(MemoryStore::new());
+        let keyring = Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap();
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        SigningRepository::new(store, vault)
+    }
+
+    fn payload(value: &str) -> String {
+        general_purpose::STANDARD.encode(value)
+    }
+
+    #[tokio::test]
+    async fn signatures_verify_across_rotations() {
+        let repo = repository();
+        for (name, algorithm) in [
+            ("webhooks", SigningAlgorithm::HmacSha256),
+            ("artifacts", SigningAlgorithm::Ed25519),
+        ] {
+            repo.create_key(name, algorithm, "alice@example.com", Vec::new())
+                .await
+                .unwrap();
+            let (old, version) = repo
+                .sign(name, "alice@example.com", &payload("v1.2.3"))
+                .await
+                .unwrap();
+            assert_eq!(version, 1);
+            assert!(old.starts_with("ecs:v1:"));
+
+            repo.rotate_key(name, "alice@example.com").await.unwrap();
+            let (new, version) = repo
+                .sign(name, "alice@example.com", &payload("v1.2.3"))
+                .await
+                .unwrap();
+            assert_eq!(version, 2);
+
+            for signature in [&old, &new] {
+                assert!(repo
+                    .verify(name, "alice@example.com", &payload("v1.2.3"), signature)
+                    .await
+                    .unwrap());
+            }
+            assert!(!repo
+                .verify(name, "alice@example.com", &payload("v1.2.4"), &new)
+                .await
+                .unwrap());
+            assert!(!repo
+                .verify(
+                    name,
+                    "alice@example.com",
+                    &payload("v1.2.3"),
+                    &new.replace("ecs:v2:", "ecs:v9:")
+                )
+                .await
+                .unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn exported_public_keys_verify_signatures() {
+        let repo = repository();
+        repo.create_key(
+            "artifacts",
+            SigningAlgorithm::Ed25519,
+            "alice@example.com",
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+        let (signature, _) = repo
+            .sign("artifacts", "alice@example.com", &payload("release"))
+            .await
+            .unwrap();
+
+        let exported = repo
+            .public_keys("artifacts", "alice@example.com")
+            .await
+            .unwrap();
+        assert_eq!(exported.keys.len(), 1);
+        assert!(exported.keys[0]
+            .pem
+            .starts_with("-----BEGIN PUBLIC KEY-----\nMCowBQYDK2VwAyEA"));
+
+        let raw = general_purpose::STANDARD
+            .decode(&exported.keys[0].public_key)
+            .unwrap();
+        let (_, signature) = parse_signature(&signature).unwrap();
+        let public_key = Ed25519PublicKey::from_slice(&raw).unwrap();
+        assert!(public_key
+            .verify(b"release", &Signature::from_slice(&signature).unwrap())
+            .is_ok());
+
+        repo.create_key(
+            "webhooks",
+            SigningAlgorithm::HmacSha256,
+            "alice@example.com",
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            repo.public_keys("webhooks", "alice@example.com").await,
+            Err(SigningError::NoPublicKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn verification_ignores_a_substituted_public_key() {
+        let store = Arc::new(MemoryStore::new());
+        let keyring = Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap();
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        let repo = SigningRepository::new(store.clone(), vault);
+        repo.create_key(
+            "artifacts",
+            SigningAlgorithm::Ed25519,
+            "alice@example.com",
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+        let (genuine, _) = repo
+            .sign("artifacts", "alice@example.com", &payload("release"))
+            .await
+            .unwrap();
+
+        // Whoever can write to the database swaps in a public key of their own
+        let forger = KeyPair::from_seed(Seed::default());
+        let mut key = store.get_signing_key("artifacts").await.unwrap().unwrap();
+        key.versions[0].public_key = Some(general_purpose::STANDARD.encode(forger.pk.as_ref()));
+        assert!(store.replace_signing_key(&key, 1).await.unwrap());
+
+        let forged = format!(
+            "{}:v1:{}",
+            SIGNATURE_PREFIX,
+            general_purpose::STANDARD.encode(forger.sk.sign(b"release", None))
+        );
+        assert!(!repo
+            .verify(
+                "artifacts",
+                "alice@example.com",
+                &payload("release"),
+                &forged
+            )
+            .await
+            .unwrap());
+        assert!(repo
+            .verify(
+                "artifacts",
+                "alice@example.com",
+                &payload("release"),
+                &genuine
+            )
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn every_use_is_audited() {
+        let repo = repository();
+        repo.create_key(
+            "webhooks",
+            SigningAlgorithm::HmacSha256,
+            "alice@example.com",
+            vec!["billing@example.com".to_string()],
+        )
+        .await
+        .unwrap();
+        let (signature, _) = repo
+            .sign("webhooks", "billing@example.com", &payload("{}"))
+            .await
+            .unwrap();
+        repo.verify(
+            "webhooks",
+            "billing@example.com",
+            &payload("{ }"),
+            &signature,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            repo.sign("webhooks", "mallory@example.com", &payload("{}"))
+                .await,
+            Err(SigningError::Forbidden(_))
+        ));
+        assert!(matches!(
+            repo.rotate_key("webhooks", "billing@example.com").await,
+            Err(SigningError::Forbidden(_))
+        ));
+        assert!(matches!(
+            repo.audit_events(
+                "webhooks",
+                "billing@example.com",
+                PageRequest {
+                    offset: 0,
+                    limit: 10
+                }
+            )
+            .await,
+            Err(SigningError::Forbidden(_))
+        ));
+
+        let events = repo
+            .audit_events(
+                "webhooks",
+                "alice@example.com",
+                PageRequest {
+                    offset: 0,
+                    limit: 10,
+                },
+            )
+            .await
+            .unwrap();
+        let summary: Vec<(&str, &str, AuditOutcome)> = events
+            .items
+            .iter()
+            .map(|event| {
+                (
+                    event.operation.as_str(),
+                    event.subject.as_str(),
+                    event.outcome,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("create", "alice@example.com", AuditOutcome::Success),
+                ("sign", "billing@example.com", AuditOutcome::Success),
+                ("verify", "billing@example.com", AuditOutcome::Failure),
+                ("sign", "mallory@example.com", AuditOutcome::Denied),
+                ("rotate", "billing@example.com", AuditOutcome::Denied),
+            ]
+        );
+        assert_eq!(events.total, 5);
+    }
+}
@@ -0,0 +1,598 @@
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use log::info;
+use mongodb::bson::oid::ObjectId;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::models::{TransitItem, TransitKeyDocument, TransitKeyVersion, TransitResult};
+use crate::repositories::vault::VaultRepository;
+use crate::storage::{StoreError, TransitStore};
+use crate::utils::envelope::DataKey;
+use crate::utils::paths::is_valid_name;
+use crate::utils::secret::SecretBytes;
+use crate::utils::vault::{decrypt_with_key_and_context, encrypt_with_key_and_context};
+
+/*---------------------------------------------------------------------------
+    Transit encryption.
+
+    Encrypts and decrypts data for callers without storing it, using
+    named keys that never leave the server. Every version of a transit
+    key is a random 256-bit key wrapped by the master key, like the data
+    keys of vault entries.
+
+    Ciphertexts look like `ecs:v<version>:<base64>` and name the version
+    that decrypts them, so a key can be rotated while older ciphertexts
+    stay readable. Rewrapping moves a ciphertext onto the latest version
+    without handing the plaintext to the caller. Ciphertexts are bound to
+    the name of their key and to the optional context of the caller.
+---------------------------------------------------------------------------*/
+
+/// Prefix of every transit ciphertext
+pub const CIPHERTEXT_PREFIX: &str = "ecs";
+
+#[derive(Error, Debug)]
+pub enum TransitError {
+    #[error("invalid transit key name: {0}")]
+    InvalidName(String),
+    #[error("transit key {0} already exists")]
+    Duplicate(String),
+    #[error("transit key {0} does not exist")]
+    NotFound(String),
+    #[error("not allowed to use transit key {0}")]
+    Forbidden(String),
+    #[error("transit key {0} was rotated concurrently")]
+    Conflict(String),
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+    #[error("version {0} of the transit key does not exist")]
+    UnknownVersion(u32),
+    #[error("the ciphertext cannot be decrypted with this key and context")]
+    Decrypt,
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+pub struct TransitRepository {
+    store: Arc<dyn TransitStore>,
+    // Holds the master keys the transit keys are wrapped with
+    vault: Arc<VaultRepository>,
+}
+
+/// The unwrapped versions of a transit key, only kept for the duration of a request
+struct UnwrappedKey {
+    name: String,
+    latest_version: u32,
+    versions: BTreeMap<u32, DataKey>,
+}
+
+impl TransitRepository {
+    pub fn new(store: Arc<dyn TransitStore>, vault: Arc<VaultRepository>) -> Self {
+        Self { store, vault }
+    }
+
+    /*---------------------------------------------------------------
+    CREATE a transit key owned by `owner`. Its first version is
+    generated right away.
+    ----------------------------------------------------------------*/
+    pub async fn create_key(
+        &self,
+        name: &str,
+        owner: &str,
+        allowed_subjects: Vec<String>,
+    ) -> Result<TransitKeyDocument, TransitError> {
+        if !is_valid_name(name) {
+            return Err(TransitError::InvalidName(name.to_string()));
+        }
+        let mut allowed_subjects: Vec<String> = allowed_subjects
+            .into_iter()
+            .map(|subject| subject.trim().to_string())
+            .filter(|subject| !subject.is_empty() && subject != owner)
+            .collect();
+        allowed_subjects.sort();
+        allowed_subjects.dedup();
+
+        let key = TransitKeyDocument {
+            id: ObjectId::new(),
+            name: name.to_string(),
+            created_by: owner.to_string(),
+            allowed_subjects,
+            latest_version: 1,
+            versions: vec![self.new_version(1)?],
+            created_at: Utc::now(),
+        };
+
+        match self.store.insert_transit_key(&key).await {
+            Ok(()) => Ok(key),
+            Err(StoreError::Duplicate(_)) => Err(TransitError::Duplicate(name.to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /*---------------------------------------------------------------
+    GET a transit key `subject` is allowed to use
+    ----------------------------------------------------------------*/
+    pub async fn get_key(
+        &self,
+        name: &str,
+        subject: &str,
+    ) -> Result<TransitKeyDocument, TransitError> {
+        let key = self
+            .store
+            .get_transit_key(name)
+            .await?
+            .ok_or_else(|| TransitError::NotFound(name.to_string()))?;
+        if !key.is_allowed(subject) {
+            return Err(TransitError::Forbidden(name.to_string()));
+        }
+        Ok(key)
+    }
+
+    /*---------------------------------------------------------------
+    LIST the transit keys `subject` is allowed to use
+    ----------------------------------------------------------------*/
+    pub async fn list_keys(&self, subject: &str) -> Result<Vec<TransitKeyDocument>, TransitError> {
+        let keys = self.store.list_transit_keys().await?;
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.is_allowed(subject))
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    ROTATE a transit key: new ciphertexts use a new version, older
+    ones stay readable. Only the owner may rotate a key.
+    ----------------------------------------------------------------*/
+    pub async fn rotate_key(
+        &self,
+        name: &str,
+        subject: &str,
+    ) -> Result<TransitKeyDocument, TransitError> {
+        let mut key = self.get_key(name, subject).await?;
+        if key.created_by != subject {
+            return Err(TransitError::Forbidden(name.to_string()));
+        }
+
+        let expected_version = key.latest_version;
+        key.latest_version += 1;
+        key.versions.push(self.new_version(key.latest_version)?);
+
+        if !self
+            .store
+            .replace_transit_key(&key, expected_version)
+            .await?
+        {
+            return Err(TransitError::Conflict(name.to_string()));
+        }
+        info!(
+            "Transit key {} rotated to version {}",
+            name, key.latest_version
+        );
+        Ok(key)
+    }
+
+    /*---------------------------------------------------------------
+    ENCRYPT base64 plaintexts with the latest version of the key.
+    Each item succeeds or fails on its own.
+    ----------------------------------------------------------------*/
+    pub async fn encrypt(
+        &self,
+        name: &str,
+        subject: &str,
+        items: &[TransitItem],
+    ) -> Result<Vec<Result<TransitResult, TransitError>>, TransitError> {
+        let key = self.unwrapped_key(name, subject).await?;
+        Ok(items
+            .iter()
+            .map(|item| {
+                let plaintext = SecretBytes::new(decode(item.plaintext.as_deref(), "plaintext")?);
+                let context = decode_context(item)?;
+                let ciphertext = key.encrypt(plaintext.expose(), &context)?;
+                Ok(TransitResult {
+                    ciphertext: Some(ciphertext),
+                    key_version: Some(key.latest_version),
+                    ..TransitResult::default()
+                })
+            })
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    DECRYPT ciphertexts, returning base64 plaintexts
+    ----------------------------------------------------------------*/
+    pub async fn decrypt(
+        &self,
+        name: &str,
+        subject: &str,
+        items: &[TransitItem],
+    ) -> Result<Vec<Result<TransitResult, TransitError>>, TransitError> {
+        let key = self.unwrapped_key(name, subject).await?;
+        Ok(items
+            .iter()
+            .map(|item| {
+                let context = decode_context(item)?;
+                let (version, plaintext) = key.decrypt(item.ciphertext.as_deref(), &context)?;
+                Ok(TransitResult {
+                    plaintext: Some(general_purpose::STANDARD.encode(plaintext.expose())),
+                    key_version: Some(version),
+                    ..TransitResult::default()
+                })
+            })
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    REWRAP ciphertexts onto the latest version of the key. The
+    plaintext never leaves the server.
+    ----------------------------------------------------------------*/
+    pub async fn rewrap(
+        &self,
+        name: &str,
+        subject: &str,
+        items: &[TransitItem],
+    ) -> Result<Vec<Result<TransitResult, TransitError>>, TransitError> {
+        let key = self.unwrapped_key(name, subject).await?;
+        Ok(items
+            .iter()
+            .map(|item| {
+                let context = decode_context(item)?;
+                let (_, plaintext) = key.decrypt(item.ciphertext.as_deref(), &context)?;
+                let ciphertext = key.encrypt(plaintext.expose(), &context)?;
+                Ok(TransitResult {
+                    ciphertext: Some(ciphertext),
+                    key_version: Some(key.latest_version),
+                    ..TransitResult::default()
+                })
+            })
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    WRAP every version of every transit key with the active master
+    key, so that retired master keys can be removed. Called when
+    the keyring is rotated. Returns the number of keys rewritten.
+    ----------------------------------------------------------------*/
+    pub async fn rewrap_keys(&self) -> Result<usize, TransitError> {
+        let mut rewritten = 0;
+
+        for mut key in self.store.list_transit_keys().await? {
+            let mut changed = false;
+            for version in key.versions.iter_mut() {
+                if let Some((wrapped, key_id)) =
+                    self.vault.rewrap_key(&version.key_id, &version.data_key)?
+                {
+                    version.data_key = wrapped;
+                    version.key_id = key_id;
+                    changed = true;
+                }
+            }
+
+            // A key rotated meanwhile is picked up by the next run
+            if changed
+                && self
+                    .store
+                    .replace_transit_key(&key, key.latest_version)
+                    .await?
+            {
+                rewritten += 1;
+            }
+        }
+
+        Ok(rewritten)
+    }
+
+    /// Generates the key of a new version, wrapped with the active master key
+    fn new_version(&self, version: u32) -> Result<TransitKeyVersion, TransitError> {
+        let (_, data_key, key_id) = self.vault.generate_wrapped_key()?;
+        Ok(TransitKeyVersion {
+            version,
+            data_key,
+            key_id,
+            created_at: Utc::now(),
+        })
+    }
+
+    async fn unwrapped_key(&self, name: &str, subject: &str) -> Result<UnwrappedKey, TransitError> {
+        let key = self.get_key(name, subject).await?;
+        let versions = key
+            .versions
+            .iter()
+            .map(|version| {
+                let data_key = self.vault.unwrap_key(&version.key_id, &version.data_key)?;
+                Ok((version.version, data_key))
+            })
+            .collect::<Result<BTreeMap<_, _>, TransitError>>()?;
+
+        Ok(UnwrappedKey {
+            name: key.name,
+            latest_version: key.latest_version,
+            versions,
+        })
+    }
+}
+
+impl UnwrappedKey {
+    fn encrypt(&self, plaintext: &[u8], context: &[u8]) -> Result<String, TransitError> {
+        let data_key = &self.versions[&self.latest_version];
+        let ciphertext = encrypt_with_key_and_context(
+            plaintext,
+            data_key.expose(),
+            &associated_data(&self.name, context),
+        )
+        .map_err(|e| StoreError::Crypto(e.to_string()))?;
+
+        Ok(format!(
+            "{}:v{}:{}",
+            CIPHERTEXT_PREFIX,
+            self.latest_version,
+            general_purpose::STANDARD.encode(ciphertext)
+        ))
+    }
+
+    fn decrypt(
+        &self,
+        ciphertext: Option<&str>,
+        context: &[u8],
+    ) -> Result<(u32, SecretBytes), TransitError> {
+        let ciphertext = ciphertext
+            .ok_or_else(|| TransitError::InvalidInput("ciphertext is missing".to_string()))?;
+        let (version, encoded) = parse_ciphertext(ciphertext)?;
+        let data_key = self
+            .versions
+            .get(&version)
+            .ok_or(TransitError::UnknownVersion(version))?;
+        let sealed = general_purpose::STANDARD.decode(encoded).map_err(|_| {
+            TransitError::InvalidInput("ciphertext is not valid base64".to_string())
+        })?;
+
+        let plaintext = decrypt_with_key_and_context(
+            &sealed,
+            data_key.expose(),
+            &associated_data(&self.name, context),
+        )
+        .map_err(|_| TransitError::Decrypt)?;
+        Ok((version, SecretBytes::new(plaintext)))
+    }
+}
+
+/// Splits `ecs:v<version>:<base64>`
+fn parse_ciphertext(ciphertext: &str) -> Result<(u32, &str), TransitError> {
+    let invalid =
+        || TransitError::InvalidInput("ciphertext is not a transit ciphertext".to_string());
+    let mut parts = ciphertext.splitn(3, ':');
+    if parts.next() != Some(CIPHERTEXT_PREFIX) {
+        return Err(invalid());
+    }
+    let version = parts
+        .next()
+        .and_then(|version| version.strip_prefix('v'))
+        .and_then(|version| version.parse::<u32>().ok())
+        .ok_or_else(invalid)?;
+    let encoded = parts.next().ok_or_else(invalid)?;
+    Ok((version, encoded))
+}
+
+fn decode(value: Option<&str>, field: &str) -> Result<Vec<u8>, TransitError> {
+    let value = value.ok_or_else(|| TransitError::InvalidInput(format!("{} is missing", field)))?;
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|_| TransitError::InvalidInput(format!("{} is not valid base64", field)))
+}
+
+fn decode_context(item: &TransitItem) -> Result<Vec<u8>, TransitError> {
+    match item.context.as_deref() {
+        Some(_) => decode(item.context.as_deref(), "context"),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn associated_data(name: &str, context: &[u8]) -> Vec<u8> {
+    let mut data = b"ec_secrets_management/transit".to_vec();
+    for field in [name.as_bytes(), context] {
+        // Length prefixed so that fields cannot run into each other
+        data.extend((field.len() as u64).to_le_bytes());
+        data.extend(field);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::keyring::{Keyring, DEFAULT_KEY_ID};
+
+    fn repository() -> TransitRepository {
+        repository_with(Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap())
+    }
+
+    fn repository_with(keyring: Keyring) -> TransitRepository {
+        let store = Arc::new(MemoryStore::new());
+        let vault = Arc::new(VaultRepository::with_keyring(store.clone(), Some(keyring)));
+        TransitRepository::new(store, vault)
+    }
+
+    fn plaintext(value: &[u8]) -> TransitItem {
+        TransitItem {
+            plaintext: Some(general_purpose::STANDARD.encode(value)),
+            ..TransitItem::default()
+        }
+    }
+
+    fn ciphertext(value: &str) -> TransitItem {
+        TransitItem {
+            ciphertext: Some(value.to_string()),
+            ..TransitItem::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn values_round_trip_across_rotations() {
+        let repo = repository();
+        repo.create_key("etl", "alice@example.com", Vec::new())
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.create_key("etl", "bob@example.com", Vec::new()).await,
+            Err(TransitError::Duplicate(_))
+        ));
+
+        let encrypted = repo
+            .encrypt(
+                "etl",
+                "alice@example.com",
+                &[plaintext(b"4111 1111 1111 1111")],
+            )
+            .await
+            .unwrap();
+        let old = encrypted[0].as_ref().unwrap().ciphertext.clone().unwrap();
+        assert!(old.starts_with("ecs:v1:"));
+
+        repo.rotate_key("etl", "alice@example.com").await.unwrap();
+        let rewrapped = repo
+            .rewrap("etl", "alice@example.com", &[ciphertext(&old)])
+            .await
+            .unwrap();
+        let new = rewrapped[0].as_ref().unwrap().ciphertext.clone().unwrap();
+        assert!(new.starts_with("ecs:v2:"));
+
+        for value in [old, new] {
+            let decrypted = repo
+                .decrypt("etl", "alice@example.com", &[ciphertext(&value)])
+                .await
+                .unwrap();
+            assert_eq!(
+                decrypted[0].as_ref().unwrap().plaintext,
+                Some(general_purpose::STANDARD.encode(b"4111 1111 1111 1111"))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_items_fail_independently() {
+        let repo = repository();
+        repo.create_key("etl", "alice@example.com", Vec::new())
+            .await
+            .unwrap();
+        repo.create_key("other", "alice@example.com", Vec::new())
+            .await
+            .unwrap();
+
+        let mut with_context = plaintext(b"row 1");
+        with_context.context = Some(general_purpose::STANDARD.encode("customers"));
+        let encrypted = repo
+            .encrypt(
+                "etl",
+                "alice@example.com",
+                &[with_context.clone(), ciphertext("x")],
+            )
+            .await
+            .unwrap();
+        let sealed = encrypted[0].as_ref().unwrap().ciphertext.clone().unwrap();
+        assert!(matches!(encrypted[1], Err(TransitError::InvalidInput(_))));
+
+        let mut right_context = ciphertext(&sealed);
+        right_context.context = with_context.context.clone();
+        let results = repo
+            .decrypt(
+                "etl",
+                "alice@example.com",
+                &[
+                    right_context.clone(),
+                    ciphertext(&sealed),
+                    ciphertext("ecs:v9:AAAA"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(TransitError::Decrypt)));
+        assert!(matches!(results[2], Err(TransitError::UnknownVersion(9))));
+
+        // Ciphertexts are bound to the key that produced them
+        let results = repo
+            .decrypt("other", "alice@example.com", &[right_context])
+            .await
+            .unwrap();
+        assert!(matches!(results[0], Err(TransitError::Decrypt)));
+    }
+
+    #[tokio::test]
+    async fn only_allowed_subjects_use_a_key() {
+        let repo = repository();
+        assert!(matches!(
+            repo.create_key("../etl", "alice@example.com", Vec::new())
+                .await,
+            Err(TransitError::InvalidName(_))
+        ));
+        repo.create_key(
+            "etl",
+            "alice@example.com",
+            vec!["etl-job@example.com".to_string()],
+        )
+        .await
+        .unwrap();
+
+        assert!(repo
+            .encrypt("etl", "etl-job@example.com", &[plaintext(b"v")])
+            .await
+            .is_ok());
+        assert!(matches!(
+            repo.encrypt("etl", "mallory@example.com", &[plaintext(b"v")])
+                .await,
+            Err(TransitError::Forbidden(_))
+        ));
+        assert!(matches!(
+            repo.rotate_key("etl", "etl-job@example.com").await,
+            Err(TransitError::Forbidden(_))
+        ));
+        assert!(matches!(
+            repo.encrypt("missing", "alice@example.com", &[plaintext(b"v")])
+                .await,
+            Err(TransitError::NotFound(_))
+        ));
+        assert_eq!(
+            repo.list_keys("etl-job@example.com").await.unwrap().len(),
+            1
+        );
+        assert!(repo
+            .list_keys("mallory@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn keys_move_onto_the_active_master_key() {
+        let repo = repository();
+        repo.create_key("etl", "alice@example.com", Vec::new())
+            .await
+            .unwrap();
+        let encrypted = repo
+            .encrypt("etl", "alice@example.com", &[plaintext(b"v")])
+            .await
+            .unwrap();
+        let sealed = encrypted[0].as_ref().unwrap().ciphertext.clone().unwrap();
+
+        repo.vault.unseal(
+            Keyring::new(
+                "2025",
+                "new",
+                vec![(DEFAULT_KEY_ID.to_string(), "test".to_string())],
+            )
+            .unwrap(),
+        );
+        assert_eq!(repo.rewrap_keys().await.unwrap(), 1);
+        assert_eq!(repo.rewrap_keys().await.unwrap(), 0);
+
+        // The retired key is no longer needed
+        repo.vault
+            .unseal(Keyring::new("2025", "new", Vec::new()).unwrap());
+        let decrypted = repo
+            .decrypt("etl", "alice@example.com", &[ciphertext(&sealed)])
+            .await
+            .unwrap();
+        assert!(decrypted[0].is_ok());
+    }
+}
@@ -1,40 +1,23 @@
-#![allow(unused)]
-use chrono::{DateTime, Utc};
-use futures::stream::TryStreamExt;
-use mongodb::{
-    bson::{doc, oid::ObjectId},
-    error::{Error, Result},
-    options::ClientOptions,
-    Client, Collection,
-};
-use serde::{Deserialize, Serialize};
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use std::sync::Arc;
 
 use crate::models::UserDocument;
+use crate::storage::{parse_id, Page, PageRequest, StoreResult, UserStore};
 
-#[derive(Debug)]
 pub struct UserRepository {
-    collection: Collection<UserDocument>,
+    store: Arc<dyn UserStore>,
 }
 
 impl UserRepository {
-    pub fn new(client: &Client, db_name: &str, collection_name: &str) -> Self {
-        let collection = client
-            .database(db_name)
-            .collection::<UserDocument>(collection_name);
-        Self { collection }
+    pub fn new(store: Arc<dyn UserStore>) -> Self {
+        Self { store }
     }
 
     /*-----------------
     CREATE a new user
     --------------------*/
-    pub async fn create_user(&self, email: &str, password: &str) -> Result<UserDocument> {
-        if let Some(_) = self.collection.find_one(doc! { "email": email }).await? {
-            return Err(Error::from(std::io::Error::new(
-                std::io::ErrorKind::AlreadyExists,
-                "A user with this email already exists.",
-            )));
-        }
-
+    pub async fn create_user(&self, email: &str, password: &str) -> StoreResult<UserDocument> {
         let user = UserDocument {
             id: ObjectId::new(),
             email: email.to_string(),
@@ -42,7 +25,7 @@ impl UserRepository {
             created_at: Utc::now(),
         };
 
-        self.collection.insert_one(&user).await?;
+        self.store.insert_user(&user).await?;
 
         Ok(user)
     }
@@ -50,20 +33,16 @@ impl UserRepository {
     /*-------------
     GET user by id
     ---------------*/
-    pub async fn get_user_by_id(&self, id: &str) -> Result<Option<UserDocument>> {
-        let object_id = ObjectId::parse_str(id).unwrap();
-        let filter = doc! { "_id": object_id };
-        let user = self.collection.find_one(filter).await?;
-        Ok(user)
+    pub async fn get_user_by_id(&self, id: &str) -> StoreResult<Option<UserDocument>> {
+        let object_id = parse_id(id)?;
+        self.store.get_user_by_id(&object_id).await
     }
 
     /*----------------
     GET user by email
     ---------------*/
-    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<UserDocument>> {
-        let filter = doc! { "email": email };
-        let user = self.collection.find_one(filter).await?;
-        Ok(user)
+    pub async fn get_user_by_email(&self, email: &str) -> StoreResult<Option<UserDocument>> {
+        self.store.get_user_by_email(email).await
     }
 
     /*-------------
@@ -74,50 +53,23 @@ impl UserRepository {
         id: &str,
         email: Option<&str>,
         password: Option<&str>,
-    ) -> Result<Option<UserDocument>> {
-        let object_id = ObjectId::parse_str(id).unwrap();
-        let mut update_doc = doc! {};
-
-        if let Some(email) = email {
-            update_doc.insert("email", email);
-        }
-        if let Some(password) = password {
-            update_doc.insert("password", password);
-        }
-
-        if update_doc.is_empty() {
-            return Ok(None);
-        }
-
-        let filter = doc! { "_id": object_id };
-        let update = doc! { "$set": update_doc };
-
-        let user = self.collection.find_one_and_update(filter, update).await?;
-        Ok(user)
+    ) -> StoreResult<Option<UserDocument>> {
+        let object_id = parse_id(id)?;
+        self.store.update_user(&object_id, email, password).await
     }
 
     /*-------------
     DELETE a user
     ---------------*/
-    pub async fn delete_user(&self, id: &str) -> Result<Option<UserDocument>> {
-        let object_id = ObjectId::parse_str(id).unwrap();
-        let filter = doc! { "_id": object_id };
-        let user = self.collection.find_one_and_delete(filter).await?;
-        Ok(user)
+    pub async fn delete_user(&self, id: &str) -> StoreResult<Option<UserDocument>> {
+        let object_id = parse_id(id)?;
+        self.store.delete_user(&object_id).await
     }
 
-    /*-------------
-    GET all users
-    ---------------*/
-    pub async fn list_users(&self) -> Result<Vec<UserDocument>> {
-        let filter = doc! {};
-        let mut cursor = self.collection.find(filter).await?;
-        let mut users = Vec::new();
-
-        while let Some(user) = cursor.try_next().await? {
-            users.push(user);
-        }
-
-        Ok(users)
+    /*-------------------
+    GET a page of users
+    ---------------------*/
+    pub async fn list_users(&self, page: PageRequest) -> StoreResult<Page<UserDocument>> {
+        self.store.list_users(page).await
     }
 }
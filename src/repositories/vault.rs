@@ -1,137 +1,1825 @@
-#![allow(unused)]
-use anyhow::anyhow;
 use base64::prelude::BASE64_STANDARD;
 use base64::{engine::general_purpose, Engine};
-use chrono::Utc;
-use futures::stream::TryStreamExt;
-use mongodb::{
-    bson::{doc, oid::ObjectId},
-    error::Result,
-    Client, Collection,
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use mongodb::bson::oid::ObjectId;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use crate::models::{
+    KeyringStatus, PathListing, ReencryptionProgress, ReencryptionState, SecretMetadata,
+    SecretOptions, SecretVersion, SecretVersionSummary, TrashedSecret, ValueEncoding,
+    VaultDocument,
+};
+use crate::storage::{parse_id, Page, SecretQuery, StoreError, StoreResult, VaultStore};
+use crate::utils::envelope::DataKey;
+use crate::utils::key_provider::{self, KeyProvider};
+use crate::utils::keyring::Keyring;
+use crate::utils::labels::validate_labels;
+use crate::utils::paths::{children, normalize_path, normalize_prefix};
+use crate::utils::secret::SecretBytes;
+use crate::utils::vault::{
+    decrypt, decrypt_with_key, decrypt_with_key_and_context, encrypt_with_key_and_context,
 };
-use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
-use crate::models::VaultDocument;
-use crate::utils::vault::{decrypt, encrypt};
+/// Number of versions kept per secret when [ECS_MAX_SECRET_VERSIONS] is not set
+pub const DEFAULT_MAX_VERSIONS: usize = 10;
+
+/// Days a deleted secret stays in the trash when [ECS_TRASH_RETENTION_DAYS] is not set
+pub const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+/// Entries fetched per round trip by the re-encryption job
+const REENCRYPTION_BATCH_SIZE: u64 = 100;
 
-#[derive(Debug)]
 pub struct VaultRepository {
-    collection: Collection<VaultDocument>,
-    encryption_key: String,
+    store: Arc<dyn VaultStore>,
+    // None while the vault is sealed
+    keyring: RwLock<Option<Arc<Keyring>>>,
+    max_versions: usize,
+    trash_retention: Duration,
+    // Refuse entries whose values are not bound to them, once every entry has been re-encrypted
+    require_bound: bool,
+    reencryption: Mutex<ReencryptionProgress>,
+}
+
+/// Never prints key material: the keyring only shows its key ids
+impl fmt::Debug for VaultRepository {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultRepository")
+            .field("keyring", &*self.keyring_slot())
+            .field("max_versions", &self.max_versions)
+            .field("trash_retention", &self.trash_retention)
+            .field("require_bound", &self.require_bound)
+            .finish_non_exhaustive()
+    }
 }
 
 impl VaultRepository {
-    /// Create a new repository with a MongoDB collection and a shared SecretVault instance
-    pub fn new(client: &Client, db_name: &str, collection_name: &str) -> Self {
-        let collection = client
-            .database(db_name)
-            .collection::<VaultDocument>(collection_name);
+    /// Create a new repository on top of the given storage backend, fetching the master key
+    /// from the provider selected through ECS_KEY_PROVIDER
+    pub fn new(store: Arc<dyn VaultStore>) -> Self {
+        let provider = key_provider::from_env()
+            .unwrap_or_else(|e| panic!("Invalid key provider configuration: {}", e));
+        Self::with_key_provider(store, provider.as_ref())
+    }
 
-        let encryption_key = format!(
-            "{}",
-            std::env::var("ECS_ENCRYPTION_KEY").expect("ECS_ENCRYPTION_KEY must be set")
-        );
+    /// Create a new repository unsealed with the master key of `provider`. The repository
+    /// starts sealed when the provider has no key.
+    pub fn with_key_provider(store: Arc<dyn VaultStore>, provider: &dyn KeyProvider) -> Self {
+        let keyring = Keyring::from_provider(provider)
+            .unwrap_or_else(|e| panic!("Failed to load the encryption keyring: {}", e));
+        match keyring {
+            Some(_) => info!("Loaded the master key from the {}", provider.describe()),
+            None => info!(
+                "No master key in the {}, starting sealed",
+                provider.describe()
+            ),
+        }
+        Self::with_keyring(store, keyring)
+    }
+
+    /// Create a new repository unsealed with `keyring`, or sealed without one
+    pub fn with_keyring(store: Arc<dyn VaultStore>, keyring: Option<Keyring>) -> Self {
+        let max_versions = std::env::var("ECS_MAX_SECRET_VERSIONS")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_VERSIONS)
+            .max(1);
+
+        let trash_retention_days = std::env::var("ECS_TRASH_RETENTION_DAYS")
+            .ok()
+            .and_then(|value| value.parse::<i64>().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+            .max(0);
+
+        let require_bound = std::env::var("ECS_REQUIRE_BOUND_SECRETS")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("true") || value == "1");
 
         Self {
-            collection,
-            encryption_key,
+            store,
+            keyring: RwLock::new(keyring.map(Arc::new)),
+            max_versions,
+            trash_retention: Duration::days(trash_retention_days),
+            require_bound,
+            reencryption: Mutex::new(ReencryptionProgress::default()),
         }
     }
 
-    /*-----------------
-    CREATE a new secret
-    --------------------*/
+    /*---------------------------------------------------------------
+    CREATE a new secret. The key must be a valid path that is not
+    already in use by the same owner. An expired entry still holding
+    the path is removed first. The value is decoded according to
+    `options.encoding` and stored as raw bytes.
+    ----------------------------------------------------------------*/
     pub async fn create_secret(
         &self,
         key: &str,
         value: &str,
         created_by: &str,
-    ) -> Result<VaultDocument> {
-        let encrypted_value = encrypt(value.as_bytes(), self.encryption_key.as_bytes()).unwrap();
+        options: SecretOptions,
+    ) -> StoreResult<VaultDocument> {
+        let key = normalize_path(key).ok_or_else(|| StoreError::InvalidPath(key.to_string()))?;
+        validate_labels(&options.labels).map_err(StoreError::InvalidLabels)?;
+        let value = decode_value(value, options.encoding)?;
 
-        let secret = VaultDocument {
+        if let Some(existing) = self.store.get_secret_by_key(created_by, &key).await? {
+            if !existing.is_expired(Utc::now()) {
+                return Err(StoreError::Duplicate("key"));
+            }
+            self.store.delete_secret(&existing.id, created_by).await?;
+        }
+
+        let (data_key, wrapped_data_key) = self.new_data_key()?;
+
+        let mut secret = VaultDocument {
             id: ObjectId::new(),
-            key: key.to_string(),
-            value: general_purpose::STANDARD.encode(encrypted_value), // Use base64 for safe string storage
+            key,
+            value: String::new(),
+            data_key: Some(wrapped_data_key),
+            key_id: Some(self.keyring()?.active_id().to_string()),
+            bound: true,
+            encoding: options.encoding,
             created_by: created_by.to_string(),
             created_at: Utc::now(),
+            description: options
+                .description
+                .filter(|description| !description.is_empty()),
+            labels: options.labels,
+            expires_at: options.expires_at,
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
         };
+        secret.value = self.encrypt_with(&data_key, &associated_data(&secret), value.expose())?;
 
-        self.collection.insert_one(&secret).await?;
+        self.store.insert_secret(&secret).await?;
         Ok(secret)
     }
 
     /*---------------
     GET secret by id
     ---------------*/
-    pub async fn get_secret_by_id(&self, id: &str, subject: &str) -> Result<Option<String>> {
-        let object_id = ObjectId::parse_str(id).unwrap();
-        let filter = doc! { "_id": object_id, "created_by": subject };
+    pub async fn get_secret_by_id(&self, id: &str, subject: &str) -> StoreResult<Option<String>> {
+        let object_id = parse_id(id)?;
 
-        if let Some(secret) = self.collection.find_one(filter).await? {
-            let encoded_value = BASE64_STANDARD.decode(&secret.value).unwrap();
-            let decrypted_value = decrypt(&encoded_value, &self.encryption_key.as_bytes()).unwrap();
-            return Ok(Some(String::from_utf8_lossy(&decrypted_value).to_string()));
+        match self.find_live(&object_id, subject).await? {
+            Some(secret) => Ok(Some(self.present_value(
+                &secret,
+                &secret.value,
+                secret.encoding,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /*---------------------------------------------------------------
+    GET the raw bytes of a secret by id, whatever its encoding
+    ----------------------------------------------------------------*/
+    pub async fn get_secret_bytes_by_id(
+        &self,
+        id: &str,
+        subject: &str,
+    ) -> StoreResult<Option<Vec<u8>>> {
+        let object_id = parse_id(id)?;
+
+        match self.find_live(&object_id, subject).await? {
+            // Handed to the caller, who becomes responsible for the plaintext
+            Some(secret) => Ok(Some(
+                self.decrypt_value(&secret, &secret.value)?.into_exposed(),
+            )),
+            None => Ok(None),
         }
-        Ok(None)
     }
 
     /*-----------------
     GET secret by author
     -------------------*/
-    pub async fn get_secret_by_author(&self, created_by: &str) -> Result<Vec<VaultDocument>> {
-        let filter = doc! { "created_by": created_by };
-        let mut cursor = self.collection.find(filter).await?;
-        let mut secrets = Vec::new();
-
-        while let Some(mut secret) = cursor.try_next().await? {
-            if let Ok(encoded_value) = BASE64_STANDARD.decode(&secret.value) {
-                if let Ok(decrypted_value) = decrypt(&encoded_value, self.encryption_key.as_bytes())
-                {
-                    secret.value = String::from_utf8_lossy(&decrypted_value).to_string();
-                }
+    pub async fn get_secret_by_author(&self, created_by: &str) -> StoreResult<Vec<VaultDocument>> {
+        let secrets = self.store.list_secrets(created_by).await?;
+        self.decrypt_all(live(secrets))
+    }
+
+    /*---------------------------------------------------------------
+    DELETE a secret. The entry is moved to the trash, where it can be
+    restored until the reaper purges it after the retention window.
+    ----------------------------------------------------------------*/
+    pub async fn delete_secret(&self, id: &str, subject: &str) -> StoreResult<Option<String>> {
+        let object_id = parse_id(id)?;
+
+        if let Some(mut secret) = self.find_live(&object_id, subject).await? {
+            secret.deleted_at = Some(Utc::now());
+            if !self.store.replace_secret(&secret, secret.version).await? {
+                return Err(StoreError::Conflict);
             }
-            secrets.push(secret);
+
+            return Ok(Some(self.present_value(
+                &secret,
+                &secret.value,
+                secret.encoding,
+            )?));
         }
 
-        Ok(secrets)
+        Ok(None)
     }
 
-    /*-------------
-    DELETE a secret
-    ---------------*/
-    pub async fn delete_secret(&self, id: &str, subject: &str) -> Result<Option<String>> {
-        let object_id = ObjectId::parse_str(id).unwrap();
-        let filter = doc! { "_id": object_id, "created_by": subject };
+    /*-----------------------------
+    LIST the secrets in the trash
+    -------------------------------*/
+    pub async fn list_trash(&self, subject: &str) -> StoreResult<Vec<TrashedSecret>> {
+        let secrets = self.store.list_secrets(subject).await?;
 
-        if let Some(secret) = self.collection.find_one_and_delete(filter).await? {
-            let encoded_value = BASE64_STANDARD.decode(&secret.value).unwrap();
-            let decrypted_value = decrypt(&encoded_value, &self.encryption_key.as_bytes()).unwrap();
-            return Ok(Some(String::from_utf8_lossy(&decrypted_value).to_string()));
+        Ok(secrets
+            .into_iter()
+            .filter_map(|secret| {
+                let deleted_at = secret.deleted_at?;
+                Some(TrashedSecret {
+                    id: secret.id.to_hex(),
+                    key: secret.key,
+                    version: secret.version,
+                    deleted_at: deleted_at.to_rfc3339(),
+                    purge_at: (deleted_at + self.trash_retention).to_rfc3339(),
+                })
+            })
+            .collect())
+    }
+
+    /*---------------------------------------------------------------
+    RESTORE a secret from the trash. Fails with
+    `StoreError::Duplicate` if its path has been reused since.
+    ----------------------------------------------------------------*/
+    pub async fn restore_secret(
+        &self,
+        id: &str,
+        subject: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let object_id = parse_id(id)?;
+
+        let Some(mut secret) = self.find_trashed(&object_id, subject).await? else {
+            return Ok(None);
+        };
+
+        if let Some(existing) = self.store.get_secret_by_key(subject, &secret.key).await? {
+            if !existing.is_expired(Utc::now()) {
+                return Err(StoreError::Duplicate("key"));
+            }
+            self.store.delete_secret(&existing.id, subject).await?;
         }
 
-        Ok(None)
+        secret.deleted_at = None;
+        if !self.store.replace_secret(&secret, secret.version).await? {
+            return Err(StoreError::Conflict);
+        }
+
+        Ok(Some(secret))
+    }
+
+    /*---------------------------------------------
+    PURGE a secret from the trash, permanently
+    ----------------------------------------------*/
+    pub async fn purge_secret(
+        &self,
+        id: &str,
+        subject: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let object_id = parse_id(id)?;
+
+        if self.find_trashed(&object_id, subject).await?.is_none() {
+            return Ok(None);
+        }
+
+        self.store.delete_secret(&object_id, subject).await
     }
 
     /*-------------
     LIST all secrets
     ---------------*/
-    pub async fn list_secrets(&self, subject: &str) -> Result<Vec<VaultDocument>> {
-        let mut cursor = self.collection.find(doc! {"created_by": subject}).await?;
-        let mut secrets = Vec::new();
-
-        while let Some(mut secret) = cursor.try_next().await? {
-            if let Ok(encoded_value) = BASE64_STANDARD.decode(&secret.value) {
-                if let Ok(decrypted_value) = decrypt(&encoded_value, self.encryption_key.as_bytes())
-                {
-                    secret.value = String::from_utf8_lossy(&decrypted_value).to_string();
+    pub async fn list_secrets(&self, subject: &str) -> StoreResult<Vec<VaultDocument>> {
+        let secrets = self.store.list_secrets(subject).await?;
+        self.decrypt_all(live(secrets))
+    }
+
+    /*---------------------------------------------------------------
+    QUERY one page of secrets, filtered and sorted as requested.
+    Expired and trashed entries are never returned.
+    ----------------------------------------------------------------*/
+    pub async fn query_secrets(
+        &self,
+        subject: &str,
+        query: &SecretQuery,
+    ) -> StoreResult<Page<VaultDocument>> {
+        let page = self.store.query_secrets(subject, query, Utc::now()).await?;
+        Ok(Page {
+            items: self.decrypt_all(page.items)?,
+            total: page.total,
+        })
+    }
+
+    /*---------------------------------------------------------------
+    UPDATE a secret and, when given, its description and labels. The
+    write only succeeds if the entry is still at `expected_version`,
+    otherwise `StoreError::Conflict` is returned.
+    ----------------------------------------------------------------*/
+    pub async fn update_secret(
+        &self,
+        id: &str,
+        value: &str,
+        expected_version: u32,
+        subject: &str,
+        metadata: SecretMetadata,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let object_id = parse_id(id)?;
+        if let Some(labels) = &metadata.labels {
+            validate_labels(labels).map_err(StoreError::InvalidLabels)?;
+        }
+
+        let Some(mut secret) = self.find_live(&object_id, subject).await? else {
+            return Ok(None);
+        };
+
+        if secret.version != expected_version {
+            return Err(StoreError::Conflict);
+        }
+
+        let encoding = metadata.encoding.unwrap_or(secret.encoding);
+        let value = decode_value(value, encoding)?;
+        let encrypted_value = self.seal_value(&mut secret, value.expose())?;
+        self.push_version(&mut secret, encrypted_value, encoding, subject);
+        if let Some(description) = metadata.description {
+            secret.description = Some(description).filter(|description| !description.is_empty());
+        }
+        if let Some(labels) = metadata.labels {
+            secret.labels = labels;
+        }
+
+        if !self.store.replace_secret(&secret, expected_version).await? {
+            return Err(StoreError::Conflict);
+        }
+
+        Ok(Some(secret))
+    }
+
+    /*-----------------
+    GET secret by path
+    -------------------*/
+    pub async fn get_secret_by_path(
+        &self,
+        path: &str,
+        subject: &str,
+    ) -> StoreResult<Option<String>> {
+        let key = normalize_path(path).ok_or_else(|| StoreError::InvalidPath(path.to_string()))?;
+
+        match self.find_live_by_key(subject, &key).await? {
+            Some(secret) => Ok(Some(self.present_value(
+                &secret,
+                &secret.value,
+                secret.encoding,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /*---------------------------------------------------------------
+    PUT a secret by path. Without `expected_version` the entry is
+    created and a `StoreError::Conflict` is returned if the path is
+    taken; with it, the existing entry is updated. Returns `None`
+    when an update targets a path that does not exist.
+    ----------------------------------------------------------------*/
+    pub async fn put_secret_by_path(
+        &self,
+        path: &str,
+        value: &str,
+        expected_version: Option<u32>,
+        subject: &str,
+        metadata: SecretMetadata,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let key = normalize_path(path).ok_or_else(|| StoreError::InvalidPath(path.to_string()))?;
+        let existing = self.find_live_by_key(subject, &key).await?;
+
+        match (existing, expected_version) {
+            (None, None) => {
+                let options = SecretOptions {
+                    encoding: metadata.encoding.unwrap_or_default(),
+                    description: metadata.description,
+                    labels: metadata.labels.unwrap_or_default(),
+                    ..SecretOptions::default()
+                };
+                Ok(Some(
+                    self.create_secret(&key, value, subject, options).await?,
+                ))
+            }
+            (None, Some(_)) => Ok(None),
+            (Some(_), None) => Err(StoreError::Conflict),
+            (Some(secret), Some(version)) => {
+                self.update_secret(&secret.id.to_hex(), value, version, subject, metadata)
+                    .await
+            }
+        }
+    }
+
+    /*-------------------
+    DELETE secret by path
+    ---------------------*/
+    pub async fn delete_secret_by_path(
+        &self,
+        path: &str,
+        subject: &str,
+    ) -> StoreResult<Option<String>> {
+        let key = normalize_path(path).ok_or_else(|| StoreError::InvalidPath(path.to_string()))?;
+
+        match self.find_live_by_key(subject, &key).await? {
+            Some(secret) => self.delete_secret(&secret.id.to_hex(), subject).await,
+            None => Ok(None),
+        }
+    }
+
+    /*----------------------------------------------------
+    LIST the folders and keys directly below a path prefix
+    ------------------------------------------------------*/
+    pub async fn list_path(&self, prefix: &str, subject: &str) -> StoreResult<PathListing> {
+        let prefix =
+            normalize_prefix(prefix).ok_or_else(|| StoreError::InvalidPath(prefix.to_string()))?;
+
+        let secrets = live(
+            self.store
+                .list_secrets_with_prefix(subject, &prefix)
+                .await?,
+        );
+        let (folders, keys) = children(&prefix, secrets.iter().map(|secret| secret.key.as_str()));
+
+        Ok(PathListing {
+            prefix,
+            folders,
+            keys,
+        })
+    }
+
+    /*-----------------------
+    LIST versions of a secret
+    -------------------------*/
+    pub async fn list_secret_versions(
+        &self,
+        id: &str,
+        subject: &str,
+    ) -> StoreResult<Option<Vec<SecretVersionSummary>>> {
+        let object_id = parse_id(id)?;
+
+        let Some(secret) = self.find_live(&object_id, subject).await? else {
+            return Ok(None);
+        };
+
+        let current = secret.version;
+        let versions = versions_of(&secret)
+            .into_iter()
+            .map(|version| SecretVersionSummary {
+                version: version.version,
+                created_by: version.created_by,
+                created_at: version.created_at.to_rfc3339(),
+                current: version.version == current,
+            })
+            .collect();
+
+        Ok(Some(versions))
+    }
+
+    /*------------------------
+    GET a version of a secret
+    --------------------------*/
+    pub async fn get_secret_version(
+        &self,
+        id: &str,
+        subject: &str,
+        version: u32,
+    ) -> StoreResult<Option<String>> {
+        let object_id = parse_id(id)?;
+
+        let Some(secret) = self.find_live(&object_id, subject).await? else {
+            return Ok(None);
+        };
+
+        match versions_of(&secret)
+            .into_iter()
+            .find(|candidate| candidate.version == version)
+        {
+            Some(version) => Ok(Some(self.present_value(
+                &secret,
+                &version.value,
+                version.encoding,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    /*---------------------------------------------------------------
+    ROLLBACK a secret: the value of an earlier version is written
+    as a new version, so the rollback itself is part of the history.
+    ----------------------------------------------------------------*/
+    pub async fn rollback_secret(
+        &self,
+        id: &str,
+        subject: &str,
+        version: u32,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let object_id = parse_id(id)?;
+
+        let Some(mut secret) = self.find_live(&object_id, subject).await? else {
+            return Ok(None);
+        };
+
+        let Some(target) = versions_of(&secret)
+            .into_iter()
+            .find(|candidate| candidate.version == version)
+        else {
+            return Ok(None);
+        };
+
+        let expected_version = secret.version;
+        self.push_version(&mut secret, target.value, target.encoding, subject);
+
+        if !self.store.replace_secret(&secret, expected_version).await? {
+            return Err(StoreError::Conflict);
+        }
+
+        Ok(Some(secret))
+    }
+
+    /*---------------------------------------------------------------
+    PURGE expired secrets of every owner. Called periodically by the
+    reaper fairing, returns the entries that were removed.
+    ----------------------------------------------------------------*/
+    pub async fn purge_expired(&self) -> StoreResult<Vec<VaultDocument>> {
+        self.store.delete_expired_secrets(Utc::now()).await
+    }
+
+    /*---------------------------------------------------------------
+    PURGE secrets of every owner that have been in the trash for
+    longer than the retention window. Called by the reaper fairing.
+    ----------------------------------------------------------------*/
+    pub async fn purge_trash(&self) -> StoreResult<Vec<VaultDocument>> {
+        self.store
+            .delete_trashed_secrets(Utc::now() - self.trash_retention)
+            .await
+    }
+
+    /*-----------------------------
+    GET the ids of the master keys
+    -------------------------------*/
+    pub fn keyring_status(&self) -> StoreResult<KeyringStatus> {
+        let keyring = self.keyring()?;
+        Ok(KeyringStatus {
+            active_key_id: keyring.active_id().to_string(),
+            key_ids: keyring.key_ids(),
+        })
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.keyring_slot().is_none()
+    }
+
+    /// Makes the vault usable with `keyring`, reconstructed from the key shares
+    pub fn unseal(&self, keyring: Keyring) {
+        *self.keyring_slot_mut() = Some(Arc::new(keyring));
+    }
+
+    /// Drops the master keys from memory; the vault refuses requests until unsealed again
+    pub fn seal(&self) {
+        *self.keyring_slot_mut() = None;
+    }
+
+    /*---------------------------------------------------------------
+    START re-encrypting every entry onto the active master key in
+    the background. Only the wrapped data keys are rewritten, except
+    for entries not yet bound to their id, owner and key, whose values
+    are re-encrypted with the binding. Returns false when a job is
+    already running. Entries record their key id and binding, so a job
+    interrupted by a restart resumes where it stopped.
+    ----------------------------------------------------------------*/
+    pub fn start_reencryption(self: &Arc<Self>) -> bool {
+        {
+            let mut progress = self.progress();
+            if progress.state == ReencryptionState::Running {
+                return false;
+            }
+            *progress = ReencryptionProgress {
+                state: ReencryptionState::Running,
+                started_at: Some(Utc::now().to_rfc3339()),
+                ..ReencryptionProgress::default()
+            };
+        }
+
+        let repo = Arc::clone(self);
+        rocket::tokio::spawn(async move { repo.reencrypt_all().await });
+        true
+    }
+
+    /*------------------------------------
+    GET the progress of the re-encryption
+    --------------------------------------*/
+    pub async fn reencryption_progress(&self) -> StoreResult<ReencryptionProgress> {
+        let keyring = self.keyring()?;
+        let mut progress = self.progress().clone();
+        progress.active_key_id = keyring.active_id().to_string();
+        progress.remaining = self
+            .store
+            .count_secrets_to_reencrypt(keyring.active_id())
+            .await?;
+        Ok(progress)
+    }
+
+    async fn reencrypt_all(&self) {
+        let Ok(keyring) = self.keyring() else {
+            self.finish_reencryption(ReencryptionState::Failed);
+            return;
+        };
+        let active_id = keyring.active_id();
+        info!("Re-encrypting vault entries onto key {}", active_id);
+
+        let mut after: Option<ObjectId> = None;
+        loop {
+            if self.is_sealed() {
+                warn!("Re-encryption stopped, the vault was sealed");
+                self.finish_reencryption(ReencryptionState::Failed);
+                return;
+            }
+
+            let batch = match self
+                .store
+                .list_secrets_to_reencrypt(active_id, after.as_ref(), REENCRYPTION_BATCH_SIZE)
+                .await
+            {
+                Ok(batch) => batch,
+                Err(e) => {
+                    error!(
+                        "Re-encryption stopped, failed to list vault entries: {:?}",
+                        e
+                    );
+                    self.finish_reencryption(ReencryptionState::Failed);
+                    return;
+                }
+            };
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = Some(last.id);
+
+            for mut secret in batch {
+                let expected_version = secret.version;
+                let outcome = match self.rewrap(&mut secret) {
+                    Ok(_) => self.store.replace_secret(&secret, expected_version).await,
+                    Err(e) => Err(e),
+                };
+
+                let mut progress = self.progress();
+                match outcome {
+                    Ok(true) => progress.migrated += 1,
+                    Ok(false) => progress.skipped += 1,
+                    Err(e) => {
+                        warn!("Failed to re-encrypt vault entry {}: {:?}", secret.id, e);
+                        progress.failed += 1;
+                    }
                 }
             }
-            secrets.push(secret);
         }
 
-        Ok(secrets)
+        let state = if self.progress().failed > 0 {
+            ReencryptionState::Failed
+        } else {
+            ReencryptionState::Completed
+        };
+        self.finish_reencryption(state);
+    }
+
+    fn finish_reencryption(&self, state: ReencryptionState) {
+        let mut progress = self.progress();
+        progress.state = state;
+        progress.finished_at = Some(Utc::now().to_rfc3339());
+        info!(
+            "Re-encryption finished: {} migrated, {} skipped, {} failed",
+            progress.migrated, progress.skipped, progress.failed
+        );
+    }
+
+    /// The master keys, or `StoreError::Sealed` while the vault is sealed
+    pub(crate) fn keyring(&self) -> StoreResult<Arc<Keyring>> {
+        self.keyring_slot().clone().ok_or(StoreError::Sealed)
+    }
+
+    fn keyring_slot(&self) -> std::sync::RwLockReadGuard<'_, Option<Arc<Keyring>>> {
+        self.keyring
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn keyring_slot_mut(&self) -> std::sync::RwLockWriteGuard<'_, Option<Arc<Keyring>>> {
+        self.keyring
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn progress(&self) -> MutexGuard<'_, ReencryptionProgress> {
+        self.reencryption
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Looks up an entry, treating expired and trashed entries as missing
+    async fn find_live(&self, id: &ObjectId, subject: &str) -> StoreResult<Option<VaultDocument>> {
+        let secret = self.store.get_secret(id, subject).await?;
+        Ok(secret.filter(|secret| !secret.is_expired(Utc::now()) && !secret.is_trashed()))
+    }
+
+    async fn find_trashed(
+        &self,
+        id: &ObjectId,
+        subject: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let secret = self.store.get_secret(id, subject).await?;
+        Ok(secret.filter(|secret| secret.is_trashed()))
+    }
+
+    async fn find_live_by_key(
+        &self,
+        subject: &str,
+        key: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let secret = self.store.get_secret_by_key(subject, key).await?;
+        Ok(secret.filter(|secret| !secret.is_expired(Utc::now())))
+    }
+
+    /// Moves the current value into the history and makes `value` the new current version
+    fn push_version(
+        &self,
+        secret: &mut VaultDocument,
+        value: String,
+        encoding: ValueEncoding,
+        author: &str,
+    ) {
+        let previous = SecretVersion {
+            version: secret.version,
+            value: std::mem::replace(&mut secret.value, value),
+            encoding: std::mem::replace(&mut secret.encoding, encoding),
+            created_by: secret
+                .updated_by
+                .take()
+                .unwrap_or_else(|| secret.created_by.clone()),
+            created_at: secret.updated_at.take().unwrap_or(secret.created_at),
+        };
+
+        secret.history.push(previous);
+        let excess = (secret.history.len() + 1).saturating_sub(self.max_versions);
+        secret.history.drain(..excess);
+
+        secret.version += 1;
+        secret.updated_by = Some(author.to_string());
+        secret.updated_at = Some(Utc::now());
+    }
+
+    /// Generates a random key wrapped with the active master key, returning it with its
+    /// base64 wrapped form and the id of the master key. Transit and signing keys are
+    /// stored this way.
+    pub(crate) fn generate_wrapped_key(&self) -> StoreResult<(DataKey, String, String)> {
+        let keyring = self.keyring()?;
+        let (key, wrapped) = keyring
+            .active_kek()
+            .generate_data_key()
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        Ok((
+            key,
+            general_purpose::STANDARD.encode(wrapped),
+            keyring.active_id().to_string(),
+        ))
+    }
+
+    /// Unwraps a key produced by [Self::generate_wrapped_key]
+    pub(crate) fn unwrap_key(&self, key_id: &str, wrapped: &str) -> StoreResult<DataKey> {
+        let keyring = self.keyring()?;
+        let kek = keyring
+            .kek(Some(key_id))
+            .ok_or_else(|| StoreError::Crypto(format!("unknown key id {}", key_id)))?;
+        let wrapped = BASE64_STANDARD
+            .decode(wrapped)
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        kek.unwrap(&wrapped)
+            .map_err(|e| StoreError::Crypto(e.to_string()))
+    }
+
+    /// Wraps a key produced by [Self::generate_wrapped_key] with the active master key,
+    /// returning its new wrapped form and key id, or `None` if it already is
+    pub(crate) fn rewrap_key(
+        &self,
+        key_id: &str,
+        wrapped: &str,
+    ) -> StoreResult<Option<(String, String)>> {
+        let keyring = self.keyring()?;
+        if keyring.is_active(Some(key_id)) {
+            return Ok(None);
+        }
+        let key = self.unwrap_key(key_id, wrapped)?;
+        let wrapped = keyring
+            .active_kek()
+            .wrap(&key)
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        Ok(Some((
+            general_purpose::STANDARD.encode(wrapped),
+            keyring.active_id().to_string(),
+        )))
+    }
+
+    /// Whether `keyring` opens an entry stored under the master key `key_id`, or `None`
+    /// when there is no such entry. The vault may still be sealed.
+    pub(crate) async fn opens_entries_of(
+        &self,
+        keyring: &Keyring,
+        key_id: &str,
+    ) -> StoreResult<Option<bool>> {
+        let Some(secret) = self.store.find_secret_with_key_id(key_id).await? else {
+            return Ok(None);
+        };
+
+        let opened = match &secret.data_key {
+            Some(wrapped) => keyring.kek(Some(key_id)).is_some_and(|kek| {
+                BASE64_STANDARD
+                    .decode(wrapped)
+                    .is_ok_and(|wrapped| kek.unwrap(&wrapped).is_ok())
+            }),
+            // Entries written before envelope encryption are encrypted with the master key
+            None => keyring.master_key(Some(key_id)).is_some_and(|master_key| {
+                BASE64_STANDARD
+                    .decode(&secret.value)
+                    .is_ok_and(|value| decrypt(&value, master_key).is_ok())
+            }),
+        };
+        Ok(Some(opened))
+    }
+
+    /// Generates a data key for a new entry, returning it with its stored (wrapped) form
+    fn new_data_key(&self) -> StoreResult<(DataKey, String)> {
+        let (data_key, wrapped) = self
+            .keyring()?
+            .active_kek()
+            .generate_data_key()
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        Ok((data_key, general_purpose::STANDARD.encode(wrapped)))
+    }
+
+    fn unwrap_data_key(&self, secret: &VaultDocument, wrapped: &str) -> StoreResult<DataKey> {
+        let key_id = secret.key_id.as_deref();
+        let keyring = self.keyring()?;
+        let kek = keyring
+            .kek(key_id)
+            .ok_or_else(|| StoreError::Crypto(format!("unknown key id {:?}", key_id)))?;
+
+        let wrapped = BASE64_STANDARD
+            .decode(wrapped)
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        kek.unwrap(&wrapped)
+            .map_err(|e| StoreError::Crypto(e.to_string()))
+    }
+
+    /// Wraps the data key of `secret` with the active master key and binds its values to the
+    /// entry, returning the data key. Legacy entries are moved onto envelope encryption.
+    fn rewrap(&self, secret: &mut VaultDocument) -> StoreResult<DataKey> {
+        let Some(wrapped) = &secret.data_key else {
+            return self.migrate_to_envelope(secret);
+        };
+
+        let data_key = self.unwrap_data_key(secret, wrapped)?;
+        let keyring = self.keyring()?;
+        if !keyring.is_active(secret.key_id.as_deref()) {
+            let wrapped = keyring
+                .active_kek()
+                .wrap(&data_key)
+                .map_err(|e| StoreError::Crypto(e.to_string()))?;
+            secret.data_key = Some(general_purpose::STANDARD.encode(wrapped));
+            secret.key_id = Some(keyring.active_id().to_string());
+        }
+        if !secret.bound {
+            self.reencrypt_values(secret, &data_key)?;
+        }
+        Ok(data_key)
+    }
+
+    fn encrypt_with(
+        &self,
+        data_key: &DataKey,
+        context: &[u8],
+        value: &[u8],
+    ) -> StoreResult<String> {
+        let encrypted_value = encrypt_with_key_and_context(value, data_key.expose(), context)
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+        Ok(general_purpose::STANDARD.encode(encrypted_value)) // Use base64 for safe string storage
+    }
+
+    /// Encrypts a new value for `secret`, moving the entry onto the active master key first
+    fn seal_value(&self, secret: &mut VaultDocument, value: &[u8]) -> StoreResult<String> {
+        let data_key = self.rewrap(secret)?;
+        self.encrypt_with(&data_key, &associated_data(secret), value)
+    }
+
+    /// Re-encrypts the current value and the history of a legacy entry under a new data key
+    fn migrate_to_envelope(&self, secret: &mut VaultDocument) -> StoreResult<DataKey> {
+        let (data_key, wrapped) = self.new_data_key()?;
+        self.reencrypt_values(secret, &data_key)?;
+
+        secret.data_key = Some(wrapped);
+        secret.key_id = Some(self.keyring()?.active_id().to_string());
+        Ok(data_key)
+    }
+
+    /// Re-encrypts the current value and the history of `secret` under `data_key`, bound to the entry
+    fn reencrypt_values(&self, secret: &mut VaultDocument, data_key: &DataKey) -> StoreResult<()> {
+        let value = self.open_value(secret, &secret.value)?;
+        let history = secret
+            .history
+            .iter()
+            .map(|version| self.open_value(secret, &version.value))
+            .collect::<StoreResult<Vec<SecretBytes>>>()?;
+
+        let context = associated_data(secret);
+        secret.value = self.encrypt_with(data_key, &context, value.expose())?;
+        for (version, value) in secret.history.iter_mut().zip(history) {
+            version.value = self.encrypt_with(data_key, &context, value.expose())?;
+        }
+        secret.bound = true;
+        Ok(())
+    }
+
+    /// Decrypts `value`, the current or an earlier value of `secret`, and encodes it for JSON
+    fn present_value(
+        &self,
+        secret: &VaultDocument,
+        value: &str,
+        encoding: ValueEncoding,
+    ) -> StoreResult<String> {
+        let value = self.decrypt_value(secret, value)?;
+        Ok(match encoding {
+            // Handed to the caller, who becomes responsible for the plaintext
+            ValueEncoding::Utf8 => value.into_string().into_exposed(),
+            ValueEncoding::Base64 => general_purpose::STANDARD.encode(value.expose()),
+        })
+    }
+
+    /// Decrypts `value`, the current or an earlier value of `secret`
+    fn decrypt_value(&self, secret: &VaultDocument, value: &str) -> StoreResult<SecretBytes> {
+        if self.require_bound && !secret.bound {
+            return Err(StoreError::Crypto(format!(
+                "vault entry {} is not bound to its record",
+                secret.id
+            )));
+        }
+        self.open_value(secret, value)
+    }
+
+    /// Decrypts `value` without enforcing ECS_REQUIRE_BOUND_SECRETS, to migrate unbound entries
+    fn open_value(&self, secret: &VaultDocument, value: &str) -> StoreResult<SecretBytes> {
+        let encoded_value = BASE64_STANDARD
+            .decode(value)
+            .map_err(|e| StoreError::Crypto(e.to_string()))?;
+
+        let decrypted_value = match &secret.data_key {
+            Some(wrapped) if secret.bound => decrypt_with_key_and_context(
+                &encoded_value,
+                self.unwrap_data_key(secret, wrapped)?.expose(),
+                &associated_data(secret),
+            ),
+            Some(wrapped) => decrypt_with_key(
+                &encoded_value,
+                self.unwrap_data_key(secret, wrapped)?.expose(),
+            ),
+            None => {
+                let keyring = self.keyring()?;
+                let master_key = keyring
+                    .master_key(secret.key_id.as_deref())
+                    .ok_or_else(|| StoreError::Crypto("unknown key id".to_string()))?;
+                decrypt(&encoded_value, master_key)
+            }
+        }
+        .map_err(|e| StoreError::Crypto(e.to_string()))?;
+
+        Ok(SecretBytes::new(decrypted_value))
+    }
+
+    /// Fails as a whole when any value does not decrypt, rather than returning its ciphertext
+    fn decrypt_all(&self, secrets: Vec<VaultDocument>) -> StoreResult<Vec<VaultDocument>> {
+        secrets
+            .into_iter()
+            .map(|mut secret| {
+                secret.value = self
+                    .present_value(&secret, &secret.value, secret.encoding)
+                    .inspect_err(|e| error!("Cannot decrypt vault entry {}: {}", secret.id, e))?;
+                // Earlier versions are only exposed through the versions endpoints
+                secret.history.clear();
+                secret.data_key = None;
+                Ok(secret)
+            })
+            .collect()
+    }
+}
+
+/// The bytes to store for a value submitted with `encoding`
+fn decode_value(value: &str, encoding: ValueEncoding) -> StoreResult<SecretBytes> {
+    match encoding {
+        ValueEncoding::Utf8 => Ok(SecretBytes::new(value.as_bytes().to_vec())),
+        ValueEncoding::Base64 => general_purpose::STANDARD
+            .decode(value.trim())
+            .map(SecretBytes::new)
+            .map_err(|e| StoreError::InvalidValue(format!("value is not valid base64: {}", e))),
+    }
+}
+
+/// The id, owner and key of an entry, authenticated with each of its values so that a
+/// ciphertext copied into another record fails to decrypt
+fn associated_data(secret: &VaultDocument) -> Vec<u8> {
+    let mut context = b"ec_secrets_management/vault".to_vec();
+    for field in [secret.id.to_hex().as_str(), &secret.created_by, &secret.key] {
+        // Length prefixed so that fields cannot run into each other
+        context.extend((field.len() as u64).to_le_bytes());
+        context.extend(field.as_bytes());
+    }
+    context
+}
+
+fn live(secrets: Vec<VaultDocument>) -> Vec<VaultDocument> {
+    let now: DateTime<Utc> = Utc::now();
+    secrets
+        .into_iter()
+        .filter(|secret| !secret.is_expired(now) && !secret.is_trashed())
+        .collect()
+}
+
+/// Every version of a secret, oldest first, including the current one
+fn versions_of(secret: &VaultDocument) -> Vec<SecretVersion> {
+    let mut versions = secret.history.clone();
+    versions.push(SecretVersion {
+        version: secret.version,
+        value: secret.value.clone(),
+        encoding: secret.encoding,
+        created_by: secret
+            .updated_by
+            .clone()
+            .unwrap_or_else(|| secret.created_by.clone()),
+        created_at: secret.updated_at.unwrap_or(secret.created_at),
+    });
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory::MemoryStore;
+    use crate::utils::key_provider::StaticKeyProvider;
+    use crate::utils::keyring::DEFAULT_KEY_ID;
+
+    fn repository(max_versions: usize) -> VaultRepository {
+        repository_on(
+            Arc::new(MemoryStore::new()),
+            Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap(),
+            max_versions,
+        )
+    }
+
+    fn repository_on(
+        store: Arc<dyn VaultStore>,
+        keyring: Keyring,
+        max_versions: usize,
+    ) -> VaultRepository {
+        VaultRepository {
+            store,
+            keyring: RwLock::new(Some(Arc::new(keyring))),
+            max_versions,
+            trash_retention: Duration::days(DEFAULT_TRASH_RETENTION_DAYS),
+            require_bound: false,
+            reencryption: Mutex::new(ReencryptionProgress::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn the_master_key_comes_from_the_key_provider() {
+        let store = Arc::new(MemoryStore::new());
+        let sealed =
+            VaultRepository::with_key_provider(store.clone(), &StaticKeyProvider::new(None));
+        assert!(sealed.is_sealed());
+        assert!(matches!(
+            sealed
+                .create_secret(
+                    "api_key",
+                    "v",
+                    "alice@example.com",
+                    SecretOptions::default()
+                )
+                .await,
+            Err(StoreError::Sealed)
+        ));
+
+        let key = "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=";
+        let unsealed =
+            VaultRepository::with_key_provider(store, &StaticKeyProvider::new(Some(key)));
+        let secret = unsealed
+            .create_secret(
+                "api_key",
+                "v",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let other = repository_on(
+            unsealed.store.clone(),
+            Keyring::new(DEFAULT_KEY_ID, key, Vec::new()).unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        );
+        assert_eq!(
+            other
+                .get_secret_by_id(&secret.id.to_hex(), "alice@example.com")
+                .await
+                .unwrap(),
+            Some("v".to_string())
+        );
+
+        // Another key never hands out ciphertext in place of a value
+        let wrong = repository_on(
+            unsealed.store.clone(),
+            Keyring::new(DEFAULT_KEY_ID, "wrong", Vec::new()).unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        );
+        assert!(matches!(
+            wrong
+                .get_secret_by_id(&secret.id.to_hex(), "alice@example.com")
+                .await,
+            Err(StoreError::Crypto(_))
+        ));
+        assert!(matches!(
+            wrong.list_secrets("alice@example.com").await,
+            Err(StoreError::Crypto(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rollback_writes_a_new_version() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let secret = repo
+            .create_secret(
+                "api_key",
+                "first",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+
+        let rolled_back = repo
+            .rollback_secret(&id, "alice@example.com", 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rolled_back.version, 2);
+
+        let versions = repo
+            .list_secret_versions(&id, "alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(versions[1].current);
+        assert_eq!(
+            repo.get_secret_version(&id, "alice@example.com", 2)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("first")
+        );
+        assert!(repo
+            .rollback_secret(&id, "alice@example.com", 7)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn binary_values_are_stored_as_raw_bytes() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let keystore = [0x30, 0x82, 0xff, 0x00, 0xfe];
+        let encoded = general_purpose::STANDARD.encode(keystore);
+        let options = SecretOptions {
+            encoding: ValueEncoding::Base64,
+            ..SecretOptions::default()
+        };
+        assert!(matches!(
+            repo.create_secret(
+                "keystore",
+                "not base64!",
+                "alice@example.com",
+                options.clone()
+            )
+            .await,
+            Err(StoreError::InvalidValue(_))
+        ));
+
+        let secret = repo
+            .create_secret("keystore", &encoded, "alice@example.com", options)
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+        assert_eq!(
+            repo.get_secret_bytes_by_id(&id, "alice@example.com")
+                .await
+                .unwrap(),
+            Some(keystore.to_vec())
+        );
+        assert_eq!(
+            repo.get_secret_by_id(&id, "alice@example.com")
+                .await
+                .unwrap(),
+            Some(encoded.clone())
+        );
+
+        // Updates keep the encoding of the entry unless another one is given
+        let metadata = SecretMetadata {
+            encoding: Some(ValueEncoding::Utf8),
+            ..SecretMetadata::default()
+        };
+        repo.update_secret(&id, "text", 1, "alice@example.com", metadata)
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.get_secret_bytes_by_id(&id, "alice@example.com")
+                .await
+                .unwrap(),
+            Some(b"text".to_vec())
+        );
+        assert_eq!(
+            repo.get_secret_version(&id, "alice@example.com", 1)
+                .await
+                .unwrap(),
+            Some(encoded.clone())
+        );
+
+        let rolled_back = repo
+            .rollback_secret(&id, "alice@example.com", 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rolled_back.encoding, ValueEncoding::Base64);
+        let listed = repo.list_secrets("alice@example.com").await.unwrap();
+        assert_eq!(listed[0].value, encoded);
+    }
+
+    #[tokio::test]
+    async fn stale_updates_conflict() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let secret = repo
+            .create_secret(
+                "api_key",
+                "first",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+
+        let updated = repo
+            .update_secret(
+                &id,
+                "second",
+                1,
+                "alice@example.com",
+                SecretMetadata::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.version, 2);
+
+        assert!(matches!(
+            repo.update_secret(
+                &id,
+                "third",
+                1,
+                "alice@example.com",
+                SecretMetadata::default()
+            )
+            .await,
+            Err(StoreError::Conflict)
+        ));
+        assert_eq!(
+            repo.get_secret_by_id(&id, "alice@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("second")
+        );
+    }
+
+    #[tokio::test]
+    async fn paths_are_unique_per_owner() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        repo.create_secret(
+            "payments/prod/stripe_api_key",
+            "sk",
+            "alice@example.com",
+            SecretOptions::default(),
+        )
+        .await
+        .unwrap();
+        repo.create_secret(
+            "payments/prod/stripe_api_key",
+            "sk",
+            "bob@example.com",
+            SecretOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            repo.create_secret(
+                "/payments/prod/stripe_api_key",
+                "sk",
+                "alice@example.com",
+                SecretOptions::default()
+            )
+            .await,
+            Err(StoreError::Duplicate("key"))
+        ));
+        assert!(matches!(
+            repo.create_secret(
+                "payments/../stripe_api_key",
+                "sk",
+                "alice@example.com",
+                SecretOptions::default()
+            )
+            .await,
+            Err(StoreError::InvalidPath(_))
+        ));
+
+        let updated = repo
+            .put_secret_by_path(
+                "payments/prod/stripe_api_key",
+                "sk2",
+                Some(1),
+                "alice@example.com",
+                SecretMetadata::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.version, 2);
+        assert_eq!(
+            repo.get_secret_by_path("payments/prod/stripe_api_key", "bob@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("sk")
+        );
+
+        let listing = repo
+            .list_path("payments", "alice@example.com")
+            .await
+            .unwrap();
+        assert_eq!(listing.prefix, "payments/");
+        assert_eq!(listing.folders, vec!["prod"]);
+        assert!(listing.keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_secrets_are_hidden_and_purged() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let expired = SecretOptions {
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+            ..SecretOptions::default()
+        };
+        let secret = repo
+            .create_secret("temporary", "value", "alice@example.com", expired)
+            .await
+            .unwrap();
+        repo.create_secret(
+            "permanent",
+            "value",
+            "alice@example.com",
+            SecretOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(repo
+            .get_secret_by_id(&secret.id.to_hex(), "alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            repo.list_secrets("alice@example.com").await.unwrap().len(),
+            1
+        );
+
+        let purged = repo.purge_expired().await.unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].key, "temporary");
+
+        // The path is free again once the entry has expired
+        repo.create_secret(
+            "temporary",
+            "value",
+            "alice@example.com",
+            SecretOptions::default(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleted_secrets_can_be_restored_from_the_trash() {
+        let mut repo = repository(DEFAULT_MAX_VERSIONS);
+        let secret = repo
+            .create_secret(
+                "api_key",
+                "first",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+
+        repo.delete_secret(&id, "alice@example.com").await.unwrap();
+        assert!(repo
+            .get_secret_by_id(&id, "alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        let trash = repo.list_trash("alice@example.com").await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].key, "api_key");
+
+        // The path can be reused while the old entry sits in the trash
+        let replacement = repo
+            .create_secret(
+                "api_key",
+                "second",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.restore_secret(&id, "alice@example.com").await,
+            Err(StoreError::Duplicate("key"))
+        ));
+
+        repo.delete_secret(&replacement.id.to_hex(), "alice@example.com")
+            .await
+            .unwrap();
+        repo.restore_secret(&id, "alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            repo.get_secret_by_path("api_key", "alice@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("first")
+        );
+
+        // Live entries cannot be purged, trashed ones are purged after retention
+        assert!(repo
+            .purge_secret(&id, "alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(repo.purge_trash().await.unwrap().is_empty());
+        repo.trash_retention = Duration::zero();
+        let purged = repo.purge_trash().await.unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].id, replacement.id);
+    }
+
+    #[tokio::test]
+    async fn labels_and_descriptions_are_kept_across_updates() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let options = SecretOptions {
+            description: Some("Stripe key used by checkout".to_string()),
+            labels: [("env".to_string(), "prod".to_string())].into(),
+            ..SecretOptions::default()
+        };
+        let secret = repo
+            .create_secret("stripe_api_key", "sk", "alice@example.com", options)
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+
+        let updated = repo
+            .update_secret(
+                &id,
+                "sk2",
+                1,
+                "alice@example.com",
+                SecretMetadata::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.labels.get("env").map(String::as_str), Some("prod"));
+        assert!(updated.description.is_some());
+
+        let relabelled = repo
+            .update_secret(
+                &id,
+                "sk3",
+                2,
+                "alice@example.com",
+                SecretMetadata {
+                    encoding: None,
+                    description: Some(String::new()),
+                    labels: Some([("team".to_string(), "payments".to_string())].into()),
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(relabelled.description.is_none());
+        assert_eq!(relabelled.labels.len(), 1);
+        assert!(relabelled.labels.contains_key("team"));
+
+        let invalid = SecretOptions {
+            labels: [("env name".to_string(), "prod".to_string())].into(),
+            ..SecretOptions::default()
+        };
+        assert!(matches!(
+            repo.create_secret("other", "sk", "alice@example.com", invalid)
+                .await,
+            Err(StoreError::InvalidLabels(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn legacy_entries_move_to_envelope_encryption_on_write() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let legacy_value = |value: &str| {
+            general_purpose::STANDARD
+                .encode(crate::utils::vault::encrypt(value.as_bytes(), b"test").unwrap())
+        };
+        let legacy = VaultDocument {
+            id: ObjectId::new(),
+            key: "legacy".to_string(),
+            value: legacy_value("second"),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: "alice@example.com".to_string(),
+            created_at: Utc::now(),
+            description: None,
+            labels: Default::default(),
+            expires_at: None,
+            deleted_at: None,
+            version: 2,
+            updated_by: None,
+            updated_at: None,
+            history: vec![SecretVersion {
+                version: 1,
+                value: legacy_value("first"),
+                encoding: ValueEncoding::Utf8,
+                created_by: "alice@example.com".to_string(),
+                created_at: Utc::now(),
+            }],
+        };
+        repo.store.insert_secret(&legacy).await.unwrap();
+        let id = legacy.id.to_hex();
+
+        assert_eq!(
+            repo.get_secret_by_id(&id, "alice@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("second")
+        );
+
+        let updated = repo
+            .update_secret(
+                &id,
+                "third",
+                2,
+                "alice@example.com",
+                SecretMetadata::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(updated.data_key.is_some());
+
+        for (version, value) in [(1, "first"), (2, "second"), (3, "third")] {
+            assert_eq!(
+                repo.get_secret_version(&id, "alice@example.com", version)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(value)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn history_is_trimmed_to_max_versions() {
+        let repo = repository(2);
+        let mut secret = repo
+            .create_secret(
+                "api_key",
+                "first",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        for value in ["second", "third", "fourth"] {
+            repo.push_version(
+                &mut secret,
+                value.to_string(),
+                ValueEncoding::Utf8,
+                "alice@example.com",
+            );
+        }
+
+        assert_eq!(secret.version, 4);
+        assert_eq!(secret.value, "fourth");
+        assert_eq!(
+            secret.history.iter().map(|v| v.version).collect::<Vec<_>>(),
+            vec![3]
+        );
+    }
+
+    #[tokio::test]
+    async fn rotation_moves_every_entry_onto_the_active_key() {
+        let store: Arc<dyn VaultStore> = Arc::new(MemoryStore::new());
+        let old = repository_on(
+            store.clone(),
+            Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        );
+        let mut ids = Vec::new();
+        for key in ["a", "b", "c"] {
+            let secret = old
+                .create_secret(key, key, "alice@example.com", SecretOptions::default())
+                .await
+                .unwrap();
+            ids.push(secret.id.to_hex());
+        }
+
+        let rotated = Arc::new(repository_on(
+            store.clone(),
+            Keyring::new(
+                "2025",
+                "new",
+                vec![(DEFAULT_KEY_ID.to_string(), "test".to_string())],
+            )
+            .unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        ));
+        assert_eq!(rotated.reencryption_progress().await.unwrap().remaining, 3);
+
+        assert!(rotated.start_reencryption());
+        let progress = loop {
+            let progress = rotated.reencryption_progress().await.unwrap();
+            if progress.state != ReencryptionState::Running {
+                break progress;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert_eq!(progress.state, ReencryptionState::Completed);
+        assert_eq!((progress.migrated, progress.remaining), (3, 0));
+
+        // The retired key is no longer needed
+        let new_only = repository_on(
+            store,
+            Keyring::new("2025", "new", Vec::new()).unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        );
+        for (id, value) in ids.iter().zip(["a", "b", "c"]) {
+            assert_eq!(
+                new_only
+                    .get_secret_by_id(id, "alice@example.com")
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(value)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn values_are_bound_to_their_entry() {
+        let repo = repository(DEFAULT_MAX_VERSIONS);
+        let alice = repo
+            .create_secret(
+                "db",
+                "alice's",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let mallory = repo
+            .create_secret(
+                "db",
+                "mallory's",
+                "mallory@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        // Moving a ciphertext and its data key into another record does not decrypt
+        let mut forged = mallory.clone();
+        forged.value = alice.value.clone();
+        forged.data_key = alice.data_key.clone();
+        repo.store
+            .replace_secret(&forged, forged.version)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repo.get_secret_by_id(&mallory.id.to_hex(), "mallory@example.com")
+                .await,
+            Err(StoreError::Crypto(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn unbound_entries_are_bound_by_the_reencryption() {
+        let repo = Arc::new(repository(DEFAULT_MAX_VERSIONS));
+        let mut secret = repo
+            .create_secret(
+                "db",
+                "password",
+                "alice@example.com",
+                SecretOptions::default(),
+            )
+            .await
+            .unwrap();
+        let id = secret.id.to_hex();
+
+        // As written before values were bound to their entry
+        let data_key = repo
+            .unwrap_data_key(&secret, secret.data_key.as_deref().unwrap())
+            .unwrap();
+        secret.value = repo.encrypt_with(&data_key, &[], b"password").unwrap();
+        secret.bound = false;
+        repo.store
+            .replace_secret(&secret, secret.version)
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.get_secret_by_id(&id, "alice@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("password")
+        );
+        assert_eq!(repo.reencryption_progress().await.unwrap().remaining, 1);
+
+        assert!(repo.start_reencryption());
+        while repo.reencryption_progress().await.unwrap().state == ReencryptionState::Running {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(repo.reencryption_progress().await.unwrap().remaining, 0);
+
+        let strict = VaultRepository {
+            require_bound: true,
+            ..repository_on(
+                repo.store.clone(),
+                Keyring::new(DEFAULT_KEY_ID, "test", Vec::new()).unwrap(),
+                DEFAULT_MAX_VERSIONS,
+            )
+        };
+        assert_eq!(
+            strict
+                .get_secret_by_id(&id, "alice@example.com")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("password")
+        );
+    }
+
+    #[test]
+    fn debug_output_redacts_key_material() {
+        let repo = repository_on(
+            Arc::new(MemoryStore::new()),
+            Keyring::new(
+                "2025",
+                "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=",
+                Vec::new(),
+            )
+            .unwrap(),
+            DEFAULT_MAX_VERSIONS,
+        );
+        let debug = format!("{:?}", repo);
+        assert!(debug.contains("2025"));
+        assert!(debug.contains("REDACTED"));
+        assert!(!debug.contains("IRwTgHBtmblSfAXpYOuvf4ZI"));
     }
 }
@@ -15,6 +15,7 @@ use rocket::{
 use std::sync::Arc;
 
 use crate::repositories::key::KeyRepository;
+use crate::repositories::vault::VaultRepository;
 
 pub struct TokenGuard(pub Claims);
 
@@ -61,3 +62,87 @@ impl<'r> FromRequest<'r> for TokenGuard {
         }
     }
 }
+
+/*---------------------------------------------------------------
+ Admits authenticated users listed in ECS_ADMIN_USERS, a comma
+ separated list of emails. Nobody is an admin when it is unset.
+----------------------------------------------------------------*/
+pub struct AdminGuard(pub Claims);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for AdminGuard {
+    type Error = Status;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let token = match request.guard::<TokenGuard>().await {
+            Outcome::Success(token) => token,
+            Outcome::Error(e) => return Outcome::Error(e),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+
+        let subject = token
+            .0
+            .get_claim("sub")
+            .and_then(|subject| subject.as_str())
+            .map(str::to_string);
+
+        match subject {
+            Some(subject) if is_admin(&subject) => Outcome::Success(AdminGuard(token.0)),
+            _ => Outcome::Error((Status::Forbidden, Status::Forbidden)),
+        }
+    }
+}
+
+fn is_admin(subject: &str) -> bool {
+    std::env::var("ECS_ADMIN_USERS").is_ok_and(|admins| {
+        admins
+            .split(',')
+            .map(str::trim)
+            .any(|admin| !admin.is_empty() && admin == subject)
+    })
+}
+
+/*---------------------------------------------------------------
+ Fails with 503 Service Unavailable while the vault is sealed, so
+ requests needing the master key reach the service_unavailable
+ catcher instead of the handler.
+----------------------------------------------------------------*/
+pub struct Unsealed;
+
+#[async_trait]
+impl<'r> FromRequest<'r> for Unsealed {
+    type Error = Status;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let vault_repo = match request.guard::<&State<Arc<VaultRepository>>>().await {
+            Outcome::Success(state) => state,
+            _ => return Outcome::Forward(Status::InternalServerError),
+        };
+
+        if vault_repo.is_sealed() {
+            Outcome::Error((Status::ServiceUnavailable, Status::ServiceUnavailable))
+        } else {
+            Outcome::Success(Unsealed)
+        }
+    }
+}
+
+/*---------------------------------------------------------------
+ The passphrase of a backup, from the X-Backup-Passphrase header.
+ A header keeps it out of URLs and lets the body carry the bundle.
+----------------------------------------------------------------*/
+pub struct BackupPassphrase(pub String);
+
+#[async_trait]
+impl<'r> FromRequest<'r> for BackupPassphrase {
+    type Error = Status;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("X-Backup-Passphrase") {
+            Some(passphrase) if !passphrase.is_empty() => {
+                Outcome::Success(BackupPassphrase(passphrase.to_string()))
+            }
+            _ => Outcome::Error((Status::BadRequest, Status::BadRequest)),
+        }
+    }
+}
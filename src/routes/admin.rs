@@ -0,0 +1,122 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::signing::SigningRepository;
+use crate::repositories::transit::TransitRepository;
+use crate::repositories::vault::VaultRepository;
+use crate::request_guards::{AdminGuard, Unsealed};
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, info, warn};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/*----------------------------------
+ List the master keys of the keyring
+-----------------------------------*/
+#[get("/admin/keyring")]
+pub async fn get_keyring(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    _admin: AdminGuard,
+) -> Result<Json<KeyringStatus>, Json<ErrorResponse>> {
+    match repo.keyring_status() {
+        Ok(status) => Ok(Json(status)),
+        Err(e) => {
+            error!("Failed to read the keyring: {:?}", e);
+            Err(Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Failed to read the keyring.".to_string(),
+            }))
+        }
+    }
+}
+
+/*-------------------------------------------------------------
+ Re-encrypt every vault entry onto the active master key. The
+ job runs in the background; poll the rotation endpoint for its
+ progress. Starting it again after a restart resumes the work.
+ Transit and signing keys are few and are rewrapped before the
+ job starts.
+--------------------------------------------------------------*/
+#[post("/admin/keyring/rotate")]
+pub async fn rotate_keyring(
+    repo: &State<Arc<VaultRepository>>,
+    transit: &State<Arc<TransitRepository>>,
+    signing: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    _admin: AdminGuard,
+) -> Result<Json<ReencryptionProgress>, Json<ErrorResponse>> {
+    match transit.rewrap_keys().await {
+        Ok(count) => info!("Rewrapped {} transit keys", count),
+        Err(e) => {
+            error!("Failed to rewrap the transit keys: {:?}", e);
+            return Err(Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Failed to rewrap the transit keys.".to_string(),
+            }));
+        }
+    }
+
+    match signing.rewrap_keys().await {
+        Ok(count) => info!("Rewrapped {} signing keys", count),
+        Err(e) => {
+            error!("Failed to rewrap the signing keys: {:?}", e);
+            return Err(Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Failed to rewrap the signing keys.".to_string(),
+            }));
+        }
+    }
+
+    if !repo.inner().start_reencryption() {
+        warn!("Re-encryption is already running");
+        return Err(Json(ErrorResponse {
+            status: Status::Conflict.code,
+            message: "Re-encryption is already running.".to_string(),
+        }));
+    }
+
+    info!("Re-encryption started");
+    progress(repo).await
+}
+
+/*-----------------------------
+ Progress of the re-encryption
+------------------------------*/
+#[get("/admin/keyring/rotation")]
+pub async fn rotation_progress(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    _admin: AdminGuard,
+) -> Result<Json<ReencryptionProgress>, Json<ErrorResponse>> {
+    progress(repo).await
+}
+
+async fn progress(
+    repo: &VaultRepository,
+) -> Result<Json<ReencryptionProgress>, Json<ErrorResponse>> {
+    match repo.reencryption_progress().await {
+        Ok(progress) => Ok(Json(progress)),
+        Err(e) => {
+            error!("Failed to read the re-encryption progress: {:?}", e);
+            Err(Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Failed to read the re-encryption progress.".to_string(),
+            }))
+        }
+    }
+}
+
+pub fn admin_routes() -> Vec<rocket::Route> {
+    routes![get_keyring, rotate_keyring, rotation_progress]
+}
@@ -0,0 +1,135 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::backup::{BackupError, BackupRepository};
+use crate::request_guards::{AdminGuard, BackupPassphrase};
+use crate::storage::StoreError;
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, info, warn};
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
+use rocket::{post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/// Largest backup bundle a restore accepts
+const MAX_BUNDLE_SIZE_MIB: u64 = 256;
+
+/*---------------------------------------------------------------
+ Download an encrypted backup of every store. The passphrase is
+ needed again to restore it and cannot be recovered.
+----------------------------------------------------------------*/
+#[post("/admin/backup")]
+pub async fn backup(
+    repo: &State<Arc<BackupRepository>>,
+    passphrase: BackupPassphrase,
+    _admin: AdminGuard,
+) -> Result<(ContentType, Vec<u8>), Json<ErrorResponse>> {
+    match repo.backup(&passphrase.0).await {
+        Ok(bundle) => {
+            info!("Backup downloaded.");
+            Ok((ContentType::Binary, bundle))
+        }
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Restore a backup sent as the request body. `mode` is merge (the
+ default) or replace; with `dry_run` nothing is written and the
+ report tells what the restore would do. `force` restores a backup
+ whose key check values the master keys do not match.
+----------------------------------------------------------------*/
+#[post("/admin/restore?<query..>", data = "<bundle>")]
+pub async fn restore(
+    repo: &State<Arc<BackupRepository>>,
+    passphrase: BackupPassphrase,
+    query: RestoreQuery,
+    bundle: Data<'_>,
+    _admin: AdminGuard,
+) -> Result<Json<RestoreResponse>, Json<ErrorResponse>> {
+    let mode = match query.mode.as_deref().map(str::parse).transpose() {
+        Ok(mode) => mode.unwrap_or_default(),
+        Err(message) => {
+            error!("Invalid request: {}", message);
+            return Err(Json(ErrorResponse {
+                status: Status::BadRequest.code,
+                message,
+            }));
+        }
+    };
+
+    let bundle = match bundle
+        .open(MAX_BUNDLE_SIZE_MIB.mebibytes())
+        .into_bytes()
+        .await
+    {
+        Ok(bundle) if bundle.is_complete() => bundle.into_inner(),
+        Ok(_) => {
+            return Err(Json(ErrorResponse {
+                status: Status::PayloadTooLarge.code,
+                message: format!("Backups are limited to {} MiB.", MAX_BUNDLE_SIZE_MIB),
+            }))
+        }
+        Err(e) => {
+            error!("Failed to read the backup: {:?}", e);
+            return Err(Json(ErrorResponse {
+                status: Status::BadRequest.code,
+                message: "Failed to read the backup.".to_string(),
+            }));
+        }
+    };
+
+    match repo
+        .restore(
+            &bundle,
+            &passphrase.0,
+            mode,
+            query.dry_run.unwrap_or(false),
+            query.force.unwrap_or(false),
+        )
+        .await
+    {
+        Ok(report) => Ok(Json(RestoreResponse {
+            status: Status::Ok.code,
+            report,
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+fn failure(e: BackupError) -> Json<ErrorResponse> {
+    let status = match e {
+        BackupError::WeakPassphrase(_)
+        | BackupError::Decrypt
+        | BackupError::UnsupportedFormat(_)
+        | BackupError::Invalid(_) => Status::BadRequest,
+        BackupError::KeyMismatch(_) => Status::Conflict,
+        BackupError::Store(StoreError::Sealed) => Status::ServiceUnavailable,
+        BackupError::Encrypt(_) | BackupError::Store(_) => {
+            error!("Backup operation failed: {:?}", e);
+            return Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Backup operation failed.".to_string(),
+            });
+        }
+    };
+
+    warn!("Backup operation rejected: {}", e);
+    Json(ErrorResponse {
+        status: status.code,
+        message: e.to_string(),
+    })
+}
+
+pub fn backup_routes() -> Vec<rocket::Route> {
+    routes![backup, restore]
+}
@@ -0,0 +1,221 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::generator::{GeneratorError, GeneratorRepository};
+use crate::request_guards::{AdminGuard, TokenGuard};
+use crate::routes::vault::expiry;
+use crate::storage::StoreError;
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, info, warn};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{delete, get, post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/*---------------------------------------------------------------
+ Generate a secret from a named policy or an inline spec. With
+ `store` the value becomes a new vault entry of the caller and is
+ not returned; otherwise it is returned once and kept nowhere.
+----------------------------------------------------------------*/
+#[post("/generate", format = "json", data = "<request>")]
+pub async fn generate(
+    repo: &State<Arc<GeneratorRepository>>,
+    request: Json<GenerateRequest>,
+    token: TokenGuard,
+) -> Result<Json<GenerateResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let request = request.into_inner();
+
+    let Some(store) = request.store else {
+        return match repo.generate(request.policy.as_deref(), request.spec).await {
+            Ok(generated) => Ok(Json(GenerateResponse {
+                status: Status::Ok.code,
+                encoding: generated.encoding,
+                value: Some(generated.value.expose().clone()),
+                entry_id: None,
+                key: None,
+            })),
+            Err(e) => Err(failure(e)),
+        };
+    };
+
+    let expires_at = match expiry(store.ttl, store.expires_at.as_deref()) {
+        Ok(expires_at) => expires_at,
+        Err(message) => {
+            error!("Invalid request: {}", message);
+            return Err(Json(ErrorResponse {
+                status: Status::BadRequest.code,
+                message,
+            }));
+        }
+    };
+
+    match repo
+        .generate_entry(
+            request.policy.as_deref(),
+            request.spec,
+            &store.key,
+            &subject,
+            SecretOptions {
+                expires_at,
+                description: store.description,
+                labels: store.labels,
+                ..SecretOptions::default()
+            },
+        )
+        .await
+    {
+        Ok(entry) => {
+            info!("Generated vault entry created successfully.");
+            Ok(Json(GenerateResponse {
+                status: Status::Ok.code,
+                value: None,
+                encoding: entry.encoding,
+                entry_id: Some(entry.id.to_hex()),
+                key: Some(entry.key),
+            }))
+        }
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------------
+ Create a named generator policy
+------------------------------------*/
+#[post("/generate/policies", format = "json", data = "<request>")]
+pub async fn create_policy(
+    repo: &State<Arc<GeneratorRepository>>,
+    request: Json<CreateGeneratorPolicy>,
+    admin: AdminGuard,
+) -> Result<Json<GeneratorPolicyDocument>, Json<ErrorResponse>> {
+    let request = request.into_inner();
+    let created_by = admin
+        .0
+        .get_claim("sub")
+        .and_then(|subject| subject.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    match repo
+        .create_policy(
+            &request.name,
+            request.description,
+            request.spec,
+            &created_by,
+        )
+        .await
+    {
+        Ok(policy) => {
+            info!("Generator policy {} created.", policy.name);
+            Ok(Json(policy))
+        }
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ List the generator policies
+------------------------------*/
+#[get("/generate/policies")]
+pub async fn list_policies(
+    repo: &State<Arc<GeneratorRepository>>,
+    _token: TokenGuard,
+) -> Result<Json<Vec<GeneratorPolicyDocument>>, Json<ErrorResponse>> {
+    match repo.list_policies().await {
+        Ok(policies) => Ok(Json(policies)),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ Describe a generator policy
+------------------------------*/
+#[get("/generate/policies/<name>")]
+pub async fn get_policy(
+    repo: &State<Arc<GeneratorRepository>>,
+    name: &str,
+    _token: TokenGuard,
+) -> Result<Json<GeneratorPolicyDocument>, Json<ErrorResponse>> {
+    match repo.get_policy(name).await {
+        Ok(policy) => Ok(Json(policy)),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ Delete a generator policy
+------------------------------*/
+#[delete("/generate/policies/<name>")]
+pub async fn delete_policy(
+    repo: &State<Arc<GeneratorRepository>>,
+    name: &str,
+    _admin: AdminGuard,
+) -> Result<Json<DeletePolicyResponse>, Json<ErrorResponse>> {
+    match repo.delete_policy(name).await {
+        Ok(()) => Ok(Json(DeletePolicyResponse {
+            status: Status::Ok.code,
+            message: "Generator policy deleted successfully".to_string(),
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+fn subject(token: &TokenGuard) -> Result<String, Json<ErrorResponse>> {
+    match token
+        .0
+        .get_claim("sub")
+        .and_then(|subject| subject.as_str())
+    {
+        Some(subject) => Ok(subject.to_string()),
+        None => Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })),
+    }
+}
+
+fn failure(e: GeneratorError) -> Json<ErrorResponse> {
+    let status = match e {
+        GeneratorError::InvalidName(_)
+        | GeneratorError::InvalidPolicy(_)
+        | GeneratorError::InvalidInput(_)
+        | GeneratorError::Store(StoreError::InvalidPath(_))
+        | GeneratorError::Store(StoreError::InvalidLabels(_)) => Status::BadRequest,
+        GeneratorError::NotFound(_) => Status::NotFound,
+        GeneratorError::Duplicate(_) | GeneratorError::Store(StoreError::Duplicate(_)) => {
+            Status::Conflict
+        }
+        GeneratorError::Store(StoreError::Sealed) => Status::ServiceUnavailable,
+        GeneratorError::Store(_) => {
+            error!("Secret generation failed: {:?}", e);
+            return Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Secret generation failed.".to_string(),
+            });
+        }
+    };
+
+    warn!("Secret generation rejected: {}", e);
+    Json(ErrorResponse {
+        status: status.code,
+        message: e.to_string(),
+    })
+}
+
+pub fn generator_routes() -> Vec<rocket::Route> {
+    routes![
+        generate,
+        create_policy,
+        list_policies,
+        get_policy,
+        delete_policy
+    ]
+}
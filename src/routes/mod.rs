@@ -1,2 +1,8 @@
+pub mod admin;
+pub mod backup;
+pub mod generator;
+pub mod seal;
+pub mod signing;
+pub mod transit;
 pub mod users;
 pub mod vault;
@@ -0,0 +1,107 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::seal::{SealError, SealRepository};
+use crate::request_guards::AdminGuard;
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, warn};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/*-------------------------------------------------
+ Whether the vault is initialized and sealed. Not
+ authenticated so operators can check it at any time.
+--------------------------------------------------*/
+#[get("/sys/seal-status")]
+pub async fn seal_status(
+    repo: &State<Arc<SealRepository>>,
+) -> Result<Json<SealStatus>, Json<ErrorResponse>> {
+    repo.status().await.map(Json).map_err(failure)
+}
+
+/*---------------------------------------------------------------
+ Generate the master key and return its key shares. This is the
+ only time the shares are ever shown; the vault stays sealed
+ until `threshold` of them are submitted to the unseal endpoint.
+----------------------------------------------------------------*/
+#[post("/sys/init", format = "json", data = "<request>")]
+pub async fn init(
+    repo: &State<Arc<SealRepository>>,
+    request: Json<InitRequest>,
+) -> Result<Json<InitResponse>, Json<ErrorResponse>> {
+    match repo.initialize(request.shares, request.threshold).await {
+        Ok(shares) => Ok(Json(InitResponse {
+            status: Status::Ok.code,
+            threshold: request.threshold,
+            shares,
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ Submit one key share
+------------------------------*/
+#[post("/sys/unseal", format = "json", data = "<request>")]
+pub async fn unseal(
+    repo: &State<Arc<SealRepository>>,
+    request: Json<UnsealRequest>,
+) -> Result<Json<SealStatus>, Json<ErrorResponse>> {
+    repo.unseal(&request.share).await.map(Json).map_err(failure)
+}
+
+/*-----------------------------------------
+ Seal the vault, dropping the master key
+------------------------------------------*/
+#[post("/sys/seal")]
+pub async fn seal(
+    repo: &State<Arc<SealRepository>>,
+    _admin: AdminGuard,
+) -> Result<Json<SealResponse>, Json<ErrorResponse>> {
+    match repo.seal() {
+        Ok(()) => Ok(Json(SealResponse {
+            status: Status::Ok.code,
+            message: "Vault sealed.".to_string(),
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+fn failure(e: SealError) -> Json<ErrorResponse> {
+    let status = match e {
+        SealError::ManagedByEnvironment
+        | SealError::AlreadyInitialized
+        | SealError::KeyMismatch(_) => Status::Conflict,
+        SealError::NotInitialized
+        | SealError::InvalidShare(_)
+        | SealError::InvalidParameters(_)
+        | SealError::WrongKey => Status::BadRequest,
+        SealError::Keyring(_) | SealError::Store(_) => {
+            error!("Seal operation failed: {:?}", e);
+            return Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Seal operation failed.".to_string(),
+            });
+        }
+    };
+
+    warn!("Seal operation rejected: {}", e);
+    Json(ErrorResponse {
+        status: status.code,
+        message: e.to_string(),
+    })
+}
+
+pub fn seal_routes() -> Vec<rocket::Route> {
+    routes![seal_status, init, unseal, seal]
+}
@@ -0,0 +1,248 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::signing::{SigningError, SigningRepository};
+use crate::request_guards::{TokenGuard, Unsealed};
+use crate::storage::StoreError;
+use crate::utils::pagination::{next_page_token, page_request};
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, info, warn};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/*---------------------------------------------------------------
+ Create a signing key. The caller owns it and may allow other
+ subjects to sign and verify with it.
+----------------------------------------------------------------*/
+#[post("/signing/keys", format = "json", data = "<request>")]
+pub async fn create_key(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    request: Json<CreateSigningKey>,
+    token: TokenGuard,
+) -> Result<Json<SigningKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let request = request.into_inner();
+    match repo
+        .create_key(
+            &request.name,
+            request.algorithm,
+            &subject,
+            request.allowed_subjects,
+        )
+        .await
+    {
+        Ok(key) => {
+            info!("Signing key {} created.", key.name);
+            Ok(Json(key.into()))
+        }
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------------------
+ List the signing keys the caller may use
+------------------------------------------*/
+#[get("/signing/keys")]
+pub async fn list_keys(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    token: TokenGuard,
+) -> Result<Json<Vec<SigningKeyInfo>>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.list_keys(&subject).await {
+        Ok(keys) => Ok(Json(keys.into_iter().map(SigningKeyInfo::from).collect())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ Describe a signing key
+------------------------------*/
+#[get("/signing/keys/<name>")]
+pub async fn get_key(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    token: TokenGuard,
+) -> Result<Json<SigningKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.get_key(name, &subject).await {
+        Ok(key) => Ok(Json(key.into())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Add a version to a signing key. New signatures use it, older
+ ones keep verifying. Only the owner may rotate.
+----------------------------------------------------------------*/
+#[post("/signing/keys/<name>/rotate")]
+pub async fn rotate_key(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    token: TokenGuard,
+) -> Result<Json<SigningKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.rotate_key(name, &subject).await {
+        Ok(key) => Ok(Json(key.into())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Sign a base64 payload with the latest version of a key
+----------------------------------------------------------------*/
+#[post("/signing/sign/<name>", format = "json", data = "<request>")]
+pub async fn sign(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    request: Json<SignRequest>,
+    token: TokenGuard,
+) -> Result<Json<SignResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.sign(name, &subject, &request.input).await {
+        Ok((signature, key_version)) => Ok(Json(SignResponse {
+            status: Status::Ok.code,
+            signature,
+            key_version,
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Verify a signature of a base64 payload. An invalid signature is
+ a successful request answering `valid: false`.
+----------------------------------------------------------------*/
+#[post("/signing/verify/<name>", format = "json", data = "<request>")]
+pub async fn verify(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    request: Json<VerifyRequest>,
+    token: TokenGuard,
+) -> Result<Json<VerifyResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo
+        .verify(name, &subject, &request.input, &request.signature)
+        .await
+    {
+        Ok(valid) => Ok(Json(VerifyResponse {
+            status: Status::Ok.code,
+            valid,
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Export the public keys of every version of an Ed25519 key
+----------------------------------------------------------------*/
+#[get("/signing/keys/<name>/public")]
+pub async fn public_keys(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    token: TokenGuard,
+) -> Result<Json<PublicKeys>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.public_keys(name, &subject).await {
+        Ok(keys) => Ok(Json(keys)),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ List the uses of a signing key, oldest first. Only the owner
+ may read them.
+----------------------------------------------------------------*/
+#[get("/signing/keys/<name>/audit?<query..>")]
+pub async fn audit_events(
+    repo: &State<Arc<SigningRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    query: PageQuery,
+    token: TokenGuard,
+) -> Result<Json<AuditPage>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let Some(page) = page_request(query.limit, query.page_token.as_deref()) else {
+        return Err(Json(ErrorResponse {
+            status: Status::BadRequest.code,
+            message: "Invalid page_token provided.".to_string(),
+        }));
+    };
+
+    match repo.audit_events(name, &subject, page).await {
+        Ok(events) => Ok(Json(AuditPage {
+            next_page_token: next_page_token(page, events.items.len(), events.total),
+            total: events.total,
+            events: events.items,
+        })),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+fn subject(token: &TokenGuard) -> Result<String, Json<ErrorResponse>> {
+    match token
+        .0
+        .get_claim("sub")
+        .and_then(|subject| subject.as_str())
+    {
+        Some(subject) => Ok(subject.to_string()),
+        None => Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })),
+    }
+}
+
+fn failure(e: SigningError) -> Json<ErrorResponse> {
+    let status = match e {
+        SigningError::InvalidName(_)
+        | SigningError::InvalidInput(_)
+        | SigningError::NoPublicKey(_) => Status::BadRequest,
+        SigningError::Forbidden(_) => Status::Forbidden,
+        SigningError::NotFound(_) => Status::NotFound,
+        SigningError::Duplicate(_) | SigningError::Conflict(_) => Status::Conflict,
+        SigningError::Store(StoreError::Sealed) => Status::ServiceUnavailable,
+        SigningError::Store(_) => {
+            error!("Signing operation failed: {:?}", e);
+            return Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Signing operation failed.".to_string(),
+            });
+        }
+    };
+
+    warn!("Signing operation rejected: {}", e);
+    Json(ErrorResponse {
+        status: status.code,
+        message: e.to_string(),
+    })
+}
+
+pub fn signing_routes() -> Vec<rocket::Route> {
+    routes![
+        create_key,
+        list_keys,
+        get_key,
+        rotate_key,
+        sign,
+        verify,
+        public_keys,
+        audit_events
+    ]
+}
@@ -0,0 +1,268 @@
+/*-------------
+Custom modules
+--------------*/
+use crate::models::*;
+use crate::repositories::transit::{TransitError, TransitRepository};
+use crate::request_guards::{TokenGuard, Unsealed};
+use crate::storage::StoreError;
+
+/*-------------
+3rd party modules
+--------------*/
+use log::{error, info, warn};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::{get, post, routes, State};
+
+/*-------------
+stdlib modules
+--------------*/
+use std::sync::Arc;
+
+/// Most items accepted in one batch request
+const MAX_BATCH_SIZE: usize = 1000;
+
+/*---------------------------------------------------------------
+ Create a transit key. The caller owns it and may allow other
+ subjects to encrypt and decrypt with it.
+----------------------------------------------------------------*/
+#[post("/transit/keys", format = "json", data = "<request>")]
+pub async fn create_key(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    request: Json<CreateTransitKey>,
+    token: TokenGuard,
+) -> Result<Json<TransitKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let request = request.into_inner();
+    match repo
+        .create_key(&request.name, &subject, request.allowed_subjects)
+        .await
+    {
+        Ok(key) => {
+            info!("Transit key {} created.", key.name);
+            Ok(Json(key.into()))
+        }
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------------------
+ List the transit keys the caller may use
+------------------------------------------*/
+#[get("/transit/keys")]
+pub async fn list_keys(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    token: TokenGuard,
+) -> Result<Json<Vec<TransitKeyInfo>>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.list_keys(&subject).await {
+        Ok(keys) => Ok(Json(keys.into_iter().map(TransitKeyInfo::from).collect())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*-----------------------------
+ Describe a transit key
+------------------------------*/
+#[get("/transit/keys/<name>")]
+pub async fn get_key(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    token: TokenGuard,
+) -> Result<Json<TransitKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.get_key(name, &subject).await {
+        Ok(key) => Ok(Json(key.into())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Add a version to a transit key. New ciphertexts use it, older
+ ones stay readable until rewrapped. Only the owner may rotate.
+----------------------------------------------------------------*/
+#[post("/transit/keys/<name>/rotate")]
+pub async fn rotate_key(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    token: TokenGuard,
+) -> Result<Json<TransitKeyInfo>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    match repo.rotate_key(name, &subject).await {
+        Ok(key) => Ok(Json(key.into())),
+        Err(e) => Err(failure(e)),
+    }
+}
+
+/*---------------------------------------------------------------
+ Encrypt base64 plaintexts with the latest version of a key
+----------------------------------------------------------------*/
+#[post("/transit/encrypt/<name>", format = "json", data = "<request>")]
+pub async fn encrypt(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    request: Json<TransitRequest>,
+    token: TokenGuard,
+) -> Result<Json<TransitResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let items = items(&request)?;
+    respond(
+        repo.encrypt(name, &subject, items).await,
+        is_batch(&request),
+    )
+}
+
+/*-----------------------------------------------
+ Decrypt ciphertexts, returning base64 plaintexts
+------------------------------------------------*/
+#[post("/transit/decrypt/<name>", format = "json", data = "<request>")]
+pub async fn decrypt(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    request: Json<TransitRequest>,
+    token: TokenGuard,
+) -> Result<Json<TransitResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let items = items(&request)?;
+    respond(
+        repo.decrypt(name, &subject, items).await,
+        is_batch(&request),
+    )
+}
+
+/*---------------------------------------------------------------
+ Move ciphertexts onto the latest version of a key without
+ revealing their plaintext
+----------------------------------------------------------------*/
+#[post("/transit/rewrap/<name>", format = "json", data = "<request>")]
+pub async fn rewrap(
+    repo: &State<Arc<TransitRepository>>,
+    _unsealed: Unsealed,
+    name: &str,
+    request: Json<TransitRequest>,
+    token: TokenGuard,
+) -> Result<Json<TransitResponse>, Json<ErrorResponse>> {
+    let subject = subject(&token)?;
+    let items = items(&request)?;
+    respond(repo.rewrap(name, &subject, items).await, is_batch(&request))
+}
+
+fn subject(token: &TokenGuard) -> Result<String, Json<ErrorResponse>> {
+    match token
+        .0
+        .get_claim("sub")
+        .and_then(|subject| subject.as_str())
+    {
+        Some(subject) => Ok(subject.to_string()),
+        None => Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })),
+    }
+}
+
+fn is_batch(request: &TransitRequest) -> bool {
+    !request.batch.is_empty()
+}
+
+/// The single item of the request, or its batch
+fn items(request: &TransitRequest) -> Result<&[TransitItem], Json<ErrorResponse>> {
+    if !is_batch(request) {
+        return Ok(std::slice::from_ref(&request.item));
+    }
+
+    let item = &request.item;
+    if item.plaintext.is_some() || item.ciphertext.is_some() || item.context.is_some() {
+        return Err(bad_request(
+            "Send either a single item or a batch, not both.".to_string(),
+        ));
+    }
+    if request.batch.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(format!(
+            "A batch holds at most {} items.",
+            MAX_BATCH_SIZE
+        )));
+    }
+    Ok(&request.batch)
+}
+
+fn bad_request(message: String) -> Json<ErrorResponse> {
+    error!("Invalid transit request: {}", message);
+    Json(ErrorResponse {
+        status: Status::BadRequest.code,
+        message,
+    })
+}
+
+/// A failing single item fails the request, failing batch items are reported in place
+fn respond(
+    results: Result<Vec<Result<TransitResult, TransitError>>, TransitError>,
+    batch: bool,
+) -> Result<Json<TransitResponse>, Json<ErrorResponse>> {
+    let mut results = results.map_err(failure)?;
+
+    if batch {
+        let results = results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|e| TransitResult {
+                    error: Some(e.to_string()),
+                    ..TransitResult::default()
+                })
+            })
+            .collect();
+        return Ok(Json(TransitResponse {
+            status: Status::Ok.code,
+            result: TransitResult::default(),
+            batch_results: Some(results),
+        }));
+    }
+
+    match results.pop() {
+        Some(Ok(result)) => Ok(Json(TransitResponse {
+            status: Status::Ok.code,
+            result,
+            batch_results: None,
+        })),
+        Some(Err(e)) => Err(failure(e)),
+        None => Err(failure(TransitError::InvalidInput(
+            "nothing to process".to_string(),
+        ))),
+    }
+}
+
+fn failure(e: TransitError) -> Json<ErrorResponse> {
+    let status = match e {
+        TransitError::InvalidName(_)
+        | TransitError::InvalidInput(_)
+        | TransitError::UnknownVersion(_)
+        | TransitError::Decrypt => Status::BadRequest,
+        TransitError::Forbidden(_) => Status::Forbidden,
+        TransitError::NotFound(_) => Status::NotFound,
+        TransitError::Duplicate(_) | TransitError::Conflict(_) => Status::Conflict,
+        TransitError::Store(StoreError::Sealed) => Status::ServiceUnavailable,
+        TransitError::Store(_) => {
+            error!("Transit operation failed: {:?}", e);
+            return Json(ErrorResponse {
+                status: Status::InternalServerError.code,
+                message: "Transit operation failed.".to_string(),
+            });
+        }
+    };
+
+    warn!("Transit operation rejected: {}", e);
+    Json(ErrorResponse {
+        status: status.code,
+        message: e.to_string(),
+    })
+}
+
+pub fn transit_routes() -> Vec<rocket::Route> {
+    routes![create_key, list_keys, get_key, rotate_key, encrypt, decrypt, rewrap]
+}
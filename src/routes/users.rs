@@ -2,17 +2,17 @@
 Custom modules
 --------------*/
 use crate::models::{DeleteUserResponse, ErrorResponse, LoginResponse, SetupResponse};
-use crate::models::{User, UserCredentials, UserDocument};
+use crate::models::{PageQuery, User, UserCredentials, UserDocument, UserPage};
 use crate::repositories::key::KeyRepository;
 use crate::repositories::users::UserRepository;
+use crate::utils::pagination::{next_page_token, page_request};
 use crate::utils::{hashing::hash_password, token::authorize_user};
 
 /*-------------
 3rd party modules
 --------------*/
 use rocket::http::Status;
-use rocket::response::status::Custom;
-use rocket::serde::{json::Json, Deserialize};
+use rocket::serde::json::Json;
 use rocket::{delete, get, post, put, routes, State};
 
 /*-------------
@@ -43,15 +43,15 @@ pub async fn setup(
         }
     };
 
-    let user = match repo.create_user(&credentials.email, &hashed_password).await {
-        Ok(user) => user,
+    match repo.create_user(&credentials.email, &hashed_password).await {
+        Ok(_) => {}
         Err(_) => {
             return Err(Json(ErrorResponse {
                 status: Status::InternalServerError.code,
                 message: "Failed to setup account".to_string(),
             }));
         }
-    };
+    }
 
     Ok(Json(SetupResponse {
         status: Status::Ok.code,
@@ -104,11 +104,19 @@ pub async fn login(
     }))
 }
 
-#[get("/users")]
+#[get("/users?<query..>")]
 pub async fn list_users(
     repo: &State<Arc<UserRepository>>,
-) -> Result<Json<Vec<UserDocument>>, Json<ErrorResponse>> {
-    let users = match repo.list_users().await {
+    query: PageQuery,
+) -> Result<Json<UserPage>, Json<ErrorResponse>> {
+    let Some(page) = page_request(query.limit, query.page_token.as_deref()) else {
+        return Err(Json(ErrorResponse {
+            status: Status::BadRequest.code,
+            message: "Invalid page_token provided.".to_string(),
+        }));
+    };
+
+    let users = match repo.list_users(page).await {
         Ok(users) => users,
         Err(_) => {
             return Err(Json(ErrorResponse {
@@ -118,7 +126,11 @@ pub async fn list_users(
         }
     };
 
-    Ok(Json(users))
+    Ok(Json(UserPage {
+        next_page_token: next_page_token(page, users.items.len(), users.total),
+        total: users.total,
+        users: users.items,
+    }))
 }
 
 #[get("/users/<id>")]
@@ -204,18 +216,14 @@ pub async fn delete_user(
             status: Status::Ok.code,
             message: "User deleted successfully".to_string(),
         })),
-        Ok(None) => {
-            return Err(Json(ErrorResponse {
-                status: Status::NotFound.code,
-                message: "User not found".to_string(),
-            }))
-        }
-        Err(_) => {
-            return Err(Json(ErrorResponse {
-                status: Status::InternalServerError.code,
-                message: "Internal server error".to_string(),
-            }))
-        }
+        Ok(None) => Err(Json(ErrorResponse {
+            status: Status::NotFound.code,
+            message: "User not found".to_string(),
+        })),
+        Err(_) => Err(Json(ErrorResponse {
+            status: Status::InternalServerError.code,
+            message: "Internal server error".to_string(),
+        })),
     }
 }
 
@@ -3,16 +3,19 @@ Custom modules
 --------------*/
 use crate::models::*;
 use crate::repositories::vault::VaultRepository;
-use crate::request_guards::TokenGuard;
+use crate::request_guards::{TokenGuard, Unsealed};
+use crate::storage::{SecretQuery, SortField, SortOrder, StoreError};
+use crate::utils::labels::LabelSelector;
+use crate::utils::pagination::{next_page_token, page_request};
 
-use jsonwebtoken::TokenData;
 /*-------------
 3rd party modules
 --------------*/
-use log::{debug, error, info, warn};
-use rocket::http::Status;
-use rocket::response::status::Custom;
-use rocket::serde::{json::Json, Deserialize};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use rocket::http::uri::{fmt::Path, Segments};
+use rocket::http::{ContentType, Status};
+use rocket::serde::json::Json;
 use rocket::{delete, get, post, put, routes, State};
 
 /*-------------
@@ -26,13 +29,35 @@ use std::sync::Arc;
 #[post("/create/vault/entry", data = "<secret>")]
 pub async fn create_secret(
     repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
     secret: Json<Secret>,
     claims: TokenGuard,
 ) -> Result<Json<CreateSecretResponse>, Json<ErrorResponse>> {
+    let expires_at = match expiry(secret.ttl, secret.expires_at.as_deref()) {
+        Ok(expires_at) => expires_at,
+        Err(message) => {
+            error!("Invalid request: {}", message);
+            return Err(Json(ErrorResponse {
+                status: Status::BadRequest.code,
+                message,
+            }));
+        }
+    };
+
     if let Some(created_by) = claims.0.get_claim("sub") {
         if let Some(created_by) = created_by.as_str() {
             match repo
-                .create_secret(&secret.key, &secret.value, created_by)
+                .create_secret(
+                    &secret.key,
+                    &secret.value,
+                    created_by,
+                    SecretOptions {
+                        encoding: secret.encoding,
+                        expires_at,
+                        description: secret.description.clone(),
+                        labels: secret.labels.clone(),
+                    },
+                )
                 .await
             {
                 Ok(_) => {
@@ -42,6 +67,34 @@ pub async fn create_secret(
                         message: "Vault entry created successfully".to_string(),
                     }))
                 }
+                Err(StoreError::Duplicate(_)) => {
+                    error!("Vault entry already exists with key: {}", secret.key);
+                    Err(Json(ErrorResponse {
+                        status: Status::Conflict.code,
+                        message: "A vault entry with this key already exists".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidPath(key)) => {
+                    error!("Invalid vault entry key: {}", key);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid key provided.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidLabels(message)) => {
+                    error!("Invalid labels for vault entry {}: {}", secret.key, message);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    }))
+                }
+                Err(StoreError::InvalidValue(message)) => {
+                    error!("Invalid value for vault entry {}: {}", secret.key, message);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    }))
+                }
                 Err(e) => {
                     error!("Failed to create vault entry: {:?}", e);
                     Err(Json(ErrorResponse {
@@ -64,20 +117,42 @@ pub async fn create_secret(
     }
 }
 
-/*--------------------------
- Retrieve all vault entries
----------------------------*/
-#[get("/retrieve/vault/entries")]
+/*----------------------------------------------------
+ Retrieve a page of vault entries, filtered and sorted
+-----------------------------------------------------*/
+#[get("/retrieve/vault/entries?<query..>")]
 pub async fn list_entries(
     repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    query: ListEntriesQuery,
     token: TokenGuard,
-) -> Result<Json<Vec<VaultDocument>>, Json<ErrorResponse>> {
+) -> Result<Json<SecretPage>, Json<ErrorResponse>> {
+    let query = match secret_query(&query) {
+        Ok(query) => query,
+        Err(message) => {
+            error!("Invalid request: {}", message);
+            return Err(Json(ErrorResponse {
+                status: Status::BadRequest.code,
+                message,
+            }));
+        }
+    };
+
     if let Some(subject) = token.0.get_claim("sub") {
         if let Some(subject) = subject.as_str() {
-            match repo.list_secrets(subject).await {
-                Ok(entries) => {
-                    info!("Successfully retrieved {} vault entries.", entries.len());
-                    Ok(Json(entries)) // Always return an array, even if empty
+            match repo.query_secrets(subject, &query).await {
+                Ok(page) => {
+                    info!(
+                        "Successfully retrieved {} of {} vault entries.",
+                        page.items.len(),
+                        page.total
+                    );
+                    let next_page_token = next_page_token(query.page, page.items.len(), page.total);
+                    Ok(Json(SecretPage {
+                        entries: page.items, // Always an array, even if empty
+                        total: page.total,
+                        next_page_token,
+                    }))
                 }
                 Err(_) => {
                     error!("Failed to retrieve vault entries.");
@@ -107,6 +182,7 @@ pub async fn list_entries(
 #[get("/retrieve/vault/entries/<id>")]
 pub async fn get_entry(
     repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
     id: &str,
     token: TokenGuard,
 ) -> Result<Json<String>, Json<ErrorResponse>> {
@@ -119,7 +195,7 @@ pub async fn get_entry(
     }
     if let Some(subject) = token.0.get_claim("sub") {
         if let Some(subject) = subject.as_str() {
-            match repo.get_secret_by_id(&id, subject).await {
+            match repo.get_secret_by_id(id, subject).await {
                 Ok(Some(entry)) => {
                     info!("Successfully retrieved vault entry with ID: {}", id);
                     Ok(Json(entry))
@@ -131,6 +207,13 @@ pub async fn get_entry(
                         message: "Vault entry not found.".to_string(),
                     }))
                 }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    }))
+                }
                 Err(e) => {
                     error!(
                         "Failed to retrieve vault entry by ID: {}. Error: {:?}",
@@ -156,12 +239,344 @@ pub async fn get_entry(
     }
 }
 
+/*----------------------------------------------------------------
+ Retrieve the raw bytes of a vault entry as application/octet-stream,
+ for binary values such as keystores or certificates
+-----------------------------------------------------------------*/
+#[get("/retrieve/vault/entries/<id>/raw")]
+pub async fn get_entry_raw(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    token: TokenGuard,
+) -> Result<(ContentType, Vec<u8>), Json<ErrorResponse>> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.get_secret_bytes_by_id(id, subject).await {
+                Ok(Some(value)) => {
+                    info!("Successfully retrieved raw vault entry with ID: {}", id);
+                    Ok((ContentType::Binary, value))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found with ID: {}", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to retrieve raw vault entry by ID: {}. Error: {:?}",
+                        id, e
+                    );
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to retrieve vault entry.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*----------------------------------------------------------------
+ Update a vault entry. The caller must send the version it last
+ read; if the entry changed in the meantime a 409 is returned.
+-----------------------------------------------------------------*/
+#[put("/update/vault/entry/<id>", data = "<secret>")]
+pub async fn update_entry(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    secret: Json<SecretUpdate>,
+    token: TokenGuard,
+) -> Result<Json<UpdateSecretResponse>, UpdateSecretError> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo
+                .update_secret(
+                    id,
+                    &secret.value,
+                    secret.version,
+                    subject,
+                    secret.metadata.clone(),
+                )
+                .await
+            {
+                Ok(Some(entry)) => {
+                    info!("Updated vault entry {} to version {}", id, entry.version);
+                    Ok(Json(UpdateSecretResponse {
+                        status: Status::Ok.code,
+                        message: "Vault entry updated successfully.".to_string(),
+                        version: entry.version,
+                    }))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found for update with ID: {}", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    })))
+                }
+                Err(StoreError::Conflict) => {
+                    warn!(
+                        "Rejected stale update of vault entry {} (version {})",
+                        id, secret.version
+                    );
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::InvalidLabels(message)) => {
+                    error!("Invalid labels for vault entry {}: {}", id, message);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    })))
+                }
+                Err(StoreError::InvalidValue(message)) => {
+                    error!("Invalid value for vault entry {}: {}", id, message);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    })))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    })))
+                }
+                Err(e) => {
+                    error!("Failed to update vault entry: {}. Error: {:?}", id, e);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to update vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
+/*------------------------------------
+ List the versions of a vault entry
+-------------------------------------*/
+#[get("/retrieve/vault/entries/<id>/versions")]
+pub async fn list_entry_versions(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    token: TokenGuard,
+) -> Result<Json<Vec<SecretVersionSummary>>, Json<ErrorResponse>> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.list_secret_versions(id, subject).await {
+                Ok(Some(versions)) => {
+                    info!(
+                        "Successfully retrieved {} versions of vault entry with ID: {}",
+                        versions.len(),
+                        id
+                    );
+                    Ok(Json(versions))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found with ID: {}", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to retrieve versions of vault entry: {}. Error: {:?}",
+                        id, e
+                    );
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to retrieve vault entry versions.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*--------------------------------------
+ Retrieve a specific version of an entry
+---------------------------------------*/
+#[get("/retrieve/vault/entries/<id>/versions/<version>")]
+pub async fn get_entry_version(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    version: u32,
+    token: TokenGuard,
+) -> Result<Json<String>, Json<ErrorResponse>> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.get_secret_version(id, subject, version).await {
+                Ok(Some(entry)) => {
+                    info!(
+                        "Successfully retrieved version {} of vault entry with ID: {}",
+                        version, id
+                    );
+                    Ok(Json(entry))
+                }
+                Ok(None) => {
+                    error!("Version {} of vault entry {} not found", version, id);
+                    Err(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry version not found.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to retrieve version {} of vault entry: {}. Error: {:?}",
+                        version, id, e
+                    );
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to retrieve vault entry version.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*-----------------------------------------
+ Roll a vault entry back to an earlier version
+------------------------------------------*/
+#[post("/rollback/vault/entry/<id>/<version>")]
+pub async fn rollback_entry(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    version: u32,
+    token: TokenGuard,
+) -> Result<Json<RollbackSecretResponse>, UpdateSecretError> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.rollback_secret(id, subject, version).await {
+                Ok(Some(entry)) => {
+                    info!(
+                        "Rolled back vault entry {} to version {} as version {}",
+                        id, version, entry.version
+                    );
+                    Ok(Json(RollbackSecretResponse {
+                        status: Status::Ok.code,
+                        message: "Vault entry rolled back successfully.".to_string(),
+                        version: entry.version,
+                    }))
+                }
+                Ok(None) => {
+                    error!("Version {} of vault entry {} not found", version, id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry version not found.".to_string(),
+                    })))
+                }
+                Err(StoreError::Conflict) => {
+                    error!("Vault entry {} was modified during rollback", id);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    })))
+                }
+                Err(e) => {
+                    error!("Failed to roll back vault entry: {}. Error: {:?}", id, e);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to roll back vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
 /*---------------------------------
  Retrieve a vault entry by author
 ----------------------------------*/
 #[get("/retrieve/vault/entry/<created_by>")]
 pub async fn get_entry_by_author(
     repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
     created_by: &str,
 ) -> Result<Json<Vec<VaultDocument>>, Json<ErrorResponse>> {
     if created_by.trim().is_empty() {
@@ -207,42 +622,498 @@ pub async fn get_entry_by_author(
 #[delete("/delete/<id>")]
 pub async fn delete_entry(
     repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
     id: &str,
     token: TokenGuard,
-) -> Result<Json<DeleteSecretResponse>, Json<ErrorResponse>> {
+) -> Result<Json<DeleteSecretResponse>, UpdateSecretError> {
     if id.trim().is_empty() || id.contains(char::is_whitespace) {
         error!("Invalid request: Provided ID '{}' is invalid.", id);
-        return Err(Json(ErrorResponse {
+        return Err(UpdateSecretError::Failed(Json(ErrorResponse {
             status: Status::BadRequest.code,
             message: "Invalid ID provided for deletion.".to_string(),
-        }));
+        })));
     }
 
     if let Some(subject) = token.0.get_claim("sub") {
         if let Some(subject) = subject.as_str() {
-            match repo.delete_secret(&id, subject).await {
+            match repo.delete_secret(id, subject).await {
                 Ok(Some(_)) => {
                     info!("Successfully deleted vault entry with ID: {}", id);
                     Ok(Json(DeleteSecretResponse {
                         status: Status::Ok.code,
-                        message: "Vault entry deleted successfully.".to_string(),
+                        message: "Vault entry moved to trash.".to_string(),
                     }))
                 }
                 Ok(None) => {
                     error!("Vault entry not found for deletion with ID: {}", id);
-                    Err(Json(ErrorResponse {
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
                         status: Status::NotFound.code,
                         message: "Vault entry not found.".to_string(),
-                    }))
+                    })))
+                }
+                Err(StoreError::Conflict) => {
+                    error!("Vault entry with ID: {} changed while being deleted", id);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    })))
                 }
                 Err(e) => {
                     error!(
                         "Failed to delete vault entry with ID: {}. Error: {:?}",
                         id, e
                     );
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to delete vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
+/*-------------------------------
+ List the vault entries in trash
+--------------------------------*/
+#[get("/retrieve/vault/trash")]
+pub async fn list_trash(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    token: TokenGuard,
+) -> Result<Json<Vec<TrashedSecret>>, Json<ErrorResponse>> {
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.list_trash(subject).await {
+                Ok(trash) => {
+                    info!("Found {} vault entries in trash", trash.len());
+                    Ok(Json(trash))
+                }
+                Err(e) => {
+                    error!("Failed to list vault trash: {:?}", e);
                     Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to list vault trash.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*-----------------------------------
+ Restore a vault entry from the trash
+------------------------------------*/
+#[post("/restore/vault/entry/<id>")]
+pub async fn restore_entry(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    token: TokenGuard,
+) -> Result<Json<RestoreSecretResponse>, UpdateSecretError> {
+    if id.trim().is_empty() || id.contains(char::is_whitespace) {
+        error!("Invalid request: Provided ID '{}' is invalid.", id);
+        return Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::BadRequest.code,
+            message: "Invalid ID provided.".to_string(),
+        })));
+    }
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.restore_secret(id, subject).await {
+                Ok(Some(secret)) => {
+                    info!("Restored vault entry with ID: {} from trash", id);
+                    Ok(Json(RestoreSecretResponse {
+                        status: Status::Ok.code,
+                        message: format!("Vault entry '{}' restored.", secret.key),
+                    }))
+                }
+                Ok(None) => {
+                    warn!("Vault entry not found in trash with ID: {}", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found in trash.".to_string(),
+                    })))
+                }
+                Err(StoreError::Duplicate(_)) => {
+                    error!("Cannot restore vault entry {}: its key is in use", id);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::Conflict) => {
+                    error!("Vault entry with ID: {} changed while being restored", id);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    })))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to restore vault entry with ID: {}. Error: {:?}",
+                        id, e
+                    );
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to restore vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
+/*---------------------------------------------
+ Permanently purge a vault entry from the trash
+----------------------------------------------*/
+#[delete("/purge/vault/entry/<id>")]
+pub async fn purge_entry(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    id: &str,
+    token: TokenGuard,
+) -> Result<Json<DeleteSecretResponse>, Json<ErrorResponse>> {
+    if id.trim().is_empty() || id.contains(char::is_whitespace) {
+        error!("Invalid request: Provided ID '{}' is invalid.", id);
+        return Err(Json(ErrorResponse {
+            status: Status::BadRequest.code,
+            message: "Invalid ID provided.".to_string(),
+        }));
+    }
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.purge_secret(id, subject).await {
+                Ok(Some(_)) => {
+                    info!("Permanently purged vault entry with ID: {}", id);
+                    Ok(Json(DeleteSecretResponse {
+                        status: Status::Ok.code,
+                        message: "Vault entry purged permanently.".to_string(),
+                    }))
+                }
+                Ok(None) => {
+                    warn!("Vault entry not found in trash with ID: {}", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found in trash.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidId(_)) => {
+                    error!("Invalid request: Provided ID '{}' is invalid.", id);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid ID provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to purge vault entry with ID: {}. Error: {:?}",
+                        id, e
+                    );
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to purge vault entry.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*-----------------------------------
+ Retrieve a vault entry by its path
+------------------------------------*/
+#[get("/retrieve/vault/path/<path..>")]
+pub async fn get_entry_by_path(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    path: Segments<'_, Path>,
+    token: TokenGuard,
+) -> Result<Json<String>, Json<ErrorResponse>> {
+    let path = join_segments(path);
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.get_secret_by_path(&path, subject).await {
+                Ok(Some(entry)) => {
+                    info!("Successfully retrieved vault entry at path: {}", path);
+                    Ok(Json(entry))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found at path: {}", path);
+                    Err(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    }))
+                }
+                Err(StoreError::InvalidPath(_)) => {
+                    error!("Invalid request: Provided path '{}' is invalid.", path);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid path provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to retrieve vault entry at path: {}. Error: {:?}",
+                        path, e
+                    );
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to retrieve vault entry.".to_string(),
+                    }))
+                }
+            }
+        } else {
+            Err(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            }))
+        }
+    } else {
+        Err(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        }))
+    }
+}
+
+/*----------------------------------------------------------------
+ Create or update a vault entry by its path. Updates must carry
+ the last version the caller has seen, stale writes return a 409.
+-----------------------------------------------------------------*/
+#[put("/update/vault/path/<path..>", data = "<secret>")]
+pub async fn put_entry_by_path(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    path: Segments<'_, Path>,
+    secret: Json<PathSecret>,
+    token: TokenGuard,
+) -> Result<Json<UpdateSecretResponse>, UpdateSecretError> {
+    let path = join_segments(path);
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo
+                .put_secret_by_path(
+                    &path,
+                    &secret.value,
+                    secret.version,
+                    subject,
+                    secret.metadata.clone(),
+                )
+                .await
+            {
+                Ok(Some(entry)) => {
+                    info!("Wrote vault entry {} at version {}", path, entry.version);
+                    Ok(Json(UpdateSecretResponse {
+                        status: Status::Ok.code,
+                        message: "Vault entry saved successfully.".to_string(),
+                        version: entry.version,
+                    }))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found for update at path: {}", path);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    })))
+                }
+                Err(StoreError::Conflict) | Err(StoreError::Duplicate(_)) => {
+                    warn!("Rejected conflicting write to vault entry {}", path);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(StoreError::InvalidPath(_)) => {
+                    error!("Invalid request: Provided path '{}' is invalid.", path);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid path provided.".to_string(),
+                    })))
+                }
+                Err(StoreError::InvalidLabels(message)) => {
+                    error!("Invalid labels for vault entry {}: {}", path, message);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    })))
+                }
+                Err(StoreError::InvalidValue(message)) => {
+                    error!("Invalid value for vault entry {}: {}", path, message);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message,
+                    })))
+                }
+                Err(e) => {
+                    error!("Failed to write vault entry: {}. Error: {:?}", path, e);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to save vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
+/*---------------------------------
+ Delete a vault entry by its path
+----------------------------------*/
+#[delete("/delete/vault/path/<path..>")]
+pub async fn delete_entry_by_path(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    path: Segments<'_, Path>,
+    token: TokenGuard,
+) -> Result<Json<DeleteSecretResponse>, UpdateSecretError> {
+    let path = join_segments(path);
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.delete_secret_by_path(&path, subject).await {
+                Ok(Some(_)) => {
+                    info!("Successfully deleted vault entry at path: {}", path);
+                    Ok(Json(DeleteSecretResponse {
+                        status: Status::Ok.code,
+                        message: "Vault entry moved to trash.".to_string(),
+                    }))
+                }
+                Ok(None) => {
+                    error!("Vault entry not found for deletion at path: {}", path);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::NotFound.code,
+                        message: "Vault entry not found.".to_string(),
+                    })))
+                }
+                Err(StoreError::InvalidPath(_)) => {
+                    error!("Invalid request: Provided path '{}' is invalid.", path);
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid path provided for deletion.".to_string(),
+                    })))
+                }
+                Err(StoreError::Conflict) => {
+                    error!("Vault entry at path: {} changed while being deleted", path);
+                    Err(UpdateSecretError::Conflict(Status::Conflict))
+                }
+                Err(e) => {
+                    error!(
+                        "Failed to delete vault entry at path: {}. Error: {:?}",
+                        path, e
+                    );
+                    Err(UpdateSecretError::Failed(Json(ErrorResponse {
                         status: Status::InternalServerError.code,
                         message: "Failed to delete vault entry.".to_string(),
+                    })))
+                }
+            }
+        } else {
+            Err(UpdateSecretError::Failed(Json(ErrorResponse {
+                status: Status::Unauthorized.code,
+                message: "Insufficient Permissions".to_string(),
+            })))
+        }
+    } else {
+        Err(UpdateSecretError::Failed(Json(ErrorResponse {
+            status: Status::Unauthorized.code,
+            message: "Insufficient Permissions".to_string(),
+        })))
+    }
+}
+
+/*------------------------------------------------
+ List the folders and keys directly below a path
+-------------------------------------------------*/
+#[get("/list/vault/path/<prefix..>")]
+pub async fn list_path(
+    repo: &State<Arc<VaultRepository>>,
+    _unsealed: Unsealed,
+    prefix: Segments<'_, Path>,
+    token: TokenGuard,
+) -> Result<Json<PathListing>, Json<ErrorResponse>> {
+    let prefix = join_segments(prefix);
+
+    if let Some(subject) = token.0.get_claim("sub") {
+        if let Some(subject) = subject.as_str() {
+            match repo.list_path(&prefix, subject).await {
+                Ok(listing) => {
+                    info!(
+                        "Listed {} folders and {} keys under '{}'",
+                        listing.folders.len(),
+                        listing.keys.len(),
+                        listing.prefix
+                    );
+                    Ok(Json(listing))
+                }
+                Err(StoreError::InvalidPath(_)) => {
+                    error!("Invalid request: Provided prefix '{}' is invalid.", prefix);
+                    Err(Json(ErrorResponse {
+                        status: Status::BadRequest.code,
+                        message: "Invalid path provided.".to_string(),
+                    }))
+                }
+                Err(e) => {
+                    error!("Failed to list vault path: {}. Error: {:?}", prefix, e);
+                    Err(Json(ErrorResponse {
+                        status: Status::InternalServerError.code,
+                        message: "Failed to list vault entries.".to_string(),
                     }))
                 }
             }
@@ -260,12 +1131,90 @@ pub async fn delete_entry(
     }
 }
 
+/// Resolves the optional `ttl` (seconds) or `expires_at` (RFC 3339) of a new entry
+pub(crate) fn expiry(
+    ttl: Option<u64>,
+    expires_at: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, String> {
+    let expires_at = match (ttl, expires_at) {
+        (Some(_), Some(_)) => return Err("Provide either ttl or expires_at, not both.".to_string()),
+        (Some(ttl), None) => i64::try_from(ttl)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .and_then(|ttl| Utc::now().checked_add_signed(ttl))
+            .ok_or_else(|| "Invalid ttl provided.".to_string())?,
+        (None, Some(expires_at)) => DateTime::parse_from_rfc3339(expires_at)
+            .map_err(|_| "Invalid expires_at provided.".to_string())?
+            .with_timezone(&Utc),
+        (None, None) => return Ok(None),
+    };
+
+    if expires_at <= Utc::now() {
+        return Err("Expiry must be in the future.".to_string());
+    }
+
+    Ok(Some(expires_at))
+}
+
+/// Validates the query string of a vault entry listing
+fn secret_query(params: &ListEntriesQuery) -> Result<SecretQuery, String> {
+    let page = page_request(params.limit, params.page_token.as_deref())
+        .ok_or_else(|| "Invalid page_token provided.".to_string())?;
+
+    let sort = match params.sort.as_deref() {
+        None | Some("createdAt") => SortField::CreatedAt,
+        Some("key") => SortField::Key,
+        Some(_) => return Err("sort must be either key or createdAt.".to_string()),
+    };
+    let order = match params.order.as_deref() {
+        None | Some("asc") => SortOrder::Asc,
+        Some("desc") => SortOrder::Desc,
+        Some(_) => return Err("order must be either asc or desc.".to_string()),
+    };
+
+    let timestamp = |value: Option<&str>, name: &str| {
+        value
+            .map(|value| {
+                DateTime::parse_from_rfc3339(value)
+                    .map(|value| value.with_timezone(&Utc))
+                    .map_err(|_| format!("Invalid {} provided.", name))
+            })
+            .transpose()
+    };
+
+    Ok(SecretQuery {
+        prefix: params.prefix.clone().filter(|prefix| !prefix.is_empty()),
+        created_after: timestamp(params.created_after.as_deref(), "created_after")?,
+        created_before: timestamp(params.created_before.as_deref(), "created_before")?,
+        labels: LabelSelector::parse(params.labels.as_deref().unwrap_or_default())?,
+        sort,
+        order,
+        page,
+    })
+}
+
+fn join_segments(segments: Segments<'_, Path>) -> String {
+    segments.collect::<Vec<_>>().join("/")
+}
+
 pub fn vault_routes() -> Vec<rocket::Route> {
     routes![
         create_secret,
         list_entries,
         get_entry,
+        get_entry_raw,
+        update_entry,
+        list_entry_versions,
+        get_entry_version,
+        rollback_entry,
         get_entry_by_author,
-        delete_entry
+        delete_entry,
+        list_trash,
+        restore_entry,
+        purge_entry,
+        get_entry_by_path,
+        put_entry_by_path,
+        delete_entry_by_path,
+        list_path
     ]
 }
@@ -0,0 +1,667 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use rocket::async_trait;
+use tokio::sync::RwLock;
+
+/*-------------
+Custom modules
+-------------*/
+use crate::models::{
+    AuditEventDocument, GeneratorPolicyDocument, KeyCheckDocument, KeyPairDocument,
+    SealConfigDocument, SigningKeyDocument, TransitKeyDocument, UserDocument, VaultDocument,
+};
+use crate::storage::{
+    AuditStore, BackupStore, GeneratorStore, KeyStore, Page, PageRequest, SecretQuery,
+    SigningStore, Snapshot, SortField, SortOrder, StoreError, StoreResult, TransitStore, UserStore,
+    VaultStore,
+};
+use crate::utils::keyring::DEFAULT_KEY_ID;
+
+/*---------------------------------------------------------------------------
+    In-memory storage.
+
+    Nothing is persisted: every record is lost when the process exits.
+    Intended for local development and for running the service (and its
+    tests) without a database. Records are kept in insertion order to
+    match the natural ordering returned by MongoDB.
+---------------------------------------------------------------------------*/
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    users: RwLock<Vec<UserDocument>>,
+    vault: RwLock<Vec<VaultDocument>>,
+    keys: RwLock<Vec<KeyPairDocument>>,
+    seal: RwLock<Option<SealConfigDocument>>,
+    key_checks: RwLock<Vec<KeyCheckDocument>>,
+    transit: RwLock<Vec<TransitKeyDocument>>,
+    signing: RwLock<Vec<SigningKeyDocument>>,
+    audit: RwLock<Vec<AuditEventDocument>>,
+    generator: RwLock<Vec<GeneratorPolicyDocument>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Whether another entry of the same owner is live at the key of `secret`
+fn key_taken(vault: &[VaultDocument], secret: &VaultDocument) -> bool {
+    !secret.is_trashed()
+        && vault.iter().any(|stored| {
+            stored.id != secret.id
+                && !stored.is_trashed()
+                && stored.created_by == secret.created_by
+                && stored.key == secret.key
+        })
+}
+
+#[async_trait]
+impl VaultStore for MemoryStore {
+    async fn insert_secret(&self, secret: &VaultDocument) -> StoreResult<()> {
+        let mut vault = self.vault.write().await;
+        if key_taken(&vault, secret) {
+            return Err(StoreError::Duplicate("key"));
+        }
+
+        vault.push(secret.clone());
+        Ok(())
+    }
+
+    async fn get_secret(&self, id: &ObjectId, owner: &str) -> StoreResult<Option<VaultDocument>> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .find(|secret| &secret.id == id && secret.created_by == owner)
+            .cloned())
+    }
+
+    async fn get_secret_by_key(
+        &self,
+        owner: &str,
+        key: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .find(|secret| {
+                secret.key == key && secret.created_by == owner && secret.deleted_at.is_none()
+            })
+            .cloned())
+    }
+
+    async fn list_secrets(&self, owner: &str) -> StoreResult<Vec<VaultDocument>> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .filter(|secret| secret.created_by == owner)
+            .cloned()
+            .collect())
+    }
+
+    async fn query_secrets(
+        &self,
+        owner: &str,
+        query: &SecretQuery,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Page<VaultDocument>> {
+        let vault = self.vault.read().await;
+        let mut secrets: Vec<&VaultDocument> = vault
+            .iter()
+            .filter(|secret| {
+                secret.created_by == owner
+                    && !secret.is_expired(now)
+                    && !secret.is_trashed()
+                    && query.matches(secret)
+            })
+            .collect();
+
+        secrets.sort_by(|a, b| {
+            let ordering = match query.sort {
+                SortField::Key => a.key.cmp(&b.key),
+                SortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            }
+            .then_with(|| a.id.cmp(&b.id));
+            match query.order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
+
+        Ok(Page {
+            total: secrets.len() as u64,
+            items: window(secrets.into_iter().cloned(), query.page),
+        })
+    }
+
+    async fn list_secrets_with_prefix(
+        &self,
+        owner: &str,
+        prefix: &str,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .filter(|secret| secret.created_by == owner && secret.key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    async fn replace_secret(
+        &self,
+        secret: &VaultDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let mut vault = self.vault.write().await;
+        if key_taken(&vault, secret) {
+            return Err(StoreError::Duplicate("key"));
+        }
+        let stored = vault.iter_mut().find(|stored| {
+            stored.id == secret.id
+                && stored.created_by == secret.created_by
+                && stored.version == expected_version
+        });
+
+        match stored {
+            Some(stored) => {
+                *stored = secret.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete_secret(
+        &self,
+        id: &ObjectId,
+        owner: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let mut vault = self.vault.write().await;
+        let position = vault
+            .iter()
+            .position(|secret| &secret.id == id && secret.created_by == owner);
+        Ok(position.map(|index| vault.remove(index)))
+    }
+
+    async fn delete_expired_secrets(&self, now: DateTime<Utc>) -> StoreResult<Vec<VaultDocument>> {
+        let mut vault = self.vault.write().await;
+        let (expired, kept) = std::mem::take(&mut *vault)
+            .into_iter()
+            .partition(|secret| secret.is_expired(now));
+        *vault = kept;
+        Ok(expired)
+    }
+
+    async fn list_secrets_to_reencrypt(
+        &self,
+        key_id: &str,
+        after: Option<&ObjectId>,
+        limit: u64,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let vault = self.vault.read().await;
+        let mut secrets: Vec<VaultDocument> = vault
+            .iter()
+            .filter(|secret| needs_reencryption(secret, key_id))
+            .filter(|secret| after.is_none_or(|after| &secret.id > after))
+            .cloned()
+            .collect();
+        secrets.sort_by_key(|secret| secret.id);
+        secrets.truncate(usize::try_from(limit).unwrap_or(usize::MAX));
+        Ok(secrets)
+    }
+
+    async fn count_secrets_to_reencrypt(&self, key_id: &str) -> StoreResult<u64> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .filter(|secret| needs_reencryption(secret, key_id))
+            .count() as u64)
+    }
+
+    async fn find_secret_with_key_id(&self, key_id: &str) -> StoreResult<Option<VaultDocument>> {
+        let vault = self.vault.read().await;
+        Ok(vault
+            .iter()
+            .find(|secret| secret.key_id.as_deref().unwrap_or(DEFAULT_KEY_ID) == key_id)
+            .cloned())
+    }
+
+    async fn delete_trashed_secrets(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let mut vault = self.vault.write().await;
+        let (trashed, kept) = std::mem::take(&mut *vault).into_iter().partition(|secret| {
+            secret
+                .deleted_at
+                .is_some_and(|deleted_at| deleted_at <= deleted_before)
+        });
+        *vault = kept;
+        Ok(trashed)
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn insert_user(&self, user: &UserDocument) -> StoreResult<()> {
+        let mut users = self.users.write().await;
+        if users.iter().any(|existing| existing.email == user.email) {
+            return Err(StoreError::Duplicate("email"));
+        }
+
+        users.push(user.clone());
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        let users = self.users.read().await;
+        Ok(users.iter().find(|user| &user.id == id).cloned())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> StoreResult<Option<UserDocument>> {
+        let users = self.users.read().await;
+        Ok(users.iter().find(|user| user.email == email).cloned())
+    }
+
+    async fn update_user(
+        &self,
+        id: &ObjectId,
+        email: Option<&str>,
+        password: Option<&str>,
+    ) -> StoreResult<Option<UserDocument>> {
+        if email.is_none() && password.is_none() {
+            return Ok(None);
+        }
+
+        let mut users = self.users.write().await;
+        if let Some(email) = email {
+            if users
+                .iter()
+                .any(|user| user.email == email && &user.id != id)
+            {
+                return Err(StoreError::Duplicate("email"));
+            }
+        }
+        let Some(user) = users.iter_mut().find(|user| &user.id == id) else {
+            return Ok(None);
+        };
+
+        let previous = user.clone();
+        if let Some(email) = email {
+            user.email = email.to_string();
+        }
+        if let Some(password) = password {
+            user.password = password.to_string();
+        }
+
+        Ok(Some(previous))
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        let mut users = self.users.write().await;
+        let position = users.iter().position(|user| &user.id == id);
+        Ok(position.map(|index| users.remove(index)))
+    }
+
+    async fn list_users(&self, page: PageRequest) -> StoreResult<Page<UserDocument>> {
+        let users = self.users.read().await;
+        Ok(Page {
+            total: users.len() as u64,
+            items: window(users.iter().cloned(), page),
+        })
+    }
+}
+
+#[async_trait]
+impl KeyStore for MemoryStore {
+    async fn get_key_pair(&self) -> StoreResult<Option<KeyPairDocument>> {
+        Ok(self.keys.read().await.first().cloned())
+    }
+
+    async fn insert_key_pair(&self, key_pair: &KeyPairDocument) -> StoreResult<()> {
+        self.keys.write().await.push(key_pair.clone());
+        Ok(())
+    }
+
+    async fn get_seal_config(&self) -> StoreResult<Option<SealConfigDocument>> {
+        Ok(self.seal.read().await.clone())
+    }
+
+    async fn insert_seal_config(&self, config: &SealConfigDocument) -> StoreResult<()> {
+        let mut seal = self.seal.write().await;
+        if seal.is_some() {
+            return Err(StoreError::Duplicate("seal configuration"));
+        }
+        *seal = Some(config.clone());
+        Ok(())
+    }
+
+    async fn list_key_checks(&self) -> StoreResult<Vec<KeyCheckDocument>> {
+        Ok(self.key_checks.read().await.clone())
+    }
+
+    async fn insert_key_check(&self, check: &KeyCheckDocument) -> StoreResult<()> {
+        let mut key_checks = self.key_checks.write().await;
+        if key_checks
+            .iter()
+            .any(|existing| existing.key_id == check.key_id)
+        {
+            return Err(StoreError::Duplicate("key id"));
+        }
+        key_checks.push(check.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TransitStore for MemoryStore {
+    async fn insert_transit_key(&self, key: &TransitKeyDocument) -> StoreResult<()> {
+        let mut transit = self.transit.write().await;
+        if transit.iter().any(|existing| existing.name == key.name) {
+            return Err(StoreError::Duplicate("name"));
+        }
+        transit.push(key.clone());
+        Ok(())
+    }
+
+    async fn get_transit_key(&self, name: &str) -> StoreResult<Option<TransitKeyDocument>> {
+        let transit = self.transit.read().await;
+        Ok(transit.iter().find(|key| key.name == name).cloned())
+    }
+
+    async fn list_transit_keys(&self) -> StoreResult<Vec<TransitKeyDocument>> {
+        let mut keys = self.transit.read().await.clone();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+
+    async fn replace_transit_key(
+        &self,
+        key: &TransitKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let mut transit = self.transit.write().await;
+        match transit.iter_mut().find(|existing| {
+            existing.name == key.name && existing.latest_version == expected_version
+        }) {
+            Some(existing) => {
+                *existing = key.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl SigningStore for MemoryStore {
+    async fn insert_signing_key(&self, key: &SigningKeyDocument) -> StoreResult<()> {
+        let mut signing = self.signing.write().await;
+        if signing.iter().any(|existing| existing.name == key.name) {
+            return Err(StoreError::Duplicate("name"));
+        }
+        signing.push(key.clone());
+        Ok(())
+    }
+
+    async fn get_signing_key(&self, name: &str) -> StoreResult<Option<SigningKeyDocument>> {
+        let signing = self.signing.read().await;
+        Ok(signing.iter().find(|key| key.name == name).cloned())
+    }
+
+    async fn list_signing_keys(&self) -> StoreResult<Vec<SigningKeyDocument>> {
+        let mut keys = self.signing.read().await.clone();
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+
+    async fn replace_signing_key(
+        &self,
+        key: &SigningKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let mut signing = self.signing.write().await;
+        match signing.iter_mut().find(|existing| {
+            existing.name == key.name && existing.latest_version == expected_version
+        }) {
+            Some(existing) => {
+                *existing = key.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[async_trait]
+impl AuditStore for MemoryStore {
+    async fn insert_audit_event(&self, event: &AuditEventDocument) -> StoreResult<()> {
+        self.audit.write().await.push(event.clone());
+        Ok(())
+    }
+
+    async fn list_audit_events(
+        &self,
+        resource: &str,
+        page: PageRequest,
+    ) -> StoreResult<Page<AuditEventDocument>> {
+        let audit = self.audit.read().await;
+        let events: Vec<&AuditEventDocument> = audit
+            .iter()
+            .filter(|event| event.resource == resource)
+            .collect();
+        Ok(Page {
+            total: events.len() as u64,
+            items: window(events.into_iter().cloned(), page),
+        })
+    }
+}
+
+#[async_trait]
+impl GeneratorStore for MemoryStore {
+    async fn insert_generator_policy(&self, policy: &GeneratorPolicyDocument) -> StoreResult<()> {
+        let mut generator = self.generator.write().await;
+        if generator
+            .iter()
+            .any(|existing| existing.name == policy.name)
+        {
+            return Err(StoreError::Duplicate("name"));
+        }
+        generator.push(policy.clone());
+        Ok(())
+    }
+
+    async fn get_generator_policy(
+        &self,
+        name: &str,
+    ) -> StoreResult<Option<GeneratorPolicyDocument>> {
+        let generator = self.generator.read().await;
+        Ok(generator.iter().find(|policy| policy.name == name).cloned())
+    }
+
+    async fn list_generator_policies(&self) -> StoreResult<Vec<GeneratorPolicyDocument>> {
+        let mut policies = self.generator.read().await.clone();
+        policies.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(policies)
+    }
+
+    async fn delete_generator_policy(&self, name: &str) -> StoreResult<bool> {
+        let mut generator = self.generator.write().await;
+        let before = generator.len();
+        generator.retain(|policy| policy.name != name);
+        Ok(generator.len() < before)
+    }
+}
+
+#[async_trait]
+impl BackupStore for MemoryStore {
+    async fn export(&self) -> StoreResult<Snapshot> {
+        Ok(Snapshot {
+            users: self.users.read().await.clone(),
+            vault: self.vault.read().await.clone(),
+            keys: self.keys.read().await.clone(),
+            seal: self.seal.read().await.clone(),
+            key_checks: self.key_checks.read().await.clone(),
+            transit: self.transit.read().await.clone(),
+            signing: self.signing.read().await.clone(),
+            audit: self.audit.read().await.clone(),
+            generator: self.generator.read().await.clone(),
+        })
+    }
+
+    async fn import(&self, snapshot: &Snapshot, replace: bool) -> StoreResult<()> {
+        // Every lock is held for the whole import, so readers see all of it or none
+        let mut users = self.users.write().await;
+        let mut vault = self.vault.write().await;
+        let mut keys = self.keys.write().await;
+        let mut seal = self.seal.write().await;
+        let mut key_checks = self.key_checks.write().await;
+        let mut transit = self.transit.write().await;
+        let mut signing = self.signing.write().await;
+        let mut audit = self.audit.write().await;
+        let mut generator = self.generator.write().await;
+
+        if replace {
+            users.clear();
+            vault.clear();
+            keys.clear();
+            *seal = None;
+            key_checks.clear();
+            transit.clear();
+            signing.clear();
+            audit.clear();
+            generator.clear();
+        }
+
+        users.extend(snapshot.users.iter().cloned());
+        vault.extend(snapshot.vault.iter().cloned());
+        keys.extend(snapshot.keys.iter().cloned());
+        if let Some(config) = &snapshot.seal {
+            *seal = Some(config.clone());
+        }
+        key_checks.extend(snapshot.key_checks.iter().cloned());
+        transit.extend(snapshot.transit.iter().cloned());
+        signing.extend(snapshot.signing.iter().cloned());
+        audit.extend(snapshot.audit.iter().cloned());
+        generator.extend(snapshot.generator.iter().cloned());
+        Ok(())
+    }
+}
+
+fn needs_reencryption(secret: &VaultDocument, key_id: &str) -> bool {
+    secret.key_id.as_deref().unwrap_or(DEFAULT_KEY_ID) != key_id || !secret.bound
+}
+
+fn window<T>(records: impl Iterator<Item = T>, page: PageRequest) -> Vec<T> {
+    records
+        .skip(usize::try_from(page.offset).unwrap_or(usize::MAX))
+        .take(usize::try_from(page.limit).unwrap_or(usize::MAX))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ValueEncoding;
+
+    fn secret(key: &str, owner: &str) -> VaultDocument {
+        VaultDocument {
+            id: ObjectId::new(),
+            key: key.to_string(),
+            value: "value".to_string(),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: owner.to_string(),
+            created_at: Utc::now(),
+            description: None,
+            labels: Default::default(),
+            expires_at: None,
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn secrets_are_scoped_to_their_owner() {
+        let store = MemoryStore::new();
+        let mine = secret("mine", "alice@example.com");
+        let theirs = secret("theirs", "bob@example.com");
+        store.insert_secret(&mine).await.unwrap();
+        store.insert_secret(&theirs).await.unwrap();
+
+        let listed = store.list_secrets("alice@example.com").await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].key, "mine");
+
+        assert!(store
+            .get_secret(&theirs.id, "alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .delete_secret(&theirs.id, "alice@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .delete_secret(&mine.id, "alice@example.com")
+            .await
+            .unwrap()
+            .is_some());
+        assert!(store
+            .list_secrets("alice@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn duplicate_emails_are_rejected() {
+        let store = MemoryStore::new();
+        let user = UserDocument {
+            id: ObjectId::new(),
+            email: "alice@example.com".to_string(),
+            password: "hash".to_string(),
+            created_at: Utc::now(),
+        };
+        store.insert_user(&user).await.unwrap();
+
+        let duplicate = UserDocument {
+            id: ObjectId::new(),
+            ..user.clone()
+        };
+        assert!(matches!(
+            store.insert_user(&duplicate).await,
+            Err(StoreError::Duplicate("email"))
+        ));
+
+        let previous = store
+            .update_user(&user.id, Some("carol@example.com"), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(previous.email, "alice@example.com");
+        assert!(store
+            .get_user_by_email("carol@example.com")
+            .await
+            .unwrap()
+            .is_some());
+
+        let other = UserDocument {
+            id: ObjectId::new(),
+            email: "dave@example.com".to_string(),
+            ..user.clone()
+        };
+        store.insert_user(&other).await.unwrap();
+        assert!(matches!(
+            store
+                .update_user(&other.id, Some("carol@example.com"), None)
+                .await,
+            Err(StoreError::Duplicate("email"))
+        ));
+    }
+}
@@ -0,0 +1,311 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use rocket::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/*-------------
+Custom modules
+-------------*/
+use crate::models::{
+    AuditEventDocument, GeneratorPolicyDocument, KeyCheckDocument, KeyPairDocument,
+    SealConfigDocument, SigningKeyDocument, TransitKeyDocument, UserDocument, VaultDocument,
+};
+use crate::utils::labels::LabelSelector;
+
+pub mod memory;
+pub mod mongo;
+pub mod sqlite;
+
+/*---------------------------------------------------------------------------
+    Storage backends.
+
+    The repositories handle hashing, encryption and key generation, and
+    delegate persistence to one of the stores defined below. Each backend
+    (MongoDB, SQLite, in-memory) implements every store trait so that the
+    repositories and routes never depend on a specific database driver.
+---------------------------------------------------------------------------*/
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("database error: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("error encoding or decoding a stored document: {0}")]
+    Serialization(String),
+    #[error("a record with this {0} already exists")]
+    Duplicate(&'static str),
+    #[error("the record was modified concurrently")]
+    Conflict,
+    #[error("invalid secret path: {0}")]
+    InvalidPath(String),
+    #[error("invalid labels: {0}")]
+    InvalidLabels(String),
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
+    #[error("invalid identifier: {0}")]
+    InvalidId(String),
+    #[error("error encrypting or decrypting a stored value: {0}")]
+    Crypto(String),
+    #[error("the vault is sealed")]
+    Sealed,
+}
+
+pub type StoreResult<T> = Result<T, StoreError>;
+
+/// A window of `limit` records starting at `offset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRequest {
+    pub offset: u64,
+    pub limit: u64,
+}
+
+/// One page of records together with the number of records matching the query
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortField {
+    Key,
+    #[default]
+    CreatedAt,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Filters applied when listing the secrets of an owner. Ties are broken by id.
+#[derive(Debug, Clone)]
+pub struct SecretQuery {
+    pub prefix: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub labels: LabelSelector,
+    pub sort: SortField,
+    pub order: SortOrder,
+    pub page: PageRequest,
+}
+
+impl SecretQuery {
+    /// Whether `secret` passes the prefix, creation date and label filters
+    pub fn matches(&self, secret: &VaultDocument) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| secret.key.starts_with(prefix))
+            && self
+                .created_after
+                .is_none_or(|after| secret.created_at >= after)
+            && self
+                .created_before
+                .is_none_or(|before| secret.created_at < before)
+            && self.labels.matches(&secret.labels)
+    }
+}
+
+/// Parses a hex encoded ObjectId supplied by a caller
+pub fn parse_id(id: &str) -> StoreResult<ObjectId> {
+    ObjectId::parse_str(id).map_err(|_| StoreError::InvalidId(id.to_string()))
+}
+
+#[async_trait]
+pub trait VaultStore: Send + Sync {
+    /// Fails with `StoreError::Duplicate` if the owner has another entry at the
+    /// same key that is not in the trash
+    async fn insert_secret(&self, secret: &VaultDocument) -> StoreResult<()>;
+
+    async fn get_secret(&self, id: &ObjectId, owner: &str) -> StoreResult<Option<VaultDocument>>;
+
+    /// Only entries that are not in the trash are considered
+    async fn get_secret_by_key(&self, owner: &str, key: &str)
+        -> StoreResult<Option<VaultDocument>>;
+
+    async fn list_secrets(&self, owner: &str) -> StoreResult<Vec<VaultDocument>>;
+
+    /// Lists one page of the owner's entries that are neither expired at `now` nor trashed
+    async fn query_secrets(
+        &self,
+        owner: &str,
+        query: &SecretQuery,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Page<VaultDocument>>;
+
+    /// Entries whose key starts with `prefix`, an empty prefix matches every entry
+    async fn list_secrets_with_prefix(
+        &self,
+        owner: &str,
+        prefix: &str,
+    ) -> StoreResult<Vec<VaultDocument>>;
+
+    /// Overwrites the stored entry only if it is still at `expected_version`.
+    /// Returns `false` when the entry is missing or was changed concurrently,
+    /// and fails like `insert_secret` when its key is taken.
+    async fn replace_secret(&self, secret: &VaultDocument, expected_version: u32)
+        -> StoreResult<bool>;
+
+    async fn delete_secret(&self, id: &ObjectId, owner: &str)
+        -> StoreResult<Option<VaultDocument>>;
+
+    /// Removes every entry, across all owners, that expired at or before `now`
+    async fn delete_expired_secrets(&self, now: DateTime<Utc>) -> StoreResult<Vec<VaultDocument>>;
+
+    /// Lists up to `limit` entries of every owner that need re-encrypting: their data key is
+    /// not wrapped with `key_id` or they are not bound. Returned in id order starting after
+    /// `after`. Entries without a key id use `DEFAULT_KEY_ID`.
+    async fn list_secrets_to_reencrypt(
+        &self,
+        key_id: &str,
+        after: Option<&ObjectId>,
+        limit: u64,
+    ) -> StoreResult<Vec<VaultDocument>>;
+
+    async fn count_secrets_to_reencrypt(&self, key_id: &str) -> StoreResult<u64>;
+
+    /// Any one entry encrypted under the master key `key_id`. Entries without a key id
+    /// use `DEFAULT_KEY_ID`.
+    async fn find_secret_with_key_id(&self, key_id: &str) -> StoreResult<Option<VaultDocument>>;
+
+    /// Removes every entry, across all owners, moved to the trash at or before `deleted_before`
+    async fn delete_trashed_secrets(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> StoreResult<Vec<VaultDocument>>;
+}
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Fails with `StoreError::Duplicate` if the email is already registered
+    async fn insert_user(&self, user: &UserDocument) -> StoreResult<()>;
+
+    async fn get_user_by_id(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>>;
+
+    async fn get_user_by_email(&self, email: &str) -> StoreResult<Option<UserDocument>>;
+
+    /// Returns the user as it was before the update was applied
+    async fn update_user(
+        &self,
+        id: &ObjectId,
+        email: Option<&str>,
+        password: Option<&str>,
+    ) -> StoreResult<Option<UserDocument>>;
+
+    async fn delete_user(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>>;
+
+    /// Lists users in creation order
+    async fn list_users(&self, page: PageRequest) -> StoreResult<Page<UserDocument>>;
+}
+
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    async fn get_key_pair(&self) -> StoreResult<Option<KeyPairDocument>>;
+
+    async fn insert_key_pair(&self, key_pair: &KeyPairDocument) -> StoreResult<()>;
+
+    async fn get_seal_config(&self) -> StoreResult<Option<SealConfigDocument>>;
+
+    /// Fails with `StoreError::Duplicate` if the vault was already initialized
+    async fn insert_seal_config(&self, config: &SealConfigDocument) -> StoreResult<()>;
+
+    async fn list_key_checks(&self) -> StoreResult<Vec<KeyCheckDocument>>;
+
+    /// Fails with `StoreError::Duplicate` if the key id already has a key check value
+    async fn insert_key_check(&self, check: &KeyCheckDocument) -> StoreResult<()>;
+}
+
+#[async_trait]
+pub trait TransitStore: Send + Sync {
+    /// Fails with `StoreError::Duplicate` if a transit key with the same name exists
+    async fn insert_transit_key(&self, key: &TransitKeyDocument) -> StoreResult<()>;
+
+    async fn get_transit_key(&self, name: &str) -> StoreResult<Option<TransitKeyDocument>>;
+
+    /// Lists every transit key in name order
+    async fn list_transit_keys(&self) -> StoreResult<Vec<TransitKeyDocument>>;
+
+    /// Overwrites the stored key only if its latest version is still `expected_version`.
+    /// Returns `false` when the key is missing or was rotated concurrently.
+    async fn replace_transit_key(
+        &self,
+        key: &TransitKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool>;
+}
+
+#[async_trait]
+pub trait SigningStore: Send + Sync {
+    /// Fails with `StoreError::Duplicate` if a signing key with the same name exists
+    async fn insert_signing_key(&self, key: &SigningKeyDocument) -> StoreResult<()>;
+
+    async fn get_signing_key(&self, name: &str) -> StoreResult<Option<SigningKeyDocument>>;
+
+    /// Lists every signing key in name order
+    async fn list_signing_keys(&self) -> StoreResult<Vec<SigningKeyDocument>>;
+
+    /// Overwrites the stored key only if its latest version is still `expected_version`.
+    /// Returns `false` when the key is missing or was rotated concurrently.
+    async fn replace_signing_key(
+        &self,
+        key: &SigningKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool>;
+}
+
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn insert_audit_event(&self, event: &AuditEventDocument) -> StoreResult<()>;
+
+    /// Lists the events recorded for `resource`, oldest first
+    async fn list_audit_events(
+        &self,
+        resource: &str,
+        page: PageRequest,
+    ) -> StoreResult<Page<AuditEventDocument>>;
+}
+
+#[async_trait]
+pub trait GeneratorStore: Send + Sync {
+    /// Fails with `StoreError::Duplicate` if a policy with the same name exists
+    async fn insert_generator_policy(&self, policy: &GeneratorPolicyDocument) -> StoreResult<()>;
+
+    async fn get_generator_policy(
+        &self,
+        name: &str,
+    ) -> StoreResult<Option<GeneratorPolicyDocument>>;
+
+    /// Lists every generator policy in name order
+    async fn list_generator_policies(&self) -> StoreResult<Vec<GeneratorPolicyDocument>>;
+
+    /// Returns `false` when no policy has this name
+    async fn delete_generator_policy(&self, name: &str) -> StoreResult<bool>;
+}
+
+/// Every record of every store, as written to and read from a backup
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snapshot {
+    pub users: Vec<UserDocument>,
+    pub vault: Vec<VaultDocument>,
+    pub keys: Vec<KeyPairDocument>,
+    pub seal: Option<SealConfigDocument>,
+    pub key_checks: Vec<KeyCheckDocument>,
+    pub transit: Vec<TransitKeyDocument>,
+    pub signing: Vec<SigningKeyDocument>,
+    pub audit: Vec<AuditEventDocument>,
+    pub generator: Vec<GeneratorPolicyDocument>,
+}
+
+#[async_trait]
+pub trait BackupStore: Send + Sync {
+    /// Reads every record, audit events in the order they were recorded
+    async fn export(&self) -> StoreResult<Snapshot>;
+
+    /// Writes every record of `snapshot`. With `replace` the existing records are removed
+    /// first. Backends supporting transactions apply the whole import or nothing.
+    async fn import(&self, snapshot: &Snapshot, replace: bool) -> StoreResult<()>;
+}
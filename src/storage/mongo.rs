@@ -0,0 +1,806 @@
+use chrono::{DateTime, Utc};
+use futures::stream::TryStreamExt;
+use log::warn;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    error::{ErrorKind, WriteFailure},
+    options::IndexOptions,
+    Client, Collection, IndexModel,
+};
+use rocket::async_trait;
+
+/*-------------
+Custom modules
+-------------*/
+use crate::models::{
+    AuditEventDocument, GeneratorPolicyDocument, KeyCheckDocument, KeyPairDocument,
+    SealConfigDocument, SigningKeyDocument, TransitKeyDocument, UserDocument, VaultDocument,
+};
+use crate::storage::{
+    AuditStore, BackupStore, GeneratorStore, KeyStore, Page, PageRequest, SecretQuery,
+    SigningStore, Snapshot, SortField, SortOrder, StoreError, StoreResult, TransitStore, UserStore,
+    VaultStore,
+};
+use crate::utils::keyring::DEFAULT_KEY_ID;
+use crate::utils::labels::Requirement;
+
+/*---------------------------------------------------------------------------
+    MongoDB backed storage. Each store maps onto one collection of the
+    configured database.
+---------------------------------------------------------------------------*/
+/// Id of the only document of the seal collection
+const SEAL_CONFIG_ID: &str = "config";
+
+#[derive(Debug)]
+pub struct MongoStore {
+    users: Collection<UserDocument>,
+    vault: Collection<VaultDocument>,
+    keys: Collection<KeyPairDocument>,
+    seal: Collection<SealConfigDocument>,
+    key_checks: Collection<KeyCheckDocument>,
+    transit: Collection<TransitKeyDocument>,
+    signing: Collection<SigningKeyDocument>,
+    audit: Collection<AuditEventDocument>,
+    generator: Collection<GeneratorPolicyDocument>,
+}
+
+impl MongoStore {
+    pub fn new(client: &Client, db_name: &str) -> Self {
+        let database = client.database(db_name);
+        Self {
+            users: database.collection::<UserDocument>("users"),
+            vault: database.collection::<VaultDocument>("vault"),
+            keys: database.collection::<KeyPairDocument>("keys"),
+            seal: database.collection::<SealConfigDocument>("seal"),
+            key_checks: database.collection::<KeyCheckDocument>("key_checks"),
+            transit: database.collection::<TransitKeyDocument>("transit"),
+            signing: database.collection::<SigningKeyDocument>("signing"),
+            audit: database.collection::<AuditEventDocument>("audit"),
+            generator: database.collection::<GeneratorPolicyDocument>("generator"),
+        }
+    }
+
+    /// Creates the indexes the stores rely on for uniqueness, existing ones are kept
+    pub async fn create_indexes(&self) -> mongodb::error::Result<()> {
+        let trashed = self.trash_duplicate_live_keys().await?;
+        if trashed > 0 {
+            warn!(
+                "Moved {} vault entries sharing a key with a newer entry to the trash",
+                trashed
+            );
+        }
+
+        // A partial index cannot select documents without `deletedAt`, so the
+        // deletion time is part of the key instead: live entries all index it
+        // as null, trashed ones by the time they were deleted.
+        self.vault
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "created_by": 1, "key": 1, "deletedAt": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("vault_live_key".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.key_checks
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "keyId": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("key_checks_key_id".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.transit
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "name": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("transit_name".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.signing
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "name": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("signing_name".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        self.generator
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "name": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .name("generator_name".to_string())
+                            .unique(true)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Keeps only the most recently written live entry of every owner and key, the others
+    /// are moved to the trash. Deployments created before keys were unique may hold such
+    /// duplicates, which would fail the `vault_live_key` index build.
+    async fn trash_duplicate_live_keys(&self) -> mongodb::error::Result<u64> {
+        let pipeline = vec![
+            doc! { "$match": { "deletedAt": Bson::Null } },
+            doc! { "$addFields": { "writtenAt": { "$ifNull": ["$updatedAt", "$createdAt"] } } },
+            doc! { "$sort": { "writtenAt": -1, "_id": -1 } },
+            doc! { "$group": {
+                "_id": { "created_by": "$created_by", "key": "$key" },
+                "ids": { "$push": "$_id" },
+            } },
+            doc! { "$match": { "ids.1": { "$exists": true } } },
+        ];
+        let groups: Vec<Document> = self.vault.aggregate(pipeline).await?.try_collect().await?;
+
+        let now = Utc::now();
+        let mut trashed = 0;
+        for group in groups {
+            let ids = group
+                .get_array("ids")
+                .map_err(mongodb::error::Error::custom)?;
+            // Each trashed entry gets its own deletion time, the index tells them apart by it
+            for (offset, id) in (1..).zip(ids.iter().skip(1)) {
+                let deleted_at = now - chrono::Duration::milliseconds(offset);
+                let update =
+                    doc! { "$set": { "deletedAt": bson::DateTime::from_chrono(deleted_at) } };
+                trashed += self
+                    .vault
+                    .update_one(doc! { "_id": id, "deletedAt": Bson::Null }, update)
+                    .await?
+                    .modified_count;
+            }
+        }
+        Ok(trashed)
+    }
+}
+
+/// Maps a unique index violation (E11000) to `StoreError::Duplicate`
+fn duplicate(error: mongodb::error::Error, field: &'static str) -> StoreError {
+    let code = match error.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(e)) => Some(e.code),
+        ErrorKind::Command(e) => Some(e.code),
+        _ => None,
+    };
+    match code {
+        Some(11000) => StoreError::Duplicate(field),
+        _ => StoreError::Database(error),
+    }
+}
+
+#[async_trait]
+impl VaultStore for MongoStore {
+    async fn insert_secret(&self, secret: &VaultDocument) -> StoreResult<()> {
+        self.vault
+            .insert_one(secret)
+            .await
+            .map_err(|e| duplicate(e, "key"))?;
+        Ok(())
+    }
+
+    async fn get_secret(&self, id: &ObjectId, owner: &str) -> StoreResult<Option<VaultDocument>> {
+        let filter = doc! { "_id": id, "created_by": owner };
+        Ok(self.vault.find_one(filter).await?)
+    }
+
+    async fn get_secret_by_key(
+        &self,
+        owner: &str,
+        key: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let filter = doc! { "key": key, "created_by": owner, "deletedAt": Bson::Null };
+        Ok(self.vault.find_one(filter).await?)
+    }
+
+    async fn list_secrets(&self, owner: &str) -> StoreResult<Vec<VaultDocument>> {
+        let cursor = self.vault.find(doc! { "created_by": owner }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn query_secrets(
+        &self,
+        owner: &str,
+        query: &SecretQuery,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Page<VaultDocument>> {
+        let mut filter = doc! {
+            "created_by": owner,
+            "deletedAt": Bson::Null,
+            "$or": [
+                { "expiresAt": Bson::Null },
+                { "expiresAt": { "$gt": bson::DateTime::from_chrono(now) } },
+            ],
+        };
+        if let Some(prefix) = &query.prefix {
+            filter.insert(
+                "key",
+                doc! { "$regex": format!("^{}", regex_escape(prefix)) },
+            );
+        }
+        let mut created_at = doc! {};
+        if let Some(after) = query.created_after {
+            created_at.insert("$gte", bson::DateTime::from_chrono(after));
+        }
+        if let Some(before) = query.created_before {
+            created_at.insert("$lt", bson::DateTime::from_chrono(before));
+        }
+        if !created_at.is_empty() {
+            filter.insert("createdAt", created_at);
+        }
+        let labels: Vec<Document> = query
+            .labels
+            .requirements
+            .iter()
+            .map(|requirement| match requirement {
+                Requirement::Equals(name, value) => doc! { format!("labels.{}", name): value },
+                Requirement::NotEquals(name, value) => {
+                    doc! { format!("labels.{}", name): { "$ne": value } }
+                }
+                Requirement::Exists(name) => {
+                    doc! { format!("labels.{}", name): { "$exists": true } }
+                }
+                Requirement::DoesNotExist(name) => {
+                    doc! { format!("labels.{}", name): { "$exists": false } }
+                }
+            })
+            .collect();
+        if !labels.is_empty() {
+            filter.insert("$and", labels);
+        }
+
+        let direction = match query.order {
+            SortOrder::Asc => 1,
+            SortOrder::Desc => -1,
+        };
+        let field = match query.sort {
+            SortField::Key => "key",
+            SortField::CreatedAt => "createdAt",
+        };
+
+        let total = self.vault.count_documents(filter.clone()).await?;
+        let items = self
+            .vault
+            .find(filter)
+            .sort(doc! { field: direction, "_id": direction })
+            .skip(query.page.offset)
+            .limit(page_limit(query.page))
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(Page { items, total })
+    }
+
+    async fn list_secrets_with_prefix(
+        &self,
+        owner: &str,
+        prefix: &str,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let pattern = format!("^{}", regex_escape(prefix));
+        let filter = doc! { "created_by": owner, "key": { "$regex": pattern } };
+        let cursor = self.vault.find(filter).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn replace_secret(
+        &self,
+        secret: &VaultDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let mut filter = doc! { "_id": secret.id, "created_by": &secret.created_by };
+        if expected_version == 1 {
+            // Entries created before versioning have no version field
+            filter.insert("version", doc! { "$in": [1_i64, Bson::Null] });
+        } else {
+            filter.insert("version", expected_version as i64);
+        }
+
+        let result = self
+            .vault
+            .replace_one(filter, secret)
+            .await
+            .map_err(|e| duplicate(e, "key"))?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn delete_secret(
+        &self,
+        id: &ObjectId,
+        owner: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let filter = doc! { "_id": id, "created_by": owner };
+        Ok(self.vault.find_one_and_delete(filter).await?)
+    }
+
+    async fn delete_expired_secrets(&self, now: DateTime<Utc>) -> StoreResult<Vec<VaultDocument>> {
+        let filter = doc! { "expiresAt": { "$lte": bson::DateTime::from_chrono(now) } };
+        let expired: Vec<VaultDocument> =
+            self.vault.find(filter.clone()).await?.try_collect().await?;
+
+        if !expired.is_empty() {
+            let ids: Vec<ObjectId> = expired.iter().map(|secret| secret.id).collect();
+            let mut filter = filter;
+            filter.insert("_id", doc! { "$in": ids });
+            self.vault.delete_many(filter).await?;
+        }
+
+        Ok(expired)
+    }
+
+    async fn list_secrets_to_reencrypt(
+        &self,
+        key_id: &str,
+        after: Option<&ObjectId>,
+        limit: u64,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let mut filter = to_reencrypt(key_id);
+        if let Some(after) = after {
+            filter.insert("_id", doc! { "$gt": after });
+        }
+
+        let cursor = self
+            .vault
+            .find(filter)
+            .sort(doc! { "_id": 1 })
+            .limit(i64::try_from(limit).unwrap_or(i64::MAX))
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn count_secrets_to_reencrypt(&self, key_id: &str) -> StoreResult<u64> {
+        Ok(self.vault.count_documents(to_reencrypt(key_id)).await?)
+    }
+
+    async fn find_secret_with_key_id(&self, key_id: &str) -> StoreResult<Option<VaultDocument>> {
+        let filter = if key_id == DEFAULT_KEY_ID {
+            doc! { "keyId": { "$in": [key_id, Bson::Null] } }
+        } else {
+            doc! { "keyId": key_id }
+        };
+        Ok(self.vault.find_one(filter).await?)
+    }
+
+    async fn delete_trashed_secrets(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let filter = doc! { "deletedAt": { "$lte": bson::DateTime::from_chrono(deleted_before) } };
+        let trashed: Vec<VaultDocument> =
+            self.vault.find(filter.clone()).await?.try_collect().await?;
+
+        if !trashed.is_empty() {
+            let ids: Vec<ObjectId> = trashed.iter().map(|secret| secret.id).collect();
+            let mut filter = filter;
+            filter.insert("_id", doc! { "$in": ids });
+            self.vault.delete_many(filter).await?;
+        }
+
+        Ok(trashed)
+    }
+}
+
+#[async_trait]
+impl UserStore for MongoStore {
+    async fn insert_user(&self, user: &UserDocument) -> StoreResult<()> {
+        if self
+            .users
+            .find_one(doc! { "email": &user.email })
+            .await?
+            .is_some()
+        {
+            return Err(StoreError::Duplicate("email"));
+        }
+
+        self.users.insert_one(user).await?;
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        Ok(self.users.find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> StoreResult<Option<UserDocument>> {
+        Ok(self.users.find_one(doc! { "email": email }).await?)
+    }
+
+    async fn update_user(
+        &self,
+        id: &ObjectId,
+        email: Option<&str>,
+        password: Option<&str>,
+    ) -> StoreResult<Option<UserDocument>> {
+        let mut update_doc = doc! {};
+
+        if let Some(email) = email {
+            update_doc.insert("email", email);
+        }
+        if let Some(password) = password {
+            update_doc.insert("password", password);
+        }
+
+        if update_doc.is_empty() {
+            return Ok(None);
+        }
+
+        let filter = doc! { "_id": id };
+        let update = doc! { "$set": update_doc };
+
+        Ok(self.users.find_one_and_update(filter, update).await?)
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        Ok(self.users.find_one_and_delete(doc! { "_id": id }).await?)
+    }
+
+    async fn list_users(&self, page: PageRequest) -> StoreResult<Page<UserDocument>> {
+        let total = self.users.count_documents(doc! {}).await?;
+        let items = self
+            .users
+            .find(doc! {})
+            .sort(doc! { "createdAt": 1, "_id": 1 })
+            .skip(page.offset)
+            .limit(page_limit(page))
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(Page { items, total })
+    }
+}
+
+#[async_trait]
+impl KeyStore for MongoStore {
+    async fn get_key_pair(&self) -> StoreResult<Option<KeyPairDocument>> {
+        Ok(self.keys.find_one(doc! {}).await?)
+    }
+
+    async fn insert_key_pair(&self, key_pair: &KeyPairDocument) -> StoreResult<()> {
+        self.keys.insert_one(key_pair).await?;
+        Ok(())
+    }
+
+    async fn get_seal_config(&self) -> StoreResult<Option<SealConfigDocument>> {
+        Ok(self.seal.find_one(doc! {}).await?)
+    }
+
+    async fn insert_seal_config(&self, config: &SealConfigDocument) -> StoreResult<()> {
+        // Configurations written before the fixed id was introduced have an ObjectId
+        if self.seal.find_one(doc! {}).await?.is_some() {
+            return Err(StoreError::Duplicate("seal configuration"));
+        }
+        self.seal
+            .clone_with_type::<Document>()
+            .insert_one(seal_config(config)?)
+            .await
+            .map_err(|e| duplicate(e, "seal configuration"))?;
+        Ok(())
+    }
+
+    async fn list_key_checks(&self) -> StoreResult<Vec<KeyCheckDocument>> {
+        Ok(self.key_checks.find(doc! {}).await?.try_collect().await?)
+    }
+
+    async fn insert_key_check(&self, check: &KeyCheckDocument) -> StoreResult<()> {
+        self.key_checks
+            .insert_one(check)
+            .await
+            .map_err(|e| duplicate(e, "key id"))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TransitStore for MongoStore {
+    async fn insert_transit_key(&self, key: &TransitKeyDocument) -> StoreResult<()> {
+        self.transit
+            .insert_one(key)
+            .await
+            .map_err(|e| duplicate(e, "name"))?;
+        Ok(())
+    }
+
+    async fn get_transit_key(&self, name: &str) -> StoreResult<Option<TransitKeyDocument>> {
+        Ok(self.transit.find_one(doc! { "name": name }).await?)
+    }
+
+    async fn list_transit_keys(&self) -> StoreResult<Vec<TransitKeyDocument>> {
+        let cursor = self.transit.find(doc! {}).sort(doc! { "name": 1 }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn replace_transit_key(
+        &self,
+        key: &TransitKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let filter = doc! { "name": &key.name, "latestVersion": expected_version as i64 };
+        let result = self.transit.replace_one(filter, key).await?;
+        Ok(result.matched_count == 1)
+    }
+}
+
+#[async_trait]
+impl SigningStore for MongoStore {
+    async fn insert_signing_key(&self, key: &SigningKeyDocument) -> StoreResult<()> {
+        self.signing
+            .insert_one(key)
+            .await
+            .map_err(|e| duplicate(e, "name"))?;
+        Ok(())
+    }
+
+    async fn get_signing_key(&self, name: &str) -> StoreResult<Option<SigningKeyDocument>> {
+        Ok(self.signing.find_one(doc! { "name": name }).await?)
+    }
+
+    async fn list_signing_keys(&self) -> StoreResult<Vec<SigningKeyDocument>> {
+        let cursor = self.signing.find(doc! {}).sort(doc! { "name": 1 }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn replace_signing_key(
+        &self,
+        key: &SigningKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let filter = doc! { "name": &key.name, "latestVersion": expected_version as i64 };
+        let result = self.signing.replace_one(filter, key).await?;
+        Ok(result.matched_count == 1)
+    }
+}
+
+#[async_trait]
+impl AuditStore for MongoStore {
+    async fn insert_audit_event(&self, event: &AuditEventDocument) -> StoreResult<()> {
+        self.audit.insert_one(event).await?;
+        Ok(())
+    }
+
+    async fn list_audit_events(
+        &self,
+        resource: &str,
+        page: PageRequest,
+    ) -> StoreResult<Page<AuditEventDocument>> {
+        let filter = doc! { "resource": resource };
+        let total = self.audit.count_documents(filter.clone()).await?;
+        let items = self
+            .audit
+            .find(filter)
+            .sort(doc! { "createdAt": 1, "_id": 1 })
+            .skip(page.offset)
+            .limit(page_limit(page))
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(Page { items, total })
+    }
+}
+
+#[async_trait]
+impl GeneratorStore for MongoStore {
+    async fn insert_generator_policy(&self, policy: &GeneratorPolicyDocument) -> StoreResult<()> {
+        self.generator
+            .insert_one(policy)
+            .await
+            .map_err(|e| duplicate(e, "name"))?;
+        Ok(())
+    }
+
+    async fn get_generator_policy(
+        &self,
+        name: &str,
+    ) -> StoreResult<Option<GeneratorPolicyDocument>> {
+        Ok(self.generator.find_one(doc! { "name": name }).await?)
+    }
+
+    async fn list_generator_policies(&self) -> StoreResult<Vec<GeneratorPolicyDocument>> {
+        let cursor = self
+            .generator
+            .find(doc! {})
+            .sort(doc! { "name": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_generator_policy(&self, name: &str) -> StoreResult<bool> {
+        let result = self.generator.delete_one(doc! { "name": name }).await?;
+        Ok(result.deleted_count == 1)
+    }
+}
+
+#[async_trait]
+impl BackupStore for MongoStore {
+    async fn export(&self) -> StoreResult<Snapshot> {
+        Ok(Snapshot {
+            users: self.users.find(doc! {}).await?.try_collect().await?,
+            vault: self.vault.find(doc! {}).await?.try_collect().await?,
+            keys: self.keys.find(doc! {}).await?.try_collect().await?,
+            seal: self.seal.find_one(doc! {}).await?,
+            key_checks: self.key_checks.find(doc! {}).await?.try_collect().await?,
+            transit: self.transit.find(doc! {}).await?.try_collect().await?,
+            signing: self.signing.find(doc! {}).await?.try_collect().await?,
+            audit: self
+                .audit
+                .find(doc! {})
+                .sort(doc! { "createdAt": 1, "_id": 1 })
+                .await?
+                .try_collect()
+                .await?,
+            generator: self.generator.find(doc! {}).await?.try_collect().await?,
+        })
+    }
+
+    /// Not atomic: transactions would require a replica set. An interrupted import is
+    /// completed by importing the same snapshot again with `replace`.
+    async fn import(&self, snapshot: &Snapshot, replace: bool) -> StoreResult<()> {
+        if replace {
+            self.users.delete_many(doc! {}).await?;
+            self.vault.delete_many(doc! {}).await?;
+            self.keys.delete_many(doc! {}).await?;
+            self.seal.delete_many(doc! {}).await?;
+            self.key_checks.delete_many(doc! {}).await?;
+            self.transit.delete_many(doc! {}).await?;
+            self.signing.delete_many(doc! {}).await?;
+            self.audit.delete_many(doc! {}).await?;
+            self.generator.delete_many(doc! {}).await?;
+        }
+
+        insert_all(&self.users, &snapshot.users).await?;
+        insert_all(&self.vault, &snapshot.vault).await?;
+        insert_all(&self.keys, &snapshot.keys).await?;
+        if let Some(config) = &snapshot.seal {
+            self.seal.delete_many(doc! {}).await?;
+            self.seal
+                .clone_with_type::<Document>()
+                .insert_one(seal_config(config)?)
+                .await?;
+        }
+        insert_all(&self.key_checks, &snapshot.key_checks).await?;
+        insert_all(&self.transit, &snapshot.transit).await?;
+        insert_all(&self.signing, &snapshot.signing).await?;
+        insert_all(&self.audit, &snapshot.audit).await?;
+        insert_all(&self.generator, &snapshot.generator).await?;
+        Ok(())
+    }
+}
+
+/// The seal configuration is stored under a fixed id, so that of two concurrent
+/// initializations only one can insert it
+fn seal_config(config: &SealConfigDocument) -> StoreResult<Document> {
+    let mut document =
+        bson::to_document(config).map_err(|e| StoreError::Serialization(e.to_string()))?;
+    document.insert("_id", SEAL_CONFIG_ID);
+    Ok(document)
+}
+
+/// `insert_many` rejects an empty list
+async fn insert_all<T>(collection: &Collection<T>, documents: &[T]) -> StoreResult<()>
+where
+    T: serde::Serialize + Send + Sync,
+{
+    if !documents.is_empty() {
+        collection.insert_many(documents).await?;
+    }
+    Ok(())
+}
+
+/// Entries not on `key_id` or not bound. Entries written before key ids existed are
+/// wrapped with the default key.
+fn to_reencrypt(key_id: &str) -> Document {
+    let not_on_key = if key_id == DEFAULT_KEY_ID {
+        doc! { "keyId": { "$nin": [key_id, Bson::Null] } }
+    } else {
+        doc! { "keyId": { "$ne": key_id } }
+    };
+    doc! { "$or": [not_on_key, { "bound": { "$ne": true } }] }
+}
+
+fn page_limit(page: PageRequest) -> i64 {
+    i64::try_from(page.limit).unwrap_or(i64::MAX)
+}
+
+/// Escapes regex metacharacters so user supplied prefixes are matched literally
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ValueEncoding;
+
+    fn secret(key: &str, created_at: DateTime<Utc>) -> VaultDocument {
+        VaultDocument {
+            id: ObjectId::new(),
+            key: key.to_string(),
+            value: "ciphertext".to_string(),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: "alice@example.com".to_string(),
+            created_at,
+            description: None,
+            labels: Default::default(),
+            expires_at: None,
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a MongoDB server, set ECS_TEST_DATABASE_URL"]
+    async fn duplicate_live_keys_are_trashed_before_indexing() {
+        let url = std::env::var("ECS_TEST_DATABASE_URL").expect("ECS_TEST_DATABASE_URL");
+        let client = Client::with_uri_str(url).await.unwrap();
+        let database = format!("ecs_test_{}", ObjectId::new());
+        let store = MongoStore::new(&client, &database);
+
+        // Written before keys were unique: three live copies of one key
+        let now = Utc::now();
+        let oldest = secret("api_key", now - chrono::Duration::hours(3));
+        let mut updated = secret("api_key", now - chrono::Duration::hours(2));
+        updated.updated_at = Some(now);
+        let newer = secret("api_key", now - chrono::Duration::hours(1));
+        let other = secret("other_key", now);
+        for secret in [&oldest, &updated, &newer, &other] {
+            store.insert_secret(secret).await.unwrap();
+        }
+
+        store.create_indexes().await.unwrap();
+
+        let owner = "alice@example.com";
+        let live = store.get_secret_by_key(owner, "api_key").await.unwrap();
+        assert_eq!(live.map(|secret| secret.id), Some(updated.id));
+        assert!(store
+            .get_secret_by_key(owner, "other_key")
+            .await
+            .unwrap()
+            .is_some());
+        for trashed in [&oldest, &newer] {
+            let trashed = store.get_secret(&trashed.id, owner).await.unwrap().unwrap();
+            assert!(trashed.deleted_at.is_some());
+        }
+        assert!(matches!(
+            store.insert_secret(&secret("api_key", now)).await,
+            Err(StoreError::Duplicate("key"))
+        ));
+
+        client.database(&database).drop().await.unwrap();
+    }
+}
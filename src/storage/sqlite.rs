@@ -0,0 +1,1617 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use rocket::async_trait;
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/*-------------
+Custom modules
+-------------*/
+use crate::models::{
+    AuditEventDocument, GeneratorPolicyDocument, KeyCheckDocument, KeyPairDocument,
+    SealConfigDocument, SigningKeyDocument, TransitKeyDocument, UserDocument, VaultDocument,
+};
+use crate::storage::{
+    AuditStore, BackupStore, GeneratorStore, KeyStore, Page, PageRequest, SecretQuery,
+    SigningStore, Snapshot, SortField, SortOrder, StoreError, StoreResult, TransitStore, UserStore,
+    VaultStore,
+};
+use crate::utils::keyring::DEFAULT_KEY_ID;
+use crate::utils::labels::Requirement;
+
+/*---------------------------------------------------------------------------
+    Embedded SQLite storage for single-node deployments.
+
+    Every record is stored as a BSON encoded document next to the columns
+    used for lookups, so the stored documents are identical to those held
+    by the MongoDB backend. The schema is created on first start, lookup
+    columns added later are backfilled from the stored documents.
+---------------------------------------------------------------------------*/
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS users (
+        id TEXT PRIMARY KEY,
+        email TEXT NOT NULL UNIQUE,
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS vault (
+        id TEXT PRIMARY KEY,
+        created_by TEXT NOT NULL,
+        document BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS vault_created_by ON vault (created_by);
+    CREATE TABLE IF NOT EXISTS keys (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS seal (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS key_checks (
+        key_id TEXT PRIMARY KEY,
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transit (
+        name TEXT PRIMARY KEY,
+        latest_version INTEGER NOT NULL,
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS signing (
+        name TEXT PRIMARY KEY,
+        latest_version INTEGER NOT NULL,
+        document BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS audit (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        resource TEXT NOT NULL,
+        document BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS audit_resource ON audit (resource, seq);
+    CREATE TABLE IF NOT EXISTS generator (
+        name TEXT PRIMARY KEY,
+        document BLOB NOT NULL
+    );
+";
+
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database file at `path`
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Opens a private database that only lives as long as the store
+    pub fn open_in_memory() -> StoreResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> StoreResult<Self> {
+        connection.execute_batch(SCHEMA)?;
+        migrate(&connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Runs `task` with the connection on the blocking thread pool, rusqlite
+    /// calls block and must not hold up the async workers
+    async fn run<T, F>(&self, task: F) -> StoreResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> StoreResult<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            task(&mut connection)
+        })
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+    }
+}
+
+/// Lookup columns of the vault table added after the initial schema
+const VAULT_COLUMNS: &[(&str, &str)] = &[
+    ("key", "TEXT"),
+    ("expires_at", "INTEGER"),
+    ("deleted_at", "INTEGER"),
+    ("created_at", "INTEGER"),
+    ("labels", "TEXT"),
+    ("key_id", "TEXT"),
+    ("bound", "INTEGER"),
+];
+
+/// Adds missing lookup columns and backfills them from the stored documents
+fn migrate(connection: &Connection) -> StoreResult<()> {
+    let columns = connection
+        .prepare("SELECT name FROM pragma_table_info('vault')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    let mut added = false;
+    for (name, kind) in VAULT_COLUMNS {
+        if !columns.iter().any(|column| column == name) {
+            connection.execute_batch(&format!("ALTER TABLE vault ADD COLUMN {} {}", name, kind))?;
+            added = true;
+        }
+    }
+
+    if added {
+        let rows = connection
+            .prepare("SELECT document FROM vault")?
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<Vec<u8>>, _>>()?;
+        for bytes in rows {
+            let secret: VaultDocument = decode(&bytes)?;
+            connection.execute(
+                "UPDATE vault
+                 SET key = ?2, expires_at = ?3, deleted_at = ?4, created_at = ?5, labels = ?6,
+                     key_id = ?7, bound = ?8
+                 WHERE id = ?1",
+                params![
+                    secret.id.to_hex(),
+                    secret.key,
+                    millis(secret.expires_at),
+                    millis(secret.deleted_at),
+                    secret.created_at.timestamp_millis(),
+                    labels(&secret)?,
+                    secret.key_id,
+                    secret.bound
+                ],
+            )?;
+        }
+    }
+
+    connection.execute_batch(
+        "CREATE INDEX IF NOT EXISTS vault_key ON vault (created_by, key);
+         CREATE UNIQUE INDEX IF NOT EXISTS vault_live_key ON vault (created_by, key)
+             WHERE deleted_at IS NULL;
+         CREATE INDEX IF NOT EXISTS vault_expires_at ON vault (expires_at);
+         CREATE INDEX IF NOT EXISTS vault_deleted_at ON vault (deleted_at);
+         CREATE INDEX IF NOT EXISTS vault_created_at ON vault (created_by, created_at);",
+    )?;
+    Ok(())
+}
+
+fn millis(timestamp: Option<DateTime<Utc>>) -> Option<i64> {
+    timestamp.map(|timestamp| timestamp.timestamp_millis())
+}
+
+/// Labels are kept as a JSON object so selectors can be evaluated with `json_extract`
+fn labels(secret: &VaultDocument) -> StoreResult<Option<String>> {
+    if secret.labels.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(&secret.labels)
+        .map(Some)
+        .map_err(|e| StoreError::Serialization(e.to_string()))
+}
+
+fn label_path(name: &str) -> Value {
+    // Label names never contain quotes, see utils::labels
+    Value::Text(format!("$.\"{}\"", name))
+}
+
+fn sql_limit(page: PageRequest) -> i64 {
+    i64::try_from(page.limit).unwrap_or(i64::MAX)
+}
+
+fn sql_offset(page: PageRequest) -> i64 {
+    i64::try_from(page.offset).unwrap_or(i64::MAX)
+}
+
+fn insert_secret(connection: &Connection, secret: &VaultDocument) -> StoreResult<()> {
+    connection
+        .execute(
+            "INSERT INTO vault
+             (id, created_by, key, expires_at, deleted_at, created_at, labels, key_id, bound,
+              document)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                secret.id.to_hex(),
+                secret.created_by,
+                secret.key,
+                millis(secret.expires_at),
+                millis(secret.deleted_at),
+                secret.created_at.timestamp_millis(),
+                labels(secret)?,
+                secret.key_id,
+                secret.bound,
+                encode(secret)?
+            ],
+        )
+        .map_err(|e| duplicate(e, "key"))?;
+    Ok(())
+}
+
+/// Maps a violated unique constraint to `StoreError::Duplicate`
+fn duplicate(error: rusqlite::Error, field: &'static str) -> StoreError {
+    match &error {
+        rusqlite::Error::SqliteFailure(e, _)
+            if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+        {
+            StoreError::Duplicate(field)
+        }
+        _ => error.into(),
+    }
+}
+
+fn select_all<T: DeserializeOwned>(connection: &Connection, sql: &str) -> StoreResult<Vec<T>> {
+    let rows = connection
+        .prepare(sql)?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<Vec<u8>>, _>>()?;
+    decode_all(rows)
+}
+
+fn encode<T: Serialize>(document: &T) -> StoreResult<Vec<u8>> {
+    bson::to_vec(document).map_err(|e| StoreError::Serialization(e.to_string()))
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> StoreResult<T> {
+    bson::from_slice(bytes).map_err(|e| StoreError::Serialization(e.to_string()))
+}
+
+fn decode_all<T: DeserializeOwned>(rows: Vec<Vec<u8>>) -> StoreResult<Vec<T>> {
+    rows.iter().map(|bytes| decode(bytes)).collect()
+}
+
+#[async_trait]
+impl VaultStore for SqliteStore {
+    async fn insert_secret(&self, secret: &VaultDocument) -> StoreResult<()> {
+        let secret = secret.clone();
+        self.run(move |connection| insert_secret(connection, &secret))
+            .await
+    }
+
+    async fn get_secret(&self, id: &ObjectId, owner: &str) -> StoreResult<Option<VaultDocument>> {
+        let (id, owner) = (id.to_hex(), owner.to_string());
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM vault WHERE id = ?1 AND created_by = ?2",
+                    params![id, owner],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn get_secret_by_key(
+        &self,
+        owner: &str,
+        key: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let (owner, key) = (owner.to_string(), key.to_string());
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM vault
+                     WHERE created_by = ?1 AND key = ?2 AND deleted_at IS NULL
+                     ORDER BY rowid",
+                    params![owner, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn list_secrets(&self, owner: &str) -> StoreResult<Vec<VaultDocument>> {
+        let owner = owner.to_string();
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare("SELECT document FROM vault WHERE created_by = ?1 ORDER BY rowid")?;
+            let rows = statement
+                .query_map(params![owner], |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            decode_all(rows)
+        })
+        .await
+    }
+
+    async fn query_secrets(
+        &self,
+        owner: &str,
+        query: &SecretQuery,
+        now: DateTime<Utc>,
+    ) -> StoreResult<Page<VaultDocument>> {
+        let mut clauses = vec![
+            "created_by = ?".to_string(),
+            "deleted_at IS NULL".to_string(),
+            "(expires_at IS NULL OR expires_at > ?)".to_string(),
+        ];
+        let mut values = vec![
+            Value::Text(owner.to_string()),
+            Value::Integer(now.timestamp_millis()),
+        ];
+        if let Some(prefix) = &query.prefix {
+            clauses.push("substr(key, 1, length(?)) = ?".to_string());
+            values.push(Value::Text(prefix.clone()));
+            values.push(Value::Text(prefix.clone()));
+        }
+        if let Some(after) = query.created_after {
+            clauses.push("created_at >= ?".to_string());
+            values.push(Value::Integer(after.timestamp_millis()));
+        }
+        if let Some(before) = query.created_before {
+            clauses.push("created_at < ?".to_string());
+            values.push(Value::Integer(before.timestamp_millis()));
+        }
+        for requirement in &query.labels.requirements {
+            match requirement {
+                Requirement::Equals(name, value) => {
+                    clauses.push("json_extract(labels, ?) = ?".to_string());
+                    values.extend([label_path(name), Value::Text(value.clone())]);
+                }
+                Requirement::NotEquals(name, value) => {
+                    clauses.push("coalesce(json_extract(labels, ?) != ?, 1)".to_string());
+                    values.extend([label_path(name), Value::Text(value.clone())]);
+                }
+                Requirement::Exists(name) => {
+                    clauses.push("json_extract(labels, ?) IS NOT NULL".to_string());
+                    values.push(label_path(name));
+                }
+                Requirement::DoesNotExist(name) => {
+                    clauses.push("json_extract(labels, ?) IS NULL".to_string());
+                    values.push(label_path(name));
+                }
+            }
+        }
+        let filter = clauses.join(" AND ");
+
+        let direction = match query.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        let column = match query.sort {
+            SortField::Key => "key",
+            SortField::CreatedAt => "created_at",
+        };
+        let page = query.page;
+
+        self.run(move |connection| {
+            let total: i64 = connection.query_row(
+                &format!("SELECT count(*) FROM vault WHERE {}", filter),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            values.push(Value::Integer(sql_limit(page)));
+            values.push(Value::Integer(sql_offset(page)));
+            let mut statement = connection.prepare(&format!(
+                "SELECT document FROM vault WHERE {} ORDER BY {} {}, id {} LIMIT ? OFFSET ?",
+                filter, column, direction, direction
+            ))?;
+            let rows = statement
+                .query_map(params_from_iter(values.iter()), |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+
+            Ok(Page {
+                items: decode_all(rows)?,
+                total: total as u64,
+            })
+        })
+        .await
+    }
+
+    async fn list_secrets_with_prefix(
+        &self,
+        owner: &str,
+        prefix: &str,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let (owner, prefix) = (owner.to_string(), prefix.to_string());
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT document FROM vault
+                 WHERE created_by = ?1 AND substr(key, 1, length(?2)) = ?2
+                 ORDER BY rowid",
+            )?;
+            let rows = statement
+                .query_map(params![owner, prefix], |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            decode_all(rows)
+        })
+        .await
+    }
+
+    async fn replace_secret(
+        &self,
+        secret: &VaultDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let secret = secret.clone();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM vault WHERE id = ?1 AND created_by = ?2",
+                    params![secret.id.to_hex(), secret.created_by],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            match row.map(|bytes| decode::<VaultDocument>(&bytes)).transpose()? {
+                Some(stored) if stored.version == expected_version => {
+                    connection
+                        .execute(
+                            "UPDATE vault
+                             SET key = ?2, expires_at = ?3, deleted_at = ?4, labels = ?5,
+                                 key_id = ?6, bound = ?7, document = ?8
+                             WHERE id = ?1",
+                            params![
+                                secret.id.to_hex(),
+                                secret.key,
+                                millis(secret.expires_at),
+                                millis(secret.deleted_at),
+                                labels(&secret)?,
+                                secret.key_id,
+                                secret.bound,
+                                encode(&secret)?
+                            ],
+                        )
+                        .map_err(|e| duplicate(e, "key"))?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        })
+        .await
+    }
+
+    async fn delete_secret(
+        &self,
+        id: &ObjectId,
+        owner: &str,
+    ) -> StoreResult<Option<VaultDocument>> {
+        let (id, owner) = (id.to_hex(), owner.to_string());
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "DELETE FROM vault WHERE id = ?1 AND created_by = ?2 RETURNING document",
+                    params![id, owner],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn delete_expired_secrets(&self, now: DateTime<Utc>) -> StoreResult<Vec<VaultDocument>> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "DELETE FROM vault
+                 WHERE expires_at IS NOT NULL AND expires_at <= ?1 RETURNING document",
+            )?;
+            let rows = statement
+                .query_map(params![now.timestamp_millis()], |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            decode_all(rows)
+        })
+        .await
+    }
+
+    async fn list_secrets_to_reencrypt(
+        &self,
+        key_id: &str,
+        after: Option<&ObjectId>,
+        limit: u64,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        let key_id = key_id.to_string();
+        let after = after.map(|id| id.to_hex()).unwrap_or_default();
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT document FROM vault
+                 WHERE (coalesce(key_id, ?1) != ?2 OR NOT coalesce(bound, 0)) AND id > ?3
+                 ORDER BY id LIMIT ?4",
+            )?;
+            let rows = statement
+                .query_map(
+                    params![
+                        DEFAULT_KEY_ID,
+                        key_id,
+                        after,
+                        i64::try_from(limit).unwrap_or(i64::MAX)
+                    ],
+                    |row| row.get(0),
+                )?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            decode_all(rows)
+        })
+        .await
+    }
+
+    async fn count_secrets_to_reencrypt(&self, key_id: &str) -> StoreResult<u64> {
+        let key_id = key_id.to_string();
+        self.run(move |connection| {
+            let count: i64 = connection.query_row(
+                "SELECT count(*) FROM vault
+                 WHERE coalesce(key_id, ?1) != ?2 OR NOT coalesce(bound, 0)",
+                params![DEFAULT_KEY_ID, key_id],
+                |row| row.get(0),
+            )?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn find_secret_with_key_id(&self, key_id: &str) -> StoreResult<Option<VaultDocument>> {
+        let key_id = key_id.to_string();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM vault WHERE coalesce(key_id, ?1) = ?2 LIMIT 1",
+                    params![DEFAULT_KEY_ID, key_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn delete_trashed_secrets(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> StoreResult<Vec<VaultDocument>> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "DELETE FROM vault
+                 WHERE deleted_at IS NOT NULL AND deleted_at <= ?1 RETURNING document",
+            )?;
+            let rows = statement
+                .query_map(params![deleted_before.timestamp_millis()], |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+            decode_all(rows)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn insert_user(&self, user: &UserDocument) -> StoreResult<()> {
+        let user = user.clone();
+        self.run(move |connection| {
+            let exists: Option<String> = connection
+                .query_row(
+                    "SELECT id FROM users WHERE email = ?1",
+                    params![user.email],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if exists.is_some() {
+                return Err(StoreError::Duplicate("email"));
+            }
+
+            connection.execute(
+                "INSERT INTO users (id, email, document) VALUES (?1, ?2, ?3)",
+                params![user.id.to_hex(), user.email, encode(&user)?],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_by_id(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        let id = id.to_hex();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM users WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> StoreResult<Option<UserDocument>> {
+        let email = email.to_string();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM users WHERE email = ?1",
+                    params![email],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn update_user(
+        &self,
+        id: &ObjectId,
+        email: Option<&str>,
+        password: Option<&str>,
+    ) -> StoreResult<Option<UserDocument>> {
+        if email.is_none() && password.is_none() {
+            return Ok(None);
+        }
+
+        let id = id.to_hex();
+        let email = email.map(str::to_string);
+        let password = password.map(str::to_string);
+        self.run(move |connection| {
+            // Read and write in one transaction so concurrent updates cannot interleave
+            let transaction = connection.transaction()?;
+            let row: Option<Vec<u8>> = transaction
+                .query_row(
+                    "SELECT document FROM users WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(previous) = row
+                .map(|bytes| decode::<UserDocument>(&bytes))
+                .transpose()?
+            else {
+                return Ok(None);
+            };
+
+            let mut user = previous.clone();
+            if let Some(email) = email {
+                user.email = email;
+            }
+            if let Some(password) = password {
+                user.password = password;
+            }
+
+            transaction
+                .execute(
+                    "UPDATE users SET email = ?2, document = ?3 WHERE id = ?1",
+                    params![id, user.email, encode(&user)?],
+                )
+                .map_err(|e| duplicate(e, "email"))?;
+            transaction.commit()?;
+
+            Ok(Some(previous))
+        })
+        .await
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> StoreResult<Option<UserDocument>> {
+        let id = id.to_hex();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "DELETE FROM users WHERE id = ?1 RETURNING document",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn list_users(&self, page: PageRequest) -> StoreResult<Page<UserDocument>> {
+        self.run(move |connection| {
+            let total: i64 =
+                connection.query_row("SELECT count(*) FROM users", [], |row| row.get(0))?;
+            let mut statement = connection
+                .prepare("SELECT document FROM users ORDER BY rowid LIMIT ?1 OFFSET ?2")?;
+            let rows = statement
+                .query_map(params![sql_limit(page), sql_offset(page)], |row| row.get(0))?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+
+            Ok(Page {
+                items: decode_all(rows)?,
+                total: total as u64,
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl KeyStore for SqliteStore {
+    async fn get_key_pair(&self) -> StoreResult<Option<KeyPairDocument>> {
+        self.run(|connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM keys ORDER BY seq LIMIT 1",
+                    [],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn insert_key_pair(&self, key_pair: &KeyPairDocument) -> StoreResult<()> {
+        let document = encode(key_pair)?;
+        self.run(move |connection| {
+            connection.execute("INSERT INTO keys (document) VALUES (?1)", params![document])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_seal_config(&self) -> StoreResult<Option<SealConfigDocument>> {
+        self.run(|connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row("SELECT document FROM seal WHERE id = 1", [], |row| {
+                    row.get(0)
+                })
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn insert_seal_config(&self, config: &SealConfigDocument) -> StoreResult<()> {
+        let document = encode(config)?;
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO seal (id, document) VALUES (1, ?1)",
+                params![document],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Duplicate("seal configuration"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_key_checks(&self) -> StoreResult<Vec<KeyCheckDocument>> {
+        self.run(|connection| {
+            select_all(
+                connection,
+                "SELECT document FROM key_checks ORDER BY key_id",
+            )
+        })
+        .await
+    }
+
+    async fn insert_key_check(&self, check: &KeyCheckDocument) -> StoreResult<()> {
+        let (key_id, document) = (check.key_id.clone(), encode(check)?);
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO key_checks (key_id, document) VALUES (?1, ?2)",
+                params![key_id, document],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Duplicate("key id"));
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl TransitStore for SqliteStore {
+    async fn insert_transit_key(&self, key: &TransitKeyDocument) -> StoreResult<()> {
+        let (name, latest_version, document) = (key.name.clone(), key.latest_version, encode(key)?);
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO transit (name, latest_version, document)
+                 VALUES (?1, ?2, ?3)",
+                params![name, latest_version, document],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Duplicate("name"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_transit_key(&self, name: &str) -> StoreResult<Option<TransitKeyDocument>> {
+        let name = name.to_string();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM transit WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn list_transit_keys(&self) -> StoreResult<Vec<TransitKeyDocument>> {
+        self.run(|connection| select_all(connection, "SELECT document FROM transit ORDER BY name"))
+            .await
+    }
+
+    async fn replace_transit_key(
+        &self,
+        key: &TransitKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let (name, latest_version, document) = (key.name.clone(), key.latest_version, encode(key)?);
+        self.run(move |connection| {
+            let updated = connection.execute(
+                "UPDATE transit SET latest_version = ?2, document = ?3
+                 WHERE name = ?1 AND latest_version = ?4",
+                params![name, latest_version, document, expected_version],
+            )?;
+            Ok(updated == 1)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl SigningStore for SqliteStore {
+    async fn insert_signing_key(&self, key: &SigningKeyDocument) -> StoreResult<()> {
+        let (name, latest_version, document) = (key.name.clone(), key.latest_version, encode(key)?);
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO signing (name, latest_version, document)
+                 VALUES (?1, ?2, ?3)",
+                params![name, latest_version, document],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Duplicate("name"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_signing_key(&self, name: &str) -> StoreResult<Option<SigningKeyDocument>> {
+        let name = name.to_string();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM signing WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn list_signing_keys(&self) -> StoreResult<Vec<SigningKeyDocument>> {
+        self.run(|connection| select_all(connection, "SELECT document FROM signing ORDER BY name"))
+            .await
+    }
+
+    async fn replace_signing_key(
+        &self,
+        key: &SigningKeyDocument,
+        expected_version: u32,
+    ) -> StoreResult<bool> {
+        let (name, latest_version, document) = (key.name.clone(), key.latest_version, encode(key)?);
+        self.run(move |connection| {
+            let updated = connection.execute(
+                "UPDATE signing SET latest_version = ?2, document = ?3
+                 WHERE name = ?1 AND latest_version = ?4",
+                params![name, latest_version, document, expected_version],
+            )?;
+            Ok(updated == 1)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl AuditStore for SqliteStore {
+    async fn insert_audit_event(&self, event: &AuditEventDocument) -> StoreResult<()> {
+        let (resource, document) = (event.resource.clone(), encode(event)?);
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO audit (resource, document) VALUES (?1, ?2)",
+                params![resource, document],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_audit_events(
+        &self,
+        resource: &str,
+        page: PageRequest,
+    ) -> StoreResult<Page<AuditEventDocument>> {
+        let resource = resource.to_string();
+        self.run(move |connection| {
+            let total: i64 = connection.query_row(
+                "SELECT count(*) FROM audit WHERE resource = ?1",
+                params![resource],
+                |row| row.get(0),
+            )?;
+            let rows = connection
+                .prepare(
+                    "SELECT document FROM audit WHERE resource = ?1
+                     ORDER BY seq LIMIT ?2 OFFSET ?3",
+                )?
+                .query_map(
+                    params![resource, sql_limit(page), sql_offset(page)],
+                    |row| row.get(0),
+                )?
+                .collect::<Result<Vec<Vec<u8>>, _>>()?;
+
+            Ok(Page {
+                items: decode_all(rows)?,
+                total: total as u64,
+            })
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl GeneratorStore for SqliteStore {
+    async fn insert_generator_policy(&self, policy: &GeneratorPolicyDocument) -> StoreResult<()> {
+        let (name, document) = (policy.name.clone(), encode(policy)?);
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO generator (name, document) VALUES (?1, ?2)",
+                params![name, document],
+            )?;
+            if inserted == 0 {
+                return Err(StoreError::Duplicate("name"));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_generator_policy(
+        &self,
+        name: &str,
+    ) -> StoreResult<Option<GeneratorPolicyDocument>> {
+        let name = name.to_string();
+        self.run(move |connection| {
+            let row: Option<Vec<u8>> = connection
+                .query_row(
+                    "SELECT document FROM generator WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            row.map(|bytes| decode(&bytes)).transpose()
+        })
+        .await
+    }
+
+    async fn list_generator_policies(&self) -> StoreResult<Vec<GeneratorPolicyDocument>> {
+        self.run(|connection| {
+            select_all(connection, "SELECT document FROM generator ORDER BY name")
+        })
+        .await
+    }
+
+    async fn delete_generator_policy(&self, name: &str) -> StoreResult<bool> {
+        let name = name.to_string();
+        self.run(move |connection| {
+            let deleted =
+                connection.execute("DELETE FROM generator WHERE name = ?1", params![name])?;
+            Ok(deleted == 1)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl BackupStore for SqliteStore {
+    async fn export(&self) -> StoreResult<Snapshot> {
+        self.run(|connection| {
+            Ok(Snapshot {
+                users: select_all(connection, "SELECT document FROM users ORDER BY rowid")?,
+                vault: select_all(connection, "SELECT document FROM vault ORDER BY rowid")?,
+                keys: select_all(connection, "SELECT document FROM keys ORDER BY seq")?,
+                seal: select_all(connection, "SELECT document FROM seal")?
+                    .into_iter()
+                    .next(),
+                key_checks: select_all(
+                    connection,
+                    "SELECT document FROM key_checks ORDER BY key_id",
+                )?,
+                transit: select_all(connection, "SELECT document FROM transit ORDER BY name")?,
+                signing: select_all(connection, "SELECT document FROM signing ORDER BY name")?,
+                audit: select_all(connection, "SELECT document FROM audit ORDER BY seq")?,
+                generator: select_all(connection, "SELECT document FROM generator ORDER BY name")?,
+            })
+        })
+        .await
+    }
+
+    async fn import(&self, snapshot: &Snapshot, replace: bool) -> StoreResult<()> {
+        let snapshot = snapshot.clone();
+        self.run(move |connection| {
+            // Rolled back when dropped before the commit
+            let transaction = connection.transaction()?;
+
+            if replace {
+                transaction.execute_batch(
+                    "DELETE FROM users;
+                     DELETE FROM vault;
+                     DELETE FROM keys;
+                     DELETE FROM seal;
+                     DELETE FROM key_checks;
+                     DELETE FROM transit;
+                     DELETE FROM signing;
+                     DELETE FROM audit;
+                     DELETE FROM generator;",
+                )?;
+            }
+
+            for user in &snapshot.users {
+                transaction.execute(
+                    "INSERT INTO users (id, email, document) VALUES (?1, ?2, ?3)",
+                    params![user.id.to_hex(), user.email, encode(user)?],
+                )?;
+            }
+            for secret in &snapshot.vault {
+                insert_secret(&transaction, secret)?;
+            }
+            for key_pair in &snapshot.keys {
+                transaction.execute(
+                    "INSERT INTO keys (document) VALUES (?1)",
+                    params![encode(key_pair)?],
+                )?;
+            }
+            if let Some(config) = &snapshot.seal {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO seal (id, document) VALUES (1, ?1)",
+                    params![encode(config)?],
+                )?;
+            }
+            for check in &snapshot.key_checks {
+                transaction.execute(
+                    "INSERT INTO key_checks (key_id, document) VALUES (?1, ?2)",
+                    params![check.key_id, encode(check)?],
+                )?;
+            }
+            for key in &snapshot.transit {
+                transaction.execute(
+                    "INSERT INTO transit (name, latest_version, document) VALUES (?1, ?2, ?3)",
+                    params![key.name, key.latest_version, encode(key)?],
+                )?;
+            }
+            for key in &snapshot.signing {
+                transaction.execute(
+                    "INSERT INTO signing (name, latest_version, document) VALUES (?1, ?2, ?3)",
+                    params![key.name, key.latest_version, encode(key)?],
+                )?;
+            }
+            for event in &snapshot.audit {
+                transaction.execute(
+                    "INSERT INTO audit (resource, document) VALUES (?1, ?2)",
+                    params![event.resource, encode(event)?],
+                )?;
+            }
+            for policy in &snapshot.generator {
+                transaction.execute(
+                    "INSERT INTO generator (name, document) VALUES (?1, ?2)",
+                    params![policy.name, encode(policy)?],
+                )?;
+            }
+
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        AuditOutcome, GeneratorSpec, PasswordPolicy, SigningAlgorithm, SigningKeyVersion,
+        TransitKeyVersion, ValueEncoding,
+    };
+    use crate::utils::labels::LabelSelector;
+    use chrono::SubsecRound;
+
+    fn secret(key: &str) -> VaultDocument {
+        VaultDocument {
+            id: ObjectId::new(),
+            key: key.to_string(),
+            value: "ciphertext".to_string(),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: "alice@example.com".to_string(),
+            created_at: Utc::now(),
+            description: None,
+            labels: Default::default(),
+            expires_at: None,
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn documents_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let secret = VaultDocument {
+            id: ObjectId::new(),
+            key: "stripe_api_key".to_string(),
+            value: "ciphertext".to_string(),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: "alice@example.com".to_string(),
+            created_at: Utc::now().trunc_subsecs(3),
+            description: None,
+            labels: Default::default(),
+            expires_at: None,
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
+        };
+        store.insert_secret(&secret).await.unwrap();
+
+        let stored = store
+            .get_secret(&secret.id, "alice@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.key, secret.key);
+        assert_eq!(stored.created_at, secret.created_at);
+        assert!(store
+            .get_secret(&secret.id, "bob@example.com")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get_secret_by_key("alice@example.com", "stripe_api_key")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            store
+                .list_secrets_with_prefix("alice@example.com", "stripe")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let deleted = store
+            .delete_secret(&secret.id, "alice@example.com")
+            .await
+            .unwrap();
+        assert_eq!(deleted.map(|secret| secret.id), Some(secret.id));
+        assert!(store
+            .list_secrets("alice@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_secrets_are_deleted() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let now = Utc::now();
+        let secret = VaultDocument {
+            id: ObjectId::new(),
+            key: "temporary".to_string(),
+            value: "ciphertext".to_string(),
+            data_key: None,
+            key_id: None,
+            bound: false,
+            encoding: ValueEncoding::Utf8,
+            created_by: "alice@example.com".to_string(),
+            created_at: now,
+            description: None,
+            labels: Default::default(),
+            expires_at: Some(now - chrono::Duration::seconds(1)),
+            deleted_at: None,
+            version: 1,
+            updated_by: None,
+            updated_at: None,
+            history: Vec::new(),
+        };
+        store.insert_secret(&secret).await.unwrap();
+        store
+            .insert_secret(&VaultDocument {
+                id: ObjectId::new(),
+                key: "permanent".to_string(),
+                expires_at: None,
+                ..secret.clone()
+            })
+            .await
+            .unwrap();
+
+        let deleted = store.delete_expired_secrets(now).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].key, "temporary");
+        assert_eq!(
+            store.list_secrets("alice@example.com").await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn live_keys_are_unique_per_owner() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let first = secret("db/password");
+        store.insert_secret(&first).await.unwrap();
+        assert!(matches!(
+            store.insert_secret(&secret("db/password")).await,
+            Err(StoreError::Duplicate("key"))
+        ));
+        store
+            .insert_secret(&VaultDocument {
+                created_by: "bob@example.com".to_string(),
+                ..secret("db/password")
+            })
+            .await
+            .unwrap();
+
+        // Trashed entries free their key, restoring one needs it back
+        let trashed = VaultDocument {
+            deleted_at: Some(Utc::now()),
+            ..first.clone()
+        };
+        assert!(store.replace_secret(&trashed, 1).await.unwrap());
+        let second = secret("db/password");
+        store.insert_secret(&second).await.unwrap();
+        assert!(matches!(
+            store.replace_secret(&first, 1).await,
+            Err(StoreError::Duplicate("key"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn secrets_are_queried_page_by_page() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let now = Utc::now();
+        for (offset, key) in ["b", "a", "payments/c", "payments/d"].iter().enumerate() {
+            let mut secret = secret(key);
+            secret.created_at = now - chrono::Duration::minutes(10 - offset as i64);
+            store.insert_secret(&secret).await.unwrap();
+        }
+        let mut trashed = secret("trashed");
+        trashed.deleted_at = Some(now);
+        store.insert_secret(&trashed).await.unwrap();
+
+        let mut query = SecretQuery {
+            prefix: None,
+            created_after: None,
+            created_before: None,
+            labels: LabelSelector::default(),
+            sort: SortField::Key,
+            order: SortOrder::Asc,
+            page: PageRequest {
+                offset: 1,
+                limit: 2,
+            },
+        };
+        let page = store
+            .query_secrets("alice@example.com", &query, now)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(
+            page.items
+                .iter()
+                .map(|s| s.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["b", "payments/c"]
+        );
+
+        query.prefix = Some("payments/".to_string());
+        query.created_before = Some(now - chrono::Duration::seconds(450));
+        query.sort = SortField::CreatedAt;
+        query.order = SortOrder::Desc;
+        query.page.offset = 0;
+        let page = store
+            .query_secrets("alice@example.com", &query, now)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].key, "payments/c");
+    }
+
+    #[tokio::test]
+    async fn secrets_are_selected_by_label() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        for (key, team) in [("a", Some("data")), ("b", Some("legacy")), ("c", None)] {
+            let mut secret = secret(key);
+            secret.labels.insert("env".to_string(), "prod".to_string());
+            if let Some(team) = team {
+                secret.labels.insert("team".to_string(), team.to_string());
+            }
+            store.insert_secret(&secret).await.unwrap();
+        }
+        store.insert_secret(&secret("d")).await.unwrap();
+
+        let mut query = SecretQuery {
+            prefix: None,
+            created_after: None,
+            created_before: None,
+            labels: LabelSelector::parse("env=prod,team!=legacy").unwrap(),
+            sort: SortField::Key,
+            order: SortOrder::Asc,
+            page: PageRequest {
+                offset: 0,
+                limit: 10,
+            },
+        };
+        let keys = |page: Page<VaultDocument>| {
+            page.items
+                .into_iter()
+                .map(|secret| secret.key)
+                .collect::<Vec<_>>()
+        };
+        let page = store
+            .query_secrets("alice@example.com", &query, Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(keys(page), vec!["a", "c"]);
+
+        query.labels = LabelSelector::parse("!team").unwrap();
+        let page = store
+            .query_secrets("alice@example.com", &query, Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(keys(page), vec!["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn users_keep_unique_emails() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let user = UserDocument {
+            id: ObjectId::new(),
+            email: "alice@example.com".to_string(),
+            password: "hash".to_string(),
+            created_at: Utc::now(),
+        };
+        store.insert_user(&user).await.unwrap();
+        assert!(matches!(
+            store
+                .insert_user(&UserDocument {
+                    id: ObjectId::new(),
+                    ..user.clone()
+                })
+                .await,
+            Err(StoreError::Duplicate("email"))
+        ));
+
+        store
+            .update_user(&user.id, Some("carol@example.com"), None)
+            .await
+            .unwrap();
+        let updated = store
+            .get_user_by_email("carol@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.id, user.id);
+        assert_eq!(updated.password, "hash");
+
+        let other = UserDocument {
+            id: ObjectId::new(),
+            email: "dave@example.com".to_string(),
+            ..user.clone()
+        };
+        store.insert_user(&other).await.unwrap();
+        assert!(matches!(
+            store
+                .update_user(&other.id, Some("carol@example.com"), None)
+                .await,
+            Err(StoreError::Duplicate("email"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn transit_keys_are_replaced_only_from_their_latest_version() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let version = |version| TransitKeyVersion {
+            version,
+            data_key: "wrapped".to_string(),
+            key_id: DEFAULT_KEY_ID.to_string(),
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        let mut key = TransitKeyDocument {
+            id: ObjectId::new(),
+            name: "etl".to_string(),
+            created_by: "alice@example.com".to_string(),
+            allowed_subjects: vec!["bob@example.com".to_string()],
+            latest_version: 1,
+            versions: vec![version(1)],
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        store.insert_transit_key(&key).await.unwrap();
+        assert!(matches!(
+            store.insert_transit_key(&key).await,
+            Err(StoreError::Duplicate("name"))
+        ));
+
+        key.latest_version = 2;
+        key.versions.push(version(2));
+        assert!(store.replace_transit_key(&key, 1).await.unwrap());
+        assert!(!store.replace_transit_key(&key, 1).await.unwrap());
+
+        let stored = store.get_transit_key("etl").await.unwrap().unwrap();
+        assert_eq!(stored.latest_version, 2);
+        assert_eq!(stored.versions.len(), 2);
+        assert_eq!(stored.allowed_subjects, key.allowed_subjects);
+        assert_eq!(store.list_transit_keys().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn audit_events_are_paged_per_resource_in_order() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let mut key = SigningKeyDocument {
+            id: ObjectId::new(),
+            name: "webhooks".to_string(),
+            algorithm: SigningAlgorithm::Ed25519,
+            created_by: "alice@example.com".to_string(),
+            allowed_subjects: Vec::new(),
+            latest_version: 1,
+            versions: vec![SigningKeyVersion {
+                version: 1,
+                data_key: "wrapped".to_string(),
+                key_id: DEFAULT_KEY_ID.to_string(),
+                public_key: Some("public".to_string()),
+                created_at: Utc::now().trunc_subsecs(3),
+            }],
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        store.insert_signing_key(&key).await.unwrap();
+        assert!(matches!(
+            store.insert_signing_key(&key).await,
+            Err(StoreError::Duplicate("name"))
+        ));
+        key.latest_version = 2;
+        assert!(store.replace_signing_key(&key, 1).await.unwrap());
+        assert!(!store.replace_signing_key(&key, 1).await.unwrap());
+        let stored = store.get_signing_key("webhooks").await.unwrap().unwrap();
+        assert_eq!(stored.algorithm, SigningAlgorithm::Ed25519);
+        assert_eq!(stored.versions[0].public_key.as_deref(), Some("public"));
+
+        for (resource, operation) in [
+            ("signing/webhooks", "create"),
+            ("signing/artifacts", "create"),
+            ("signing/webhooks", "sign"),
+            ("signing/webhooks", "verify"),
+        ] {
+            let event = AuditEventDocument {
+                id: ObjectId::new(),
+                resource: resource.to_string(),
+                operation: operation.to_string(),
+                subject: "alice@example.com".to_string(),
+                version: Some(1),
+                outcome: AuditOutcome::Success,
+                created_at: Utc::now().trunc_subsecs(3),
+            };
+            store.insert_audit_event(&event).await.unwrap();
+        }
+
+        let page = store
+            .list_audit_events(
+                "signing/webhooks",
+                PageRequest {
+                    offset: 1,
+                    limit: 5,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        let operations: Vec<&str> = page
+            .items
+            .iter()
+            .map(|event| event.operation.as_str())
+            .collect();
+        assert_eq!(operations, vec!["sign", "verify"]);
+    }
+
+    #[tokio::test]
+    async fn generator_policies_round_trip() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let policy = GeneratorPolicyDocument {
+            id: ObjectId::new(),
+            name: "db-passwords".to_string(),
+            description: None,
+            spec: GeneratorSpec::Password(PasswordPolicy {
+                length: 32,
+                exclude: "0O".to_string(),
+                ..PasswordPolicy::default()
+            }),
+            created_by: "admin@example.com".to_string(),
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        store.insert_generator_policy(&policy).await.unwrap();
+        assert!(matches!(
+            store.insert_generator_policy(&policy).await,
+            Err(StoreError::Duplicate("name"))
+        ));
+
+        let stored = store
+            .get_generator_policy("db-passwords")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.spec, policy.spec);
+        assert!(store.delete_generator_policy("db-passwords").await.unwrap());
+        assert!(!store.delete_generator_policy("db-passwords").await.unwrap());
+        assert!(store.list_generator_policies().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn key_checks_are_unique_per_key_id() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let check = KeyCheckDocument {
+            key_id: "default".to_string(),
+            check: "wrapped".to_string(),
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        store.insert_key_check(&check).await.unwrap();
+        assert!(matches!(
+            store.insert_key_check(&check).await,
+            Err(StoreError::Duplicate("key id"))
+        ));
+        let checks = store.list_key_checks().await.unwrap();
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].check, "wrapped");
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_imported_in_one_transaction() {
+        let source = SqliteStore::open_in_memory().unwrap();
+        let user = UserDocument {
+            id: ObjectId::new(),
+            email: "alice@example.com".to_string(),
+            password: "hash".to_string(),
+            created_at: Utc::now().trunc_subsecs(3),
+        };
+        source.insert_user(&user).await.unwrap();
+        source.insert_secret(&secret("db/password")).await.unwrap();
+        let snapshot = source.export().await.unwrap();
+
+        let target = SqliteStore::open_in_memory().unwrap();
+        target.import(&snapshot, false).await.unwrap();
+        let restored = target.export().await.unwrap();
+        assert_eq!(restored.users[0].id, user.id);
+        assert_eq!(restored.vault[0].id, snapshot.vault[0].id);
+
+        // The user clashes with the existing one, so the entry is not written either
+        let clash = Snapshot {
+            users: vec![UserDocument {
+                id: ObjectId::new(),
+                ..user.clone()
+            }],
+            vault: vec![secret("api/token")],
+            ..Snapshot::default()
+        };
+        assert!(target.import(&clash, false).await.is_err());
+        assert_eq!(target.export().await.unwrap().vault.len(), 1);
+
+        target.import(&clash, true).await.unwrap();
+        let replaced = target.export().await.unwrap();
+        assert_eq!(replaced.users[0].id, clash.users[0].id);
+        assert_eq!(replaced.vault.len(), 1);
+        assert_eq!(replaced.vault[0].key, "api/token");
+    }
+}
@@ -0,0 +1,113 @@
+use thiserror::Error;
+
+use crate::utils::secret::{Secret, SecretBytes, SecretKey};
+use crate::utils::vault::{
+    decrypt_with_key, derive_key, encrypt_with_key_id, generate_key, DecryptError, EncryptError,
+    KEY_LENGTH,
+};
+
+/*---------------------------------------------------------------------------
+    Envelope encryption.
+
+    Every secret is encrypted with its own random data-encryption key
+    (DEK). The DEK is stored next to the ciphertext, wrapped by the
+    key-encryption key (KEK) derived once from the master key. Reading a
+    secret therefore costs one unwrap and one decryption instead of an
+    Argon2 derivation, and changing the master key only requires the
+    (small) wrapped DEKs to be re-encrypted.
+---------------------------------------------------------------------------*/
+
+/// Salt of the KEK derivation. Configured master keys must be 256-bit keys
+/// rather than passwords (checked by `Keyring::with_active_key`), so a fixed
+/// salt does not weaken them and keeps the KEK stable across restarts.
+const KEK_SALT: &[u8] = b"ec_secrets_management/envelope/kek";
+
+#[derive(Error, Debug)]
+pub enum EnvelopeError {
+    #[error("failed to derive the key-encryption key: {0}")]
+    Derive(argon2::Error),
+    #[error("failed to wrap a data key: {0}")]
+    Wrap(EncryptError),
+    #[error("failed to unwrap a data key (possibly invalid encryption key): {0}")]
+    Unwrap(DecryptError),
+    #[error("unwrapped data key has an invalid length")]
+    InvalidDataKey,
+}
+
+pub type DataKey = SecretKey;
+
+#[derive(Debug)]
+pub struct KeyEncryptionKey {
+    key: SecretKey,
+    // Recorded in the header of every wrapped DEK
+    id: Option<String>,
+}
+
+impl KeyEncryptionKey {
+    /// Derives the KEK from the master key. Runs Argon2, so call once at startup.
+    /// Always uses the default Argon2 parameters: the KEK must not change with configuration.
+    pub fn derive(master_key: &[u8]) -> Result<Self, EnvelopeError> {
+        let key = derive_key(master_key, KEK_SALT).map_err(EnvelopeError::Derive)?;
+        Ok(Self { key, id: None })
+    }
+
+    /// Names the KEK, so wrapped DEKs record which master key they need
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    /// Generates a new DEK, returning it together with its wrapped form
+    pub fn generate_data_key(&self) -> Result<(DataKey, Vec<u8>), EnvelopeError> {
+        let data_key = Secret::new(generate_key());
+        let wrapped = self.wrap(&data_key)?;
+        Ok((data_key, wrapped))
+    }
+
+    pub fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>, EnvelopeError> {
+        encrypt_with_key_id(data_key.expose(), self.key.expose(), self.id.as_deref())
+            .map_err(EnvelopeError::Wrap)
+    }
+
+    pub fn unwrap(&self, wrapped: &[u8]) -> Result<DataKey, EnvelopeError> {
+        let unwrapped = SecretBytes::new(
+            decrypt_with_key(wrapped, self.key.expose()).map_err(EnvelopeError::Unwrap)?,
+        );
+        let mut data_key = Secret::new([0u8; KEY_LENGTH]);
+        if unwrapped.expose().len() != KEY_LENGTH {
+            return Err(EnvelopeError::InvalidDataKey);
+        }
+        data_key.expose_mut().copy_from_slice(unwrapped.expose());
+        Ok(data_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::vault::read_header;
+
+    #[test]
+    fn data_keys_are_wrapped_and_unwrapped() {
+        let kek = KeyEncryptionKey::derive(b"master key").unwrap();
+        let (data_key, wrapped) = kek.generate_data_key().unwrap();
+        assert_eq!(kek.unwrap(&wrapped).unwrap().expose(), data_key.expose());
+
+        // The same master key always yields the same KEK
+        let restarted = KeyEncryptionKey::derive(b"master key").unwrap();
+        assert_eq!(
+            restarted.unwrap(&wrapped).unwrap().expose(),
+            data_key.expose()
+        );
+
+        let other = KeyEncryptionKey::derive(b"another key").unwrap();
+        assert!(other.unwrap(&wrapped).is_err());
+
+        let named = KeyEncryptionKey::derive(b"master key")
+            .unwrap()
+            .with_id("2025");
+        let (_, wrapped) = named.generate_data_key().unwrap();
+        let header = read_header(&wrapped).unwrap().expect("header");
+        assert_eq!(header.key_id.as_deref(), Some("2025"));
+    }
+}
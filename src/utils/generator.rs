@@ -0,0 +1,315 @@
+use base64::{engine::general_purpose, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+use crate::models::{
+    GeneratorSpec, KeyPolicy, PasswordPolicy, TokenFormat, TokenPolicy, ValueEncoding,
+};
+use crate::utils::secret::{SecretBytes, SecretString};
+
+/*---------------------------------------------------------------------------
+    Secret generation.
+
+    Every value is drawn from the operating system's random number
+    generator. Characters are picked by rejection sampling so that each
+    one of an alphabet is equally likely, and the characters required by a
+    password policy are shuffled into random positions.
+---------------------------------------------------------------------------*/
+
+pub const MIN_PASSWORD_LENGTH: u32 = 8;
+pub const MAX_PASSWORD_LENGTH: u32 = 1024;
+pub const MIN_TOKEN_BYTES: u32 = 16;
+pub const MAX_TOKEN_BYTES: u32 = 1024;
+pub const KEY_SIZES: [u32; 5] = [128, 192, 256, 384, 512];
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+// No quotes, backslash, backtick or space, so values paste safely into shells and configs
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+#[derive(Error, Debug)]
+#[error("invalid generator policy: {0}")]
+pub struct InvalidPolicy(pub String);
+
+/// A generated value, with the encoding to store it under
+pub struct Generated {
+    pub value: SecretString,
+    pub encoding: ValueEncoding,
+}
+
+/// Checks a specification without generating anything
+pub fn validate(spec: &GeneratorSpec) -> Result<(), InvalidPolicy> {
+    match spec {
+        GeneratorSpec::Password(policy) => password_classes(policy).map(|_| ()),
+        GeneratorSpec::Token(policy) => token_bytes(policy).map(|_| ()),
+        GeneratorSpec::Uuid => Ok(()),
+        GeneratorSpec::Key(policy) => key_bytes(policy).map(|_| ()),
+    }
+}
+
+pub fn generate(spec: &GeneratorSpec) -> Result<Generated, InvalidPolicy> {
+    match spec {
+        GeneratorSpec::Password(policy) => Ok(text(password(policy)?)),
+        GeneratorSpec::Token(policy) => {
+            let bytes = random_bytes(token_bytes(policy)?);
+            let value = match policy.format {
+                TokenFormat::Hex => hex::encode(bytes.expose()),
+                TokenFormat::Base64 => general_purpose::STANDARD.encode(bytes.expose()),
+                TokenFormat::Base64url => general_purpose::URL_SAFE_NO_PAD.encode(bytes.expose()),
+            };
+            Ok(text(value))
+        }
+        GeneratorSpec::Uuid => Ok(text(uuid())),
+        GeneratorSpec::Key(policy) => {
+            let bytes = random_bytes(key_bytes(policy)?);
+            Ok(Generated {
+                value: SecretString::new(general_purpose::STANDARD.encode(bytes.expose())),
+                encoding: ValueEncoding::Base64,
+            })
+        }
+    }
+}
+
+fn text(value: String) -> Generated {
+    Generated {
+        value: SecretString::new(value),
+        encoding: ValueEncoding::Utf8,
+    }
+}
+
+/// The enabled character classes of a policy with their minimums
+fn password_classes(policy: &PasswordPolicy) -> Result<Vec<(Vec<char>, u32)>, InvalidPolicy> {
+    let invalid = |message: String| Err(InvalidPolicy(message));
+
+    if !(MIN_PASSWORD_LENGTH..=MAX_PASSWORD_LENGTH).contains(&policy.length) {
+        return invalid(format!(
+            "length must be between {} and {}",
+            MIN_PASSWORD_LENGTH, MAX_PASSWORD_LENGTH
+        ));
+    }
+
+    let mut classes = Vec::new();
+    for (name, alphabet, enabled, minimum) in [
+        (
+            "lowercase",
+            LOWERCASE,
+            policy.lowercase,
+            policy.min_lowercase,
+        ),
+        (
+            "uppercase",
+            UPPERCASE,
+            policy.uppercase,
+            policy.min_uppercase,
+        ),
+        ("digits", DIGITS, policy.digits, policy.min_digits),
+        ("symbols", SYMBOLS, policy.symbols, policy.min_symbols),
+    ] {
+        if !enabled {
+            if minimum > 0 {
+                return invalid(format!("min_{} requires {} to be enabled", name, name));
+            }
+            continue;
+        }
+        let characters: Vec<char> = alphabet
+            .chars()
+            .filter(|c| !policy.exclude.contains(*c))
+            .collect();
+        if characters.is_empty() {
+            return invalid(format!("every character of {} is excluded", name));
+        }
+        classes.push((characters, minimum));
+    }
+
+    if classes.is_empty() {
+        return invalid("at least one character class must be enabled".to_string());
+    }
+    let required: u64 = classes.iter().map(|(_, minimum)| *minimum as u64).sum();
+    if required > policy.length as u64 {
+        return invalid(format!(
+            "the minimums add up to {}, more than the length of {}",
+            required, policy.length
+        ));
+    }
+    Ok(classes)
+}
+
+fn password(policy: &PasswordPolicy) -> Result<String, InvalidPolicy> {
+    let classes = password_classes(policy)?;
+    let alphabet: Vec<char> = classes
+        .iter()
+        .flat_map(|(characters, _)| characters.iter().copied())
+        .collect();
+
+    let mut characters = Vec::with_capacity(policy.length as usize);
+    for (class, minimum) in &classes {
+        for _ in 0..*minimum {
+            characters.push(class[random_index(class.len())]);
+        }
+    }
+    while characters.len() < policy.length as usize {
+        characters.push(alphabet[random_index(alphabet.len())]);
+    }
+
+    // Fisher-Yates, so required characters do not always lead
+    for i in (1..characters.len()).rev() {
+        characters.swap(i, random_index(i + 1));
+    }
+
+    let value = characters.iter().collect();
+    characters.zeroize();
+    Ok(value)
+}
+
+fn token_bytes(policy: &TokenPolicy) -> Result<usize, InvalidPolicy> {
+    if !(MIN_TOKEN_BYTES..=MAX_TOKEN_BYTES).contains(&policy.bytes) {
+        return Err(InvalidPolicy(format!(
+            "bytes must be between {} and {}",
+            MIN_TOKEN_BYTES, MAX_TOKEN_BYTES
+        )));
+    }
+    Ok(policy.bytes as usize)
+}
+
+fn key_bytes(policy: &KeyPolicy) -> Result<usize, InvalidPolicy> {
+    if !KEY_SIZES.contains(&policy.bits) {
+        return Err(InvalidPolicy(format!(
+            "bits must be one of {:?}",
+            KEY_SIZES
+        )));
+    }
+    Ok(policy.bits as usize / 8)
+}
+
+fn random_bytes(len: usize) -> SecretBytes {
+    let mut bytes = SecretBytes::new(vec![0u8; len]);
+    OsRng.fill_bytes(bytes.expose_mut());
+    bytes
+}
+
+/// A uniformly distributed index below `bound`
+fn random_index(bound: usize) -> usize {
+    let bound = bound as u32;
+    // Values above the last multiple of `bound` would favour the first indexes
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let value = OsRng.next_u32();
+        if value < zone {
+            return (value % bound) as usize;
+        }
+    }
+}
+
+/// Version 4 UUID, as described by RFC 9562
+fn uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_follow_their_policy() {
+        let policy = PasswordPolicy {
+            length: 12,
+            symbols: false,
+            min_uppercase: 3,
+            min_digits: 4,
+            exclude: "0O1lI".to_string(),
+            ..PasswordPolicy::default()
+        };
+
+        for _ in 0..50 {
+            let generated = generate(&GeneratorSpec::Password(policy.clone())).unwrap();
+            let value = generated.value.expose();
+            assert_eq!(value.chars().count(), 12);
+            assert_eq!(generated.encoding, ValueEncoding::Utf8);
+            assert!(value.chars().all(|c| c.is_ascii_alphanumeric()));
+            assert!(!value.chars().any(|c| "0O1lI".contains(c)));
+            assert!(value.chars().filter(char::is_ascii_uppercase).count() >= 3);
+            assert!(value.chars().filter(char::is_ascii_digit).count() >= 4);
+        }
+    }
+
+    #[test]
+    fn invalid_password_policies_are_rejected() {
+        for policy in [
+            PasswordPolicy {
+                length: 4,
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                symbols: false,
+                min_symbols: 1,
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                length: 8,
+                min_digits: 5,
+                min_lowercase: 5,
+                ..PasswordPolicy::default()
+            },
+            PasswordPolicy {
+                lowercase: false,
+                uppercase: false,
+                symbols: false,
+                exclude: DIGITS.to_string(),
+                ..PasswordPolicy::default()
+            },
+        ] {
+            assert!(validate(&GeneratorSpec::Password(policy)).is_err());
+        }
+    }
+
+    #[test]
+    fn tokens_uuids_and_keys_are_well_formed() {
+        let token = generate(&GeneratorSpec::Token(TokenPolicy::default())).unwrap();
+        assert_eq!(hex::decode(token.value.expose()).unwrap().len(), 32);
+
+        let token = generate(&GeneratorSpec::Token(TokenPolicy {
+            bytes: 24,
+            format: TokenFormat::Base64url,
+        }))
+        .unwrap();
+        assert_eq!(token.value.expose().len(), 32);
+        assert!(!token.value.expose().contains(['+', '/', '=']));
+
+        let uuid = generate(&GeneratorSpec::Uuid).unwrap();
+        let uuid = uuid.value.expose();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+
+        let key = generate(&GeneratorSpec::Key(KeyPolicy { bits: 512 })).unwrap();
+        assert_eq!(key.encoding, ValueEncoding::Base64);
+        assert_eq!(
+            general_purpose::STANDARD
+                .decode(key.value.expose())
+                .unwrap()
+                .len(),
+            64
+        );
+        assert!(validate(&GeneratorSpec::Key(KeyPolicy { bits: 100 })).is_err());
+        assert!(validate(&GeneratorSpec::Token(TokenPolicy {
+            bytes: 4,
+            ..TokenPolicy::default()
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, OnceLock};
+
+use argon2::{Config, Variant, Version};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use crate::utils::secret::{Secret, SecretKey};
+use crate::utils::vault::KEY_LENGTH;
+
+/*---------------------------------------------------------------------------
+    Argon2id key derivation.
+
+    The cost of new password based ciphertexts is configured through the
+    environment and recorded in their header, so changing it never
+    affects existing data:
+
+    ECS_ARGON2_MEMORY_KIB    memory in KiB (defaults to 19456)
+    ECS_ARGON2_ITERATIONS    number of passes (defaults to 2)
+    ECS_ARGON2_PARALLELISM   number of lanes (defaults to 1)
+
+    Ciphertexts asking for more than a few times that cost are refused
+    before any memory is allocated for them.
+
+    Derivations are slow by design, so derived keys are cached in process,
+    keyed by a digest of the password together with the salt and params.
+---------------------------------------------------------------------------*/
+
+/// Largest number of derived keys kept in memory before the cache is cleared
+const MAX_CACHED_KEYS: usize = 1024;
+
+/// How many times the configured cost a ciphertext header may ask for
+const MAX_COST_FACTOR: u32 = 4;
+
+/// Argon2id cost parameters
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Argon2Params {
+    pub mem_cost: u32,
+    pub time_cost: u32,
+    pub lanes: u32,
+}
+
+impl Default for Argon2Params {
+    /// The parameters every headerless ciphertext was encrypted with
+    fn default() -> Self {
+        Self {
+            mem_cost: 19 * 1024,
+            time_cost: 2,
+            lanes: 1,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Reads the parameters from the environment, using the default for unset values
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = Self::default();
+        let params = Self {
+            mem_cost: env_u32("ECS_ARGON2_MEMORY_KIB", defaults.mem_cost)?,
+            time_cost: env_u32("ECS_ARGON2_ITERATIONS", defaults.time_cost)?,
+            lanes: env_u32("ECS_ARGON2_PARALLELISM", defaults.lanes)?,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// The parameters new ciphertexts are encrypted with, read from the environment once
+    pub fn configured() -> Self {
+        static CONFIGURED: OnceLock<Argon2Params> = OnceLock::new();
+        *CONFIGURED.get_or_init(|| {
+            Self::from_env().unwrap_or_else(|e| {
+                warn!("Invalid Argon2 parameters, using the defaults: {}", e);
+                Self::default()
+            })
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.time_cost == 0 {
+            return Err("ECS_ARGON2_ITERATIONS must be at least 1".to_string());
+        }
+        if !(1..=0xFF_FFFF).contains(&self.lanes) {
+            return Err("ECS_ARGON2_PARALLELISM must be between 1 and 16777215".to_string());
+        }
+        if self.mem_cost < 8 * self.lanes {
+            return Err("ECS_ARGON2_MEMORY_KIB must be at least 8 KiB per lane".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a ciphertext header may ask for these parameters. Headers are read before
+    /// they can be authenticated, so each cost is capped at `MAX_COST_FACTOR` times the
+    /// configured or default cost, whichever is higher.
+    pub fn is_acceptable(&self) -> bool {
+        let configured = Self::configured();
+        let defaults = Self::default();
+        let limit =
+            |configured: u32, default: u32| configured.max(default).saturating_mul(MAX_COST_FACTOR);
+        self.validate().is_ok()
+            && self.mem_cost <= limit(configured.mem_cost, defaults.mem_cost)
+            && self.time_cost <= limit(configured.time_cost, defaults.time_cost)
+            && self.lanes <= limit(configured.lanes, defaults.lanes)
+    }
+}
+
+fn env_u32(name: &str, default: u32) -> Result<u32, String> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} must be a positive integer", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct CacheKey {
+    // Digest rather than the password itself, which is not worth a second copy in memory
+    password: [u8; 32],
+    salt: Vec<u8>,
+    params: Argon2Params,
+}
+
+static CACHE: LazyLock<Mutex<HashMap<CacheKey, SecretKey>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Derives a raw key from a password with Argon2id, reusing earlier derivations
+pub fn derive_key(
+    password: &[u8],
+    salt: &[u8],
+    params: &Argon2Params,
+) -> Result<SecretKey, argon2::Error> {
+    let cache_key = CacheKey {
+        password: Sha256::digest(password).into(),
+        salt: salt.to_vec(),
+        params: *params,
+    };
+    if let Some(key) = cache().get(&cache_key) {
+        return Ok(key.clone());
+    }
+
+    let config = Config {
+        hash_length: KEY_LENGTH as u32,
+        variant: Variant::Argon2id,
+        version: Version::Version13,
+        mem_cost: params.mem_cost,
+        time_cost: params.time_cost,
+        lanes: params.lanes,
+        ..Default::default()
+    };
+    let mut hash = argon2::hash_raw(password, salt, &config)?;
+    let mut key = Secret::new([0u8; KEY_LENGTH]);
+    key.expose_mut().copy_from_slice(&hash);
+    hash.zeroize();
+
+    let mut cache = cache();
+    if cache.len() >= MAX_CACHED_KEYS {
+        cache.clear();
+    }
+    cache.insert(cache_key, key.clone());
+    Ok(key)
+}
+
+fn cache() -> std::sync::MutexGuard<'static, HashMap<CacheKey, SecretKey>> {
+    CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_keys_depend_on_password_salt_and_params() {
+        let params = Argon2Params {
+            mem_cost: 64,
+            time_cost: 1,
+            lanes: 1,
+        };
+        let derive = |password: &[u8], salt: &[u8], params: &Argon2Params| {
+            *derive_key(password, salt, params).unwrap().expose()
+        };
+        let key = derive(b"password", b"salt of sixteen!", &params);
+        assert_eq!(derive(b"password", b"salt of sixteen!", &params), key);
+        assert_ne!(derive(b"other", b"salt of sixteen!", &params), key);
+        assert_ne!(derive(b"password", b"another salt....", &params), key);
+        let costlier = Argon2Params {
+            time_cost: 2,
+            ..params
+        };
+        assert_ne!(derive(b"password", b"salt of sixteen!", &costlier), key);
+
+        assert!(Argon2Params { lanes: 0, ..params }.validate().is_err());
+        assert!(Argon2Params::default().validate().is_ok());
+    }
+
+    #[test]
+    fn header_params_are_capped() {
+        assert!(Argon2Params::default().is_acceptable());
+        assert!(Argon2Params::configured().is_acceptable());
+        let defaults = Argon2Params::default();
+        assert!(!Argon2Params {
+            mem_cost: u32::MAX,
+            ..defaults
+        }
+        .is_acceptable());
+        assert!(!Argon2Params {
+            time_cost: u32::MAX,
+            ..defaults
+        }
+        .is_acceptable());
+        assert!(!Argon2Params {
+            lanes: 1024,
+            mem_cost: 8 * 1024,
+            ..defaults
+        }
+        .is_acceptable());
+        assert!(!Argon2Params {
+            time_cost: 0,
+            ..defaults
+        }
+        .is_acceptable());
+    }
+}
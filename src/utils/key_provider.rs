@@ -0,0 +1,348 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::utils::secret::{SecretBytes, SecretString};
+
+/*---------------------------------------------------------------------------
+    Key providers.
+
+    The active master key is fetched from a [KeyProvider] when the vault
+    starts, so that it does not have to live in the process environment.
+    The provider is selected through [ECS_KEY_PROVIDER]:
+
+    - env (default): ECS_ENCRYPTION_KEY
+    - file: the file at ECS_ENCRYPTION_KEY_FILE, which defaults to the
+      Docker secret [DEFAULT_KEY_FILE]. Surrounding whitespace is ignored.
+    - http: a key service listening on the local machine or network,
+      queried with `GET ECS_KEY_SERVICE_URL` and an optional bearer
+      ECS_KEY_SERVICE_TOKEN. It must answer `{"key": "<base64 key>"}`.
+
+    A provider without a key leaves the vault sealed, see
+    repositories::seal.
+---------------------------------------------------------------------------*/
+
+/// Key file read by the file provider when ECS_ENCRYPTION_KEY_FILE is not set
+pub const DEFAULT_KEY_FILE: &str = "/run/secrets/ecs_encryption_key";
+
+/// Time allowed to connect to the key service and for each read or write
+const KEY_SERVICE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum KeyProviderError {
+    #[error("unknown key provider {0}, expected env, file or http")]
+    Unknown(String),
+    #[error("{0} must be set")]
+    Missing(&'static str),
+    #[error("cannot read the key file {path}: {source}")]
+    File {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("the key file {0} is empty")]
+    EmptyFile(String),
+    #[error("invalid key service url {0}, expected http://host[:port]/path")]
+    InvalidUrl(String),
+    #[error("key service request failed: {0}")]
+    Request(#[from] std::io::Error),
+    #[error("key service answered with status {0}")]
+    Status(u16),
+    #[error("invalid key service response: {0}")]
+    Response(String),
+}
+
+/// A source of the active master key
+pub trait KeyProvider: Send + Sync {
+    /// Where the key comes from, for logs. Never includes the key.
+    fn describe(&self) -> String;
+
+    /// The master key, or `None` when the source holds none and the vault should start sealed
+    fn master_key(&self) -> Result<Option<SecretString>, KeyProviderError>;
+}
+
+/// Builds the provider selected through ECS_KEY_PROVIDER
+pub fn from_env() -> Result<Box<dyn KeyProvider>, KeyProviderError> {
+    let provider = std::env::var("ECS_KEY_PROVIDER").unwrap_or_else(|_| "env".to_string());
+
+    match provider.trim().to_ascii_lowercase().as_str() {
+        "" | "env" => Ok(Box::new(EnvKeyProvider::new("ECS_ENCRYPTION_KEY"))),
+        "file" => {
+            let path = std::env::var("ECS_ENCRYPTION_KEY_FILE")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_KEY_FILE.to_string());
+            Ok(Box::new(FileKeyProvider::new(path.trim())))
+        }
+        "http" => {
+            let url = std::env::var("ECS_KEY_SERVICE_URL")
+                .map_err(|_| KeyProviderError::Missing("ECS_KEY_SERVICE_URL"))?;
+            let token = std::env::var("ECS_KEY_SERVICE_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty())
+                .map(SecretString::new);
+            Ok(Box::new(HttpKeyProvider::new(&url, token)?))
+        }
+        _ => Err(KeyProviderError::Unknown(provider)),
+    }
+}
+
+/// Reads the key from an environment variable
+#[derive(Debug)]
+pub struct EnvKeyProvider {
+    variable: &'static str,
+}
+
+impl EnvKeyProvider {
+    pub fn new(variable: &'static str) -> Self {
+        Self { variable }
+    }
+}
+
+impl KeyProvider for EnvKeyProvider {
+    fn describe(&self) -> String {
+        format!("environment variable {}", self.variable)
+    }
+
+    fn master_key(&self) -> Result<Option<SecretString>, KeyProviderError> {
+        Ok(std::env::var(self.variable).ok().map(SecretString::new))
+    }
+}
+
+/// Reads the key from a file, such as a Docker or Kubernetes secret
+#[derive(Debug)]
+pub struct FileKeyProvider {
+    path: PathBuf,
+}
+
+impl FileKeyProvider {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl KeyProvider for FileKeyProvider {
+    fn describe(&self) -> String {
+        format!("key file {}", self.path.display())
+    }
+
+    fn master_key(&self) -> Result<Option<SecretString>, KeyProviderError> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map(SecretString::new)
+            .map_err(|source| KeyProviderError::File {
+                path: self.path.display().to_string(),
+                source,
+            })?;
+
+        let key = contents.expose().trim();
+        if key.is_empty() {
+            return Err(KeyProviderError::EmptyFile(self.path.display().to_string()));
+        }
+        Ok(Some(SecretString::new(key.to_string())))
+    }
+}
+
+/// Fetches the key from a key service over plain HTTP/1.0. Only meant for a service on the
+/// same host or a private network: there is no TLS.
+#[derive(Debug)]
+pub struct HttpKeyProvider {
+    url: String,
+    authority: String,
+    path: String,
+    token: Option<SecretString>,
+}
+
+#[derive(Deserialize)]
+struct KeyServiceResponse {
+    key: SecretString,
+}
+
+impl HttpKeyProvider {
+    pub fn new(url: &str, token: Option<SecretString>) -> Result<Self, KeyProviderError> {
+        let url = url.trim();
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| KeyProviderError::InvalidUrl(url.to_string()))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        if authority.is_empty() || authority.contains('@') {
+            return Err(KeyProviderError::InvalidUrl(url.to_string()));
+        }
+
+        Ok(Self {
+            url: url.to_string(),
+            authority: authority.to_string(),
+            path: path.to_string(),
+            token,
+        })
+    }
+
+    fn request(&self) -> Result<SecretBytes, KeyProviderError> {
+        let address = if self.authority.contains(':') {
+            self.authority.clone()
+        } else {
+            format!("{}:80", self.authority)
+        };
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| KeyProviderError::InvalidUrl(self.url.clone()))?;
+
+        let mut stream = TcpStream::connect_timeout(&address, KEY_SERVICE_TIMEOUT)?;
+        stream.set_read_timeout(Some(KEY_SERVICE_TIMEOUT))?;
+        stream.set_write_timeout(Some(KEY_SERVICE_TIMEOUT))?;
+
+        // HTTP/1.0 so that the service closes the connection and never chunks the body
+        let mut request = SecretString::new(format!(
+            "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n",
+            self.path, self.authority
+        ));
+        if let Some(token) = &self.token {
+            request.expose_mut().push_str("Authorization: Bearer ");
+            request.expose_mut().push_str(token.expose());
+            request.expose_mut().push_str("\r\n");
+        }
+        request.expose_mut().push_str("\r\n");
+        stream.write_all(request.expose().as_bytes())?;
+
+        let mut response = SecretBytes::new(Vec::new());
+        stream.read_to_end(response.expose_mut())?;
+        Ok(response)
+    }
+}
+
+impl KeyProvider for HttpKeyProvider {
+    fn describe(&self) -> String {
+        format!("key service {}", self.url)
+    }
+
+    fn master_key(&self) -> Result<Option<SecretString>, KeyProviderError> {
+        let response = self.request()?;
+        let response = response.expose();
+
+        let header_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| KeyProviderError::Response("incomplete response".to_string()))?;
+        let status_line = response[..header_end]
+            .split(|byte| *byte == b'\n')
+            .next()
+            .unwrap_or_default();
+        let status = std::str::from_utf8(status_line)
+            .ok()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| KeyProviderError::Response("invalid status line".to_string()))?;
+        if !(200..300).contains(&status) {
+            return Err(KeyProviderError::Status(status));
+        }
+
+        let body: KeyServiceResponse = serde_json::from_slice(&response[header_end + 4..])
+            .map_err(|e| KeyProviderError::Response(e.to_string()))?;
+        if body.key.expose().is_empty() {
+            return Err(KeyProviderError::Response("empty key".to_string()));
+        }
+        Ok(Some(body.key))
+    }
+}
+
+/// Hands out a fixed key, or none. Stands in for a real provider in tests and embedders.
+#[derive(Debug, Default)]
+pub struct StaticKeyProvider {
+    key: Option<SecretString>,
+}
+
+impl StaticKeyProvider {
+    pub fn new(key: Option<&str>) -> Self {
+        Self {
+            key: key.map(|key| SecretString::new(key.to_string())),
+        }
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn describe(&self) -> String {
+        "static key".to_string()
+    }
+
+    fn master_key(&self) -> Result<Option<SecretString>, KeyProviderError> {
+        Ok(self.key.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Answers a single request with `response` and returns the request it received
+    fn key_service(response: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1/keys/ecs", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn keys_are_read_from_files_without_surrounding_whitespace() {
+        let path = std::env::temp_dir().join(format!("ecs-key-{}", std::process::id()));
+        std::fs::write(&path, "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=\n").unwrap();
+        let key = FileKeyProvider::new(&path).master_key().unwrap().unwrap();
+        assert_eq!(key.expose(), "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=");
+
+        std::fs::write(&path, " \n").unwrap();
+        assert!(matches!(
+            FileKeyProvider::new(&path).master_key(),
+            Err(KeyProviderError::EmptyFile(_))
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            FileKeyProvider::new(&path).master_key(),
+            Err(KeyProviderError::File { .. })
+        ));
+    }
+
+    #[test]
+    fn keys_are_fetched_from_the_key_service() {
+        let (url, service) = key_service(
+            "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"key\": \"c2VjcmV0\"}",
+        );
+        let provider =
+            HttpKeyProvider::new(&url, Some(SecretString::new("t0ken".to_string()))).unwrap();
+        let key = provider.master_key().unwrap().unwrap();
+        assert_eq!(key.expose(), "c2VjcmV0");
+
+        let request = service.join().unwrap();
+        assert!(request.starts_with("GET /v1/keys/ecs HTTP/1.0\r\n"));
+        assert!(request.contains("Authorization: Bearer t0ken\r\n"));
+    }
+
+    #[test]
+    fn key_service_errors_are_reported() {
+        let (url, service) = key_service("HTTP/1.0 403 Forbidden\r\n\r\n");
+        assert!(matches!(
+            HttpKeyProvider::new(&url, None).unwrap().master_key(),
+            Err(KeyProviderError::Status(403))
+        ));
+        service.join().unwrap();
+
+        assert!(HttpKeyProvider::new("https://kms.internal/keys", None).is_err());
+        assert!(HttpKeyProvider::new("http://", None).is_err());
+        assert!(StaticKeyProvider::new(None).master_key().unwrap().is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use base64::{engine::general_purpose, Engine as _};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::utils::envelope::{EnvelopeError, KeyEncryptionKey};
+use crate::utils::key_provider::{KeyProvider, KeyProviderError};
+use crate::utils::secret::{SecretBytes, SecretString};
+use crate::utils::vault::KEY_LENGTH;
+
+/*---------------------------------------------------------------------------
+    The keyring holds every master key the vault can decrypt with.
+
+    Exactly one key is active: new data keys are wrapped with it. Retired
+    keys are only used to read entries that have not been re-encrypted
+    yet. Each entry records the id of the key its data key is wrapped
+    with; entries written before key ids existed use [DEFAULT_KEY_ID].
+
+    Keys are configured through the environment:
+
+    ECS_ENCRYPTION_KEY           the active master key, unless it comes from
+                                 another key provider (see utils::key_provider)
+                                 or the vault is unsealed with key shares
+                                 (see repositories::seal)
+    ECS_ENCRYPTION_KEY_ID        its id (defaults to [DEFAULT_KEY_ID])
+    ECS_RETIRED_ENCRYPTION_KEYS  decrypt-only keys, as `id=key,id=key`
+---------------------------------------------------------------------------*/
+
+/// Id of the master key used before the keyring was introduced
+pub const DEFAULT_KEY_ID: &str = "default";
+
+#[derive(Error, Debug)]
+pub enum KeyringError {
+    #[error("cannot load the master key: {0}")]
+    Provider(#[from] KeyProviderError),
+    #[error("invalid retired key entry: {0}")]
+    InvalidEntry(String),
+    #[error("key id {0} is configured more than once")]
+    DuplicateId(String),
+    #[error("master key {0} is not a base64 encoded 256-bit key")]
+    InvalidKey(String),
+    #[error(transparent)]
+    Derive(#[from] EnvelopeError),
+}
+
+#[derive(Debug)]
+struct MasterKey {
+    // Entries written before envelope encryption are encrypted with the key itself
+    secret: SecretString,
+    kek: KeyEncryptionKey,
+}
+
+/// Prints the key ids only, the keys themselves are redacted
+#[derive(Debug)]
+pub struct Keyring {
+    active_id: String,
+    keys: BTreeMap<String, MasterKey>,
+}
+
+impl Keyring {
+    /// Builds the keyring, deriving the key-encryption key of every master key
+    pub fn new(
+        active_id: &str,
+        active_key: &str,
+        retired: Vec<(String, String)>,
+    ) -> Result<Self, KeyringError> {
+        let mut keys = BTreeMap::new();
+
+        for (id, secret) in
+            std::iter::once((active_id.to_string(), active_key.to_string())).chain(retired)
+        {
+            if keys.contains_key(&id) {
+                return Err(KeyringError::DuplicateId(id));
+            }
+            let secret = SecretString::new(secret);
+            let kek = KeyEncryptionKey::derive(secret.expose().as_bytes())?.with_id(&id);
+            keys.insert(id, MasterKey { secret, kek });
+        }
+
+        Ok(Self {
+            active_id: active_id.to_string(),
+            keys,
+        })
+    }
+
+    /// Builds the keyring around the key of `provider`, or returns `None` when it has no key
+    pub fn from_provider(provider: &dyn KeyProvider) -> Result<Option<Self>, KeyringError> {
+        match provider.master_key()? {
+            Some(active_key) => Self::with_active_key(active_key.expose()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the keyring around `active_key`, reading its id and the retired keys from the
+    /// environment. Every key must be a base64 encoded 256-bit key.
+    pub fn with_active_key(active_key: &str) -> Result<Self, KeyringError> {
+        let active_id = std::env::var("ECS_ENCRYPTION_KEY_ID")
+            .ok()
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_KEY_ID.to_string());
+        let retired = match std::env::var("ECS_RETIRED_ENCRYPTION_KEYS") {
+            Ok(value) => parse_retired_keys(&value)?,
+            Err(_) => Vec::new(),
+        };
+
+        validate_key(active_id.trim(), active_key)?;
+        for (id, key) in &retired {
+            validate_key(id, key)?;
+        }
+
+        Self::new(active_id.trim(), active_key, retired)
+    }
+
+    pub fn active_id(&self) -> &str {
+        &self.active_id
+    }
+
+    pub fn key_ids(&self) -> Vec<String> {
+        self.keys.keys().cloned().collect()
+    }
+
+    /// Whether an entry recording `key_id` is wrapped with the active key
+    pub fn is_active(&self, key_id: Option<&str>) -> bool {
+        key_id.unwrap_or(DEFAULT_KEY_ID) == self.active_id
+    }
+
+    pub fn active_kek(&self) -> &KeyEncryptionKey {
+        &self.keys[&self.active_id].kek
+    }
+
+    pub fn kek(&self, key_id: Option<&str>) -> Option<&KeyEncryptionKey> {
+        self.keys
+            .get(key_id.unwrap_or(DEFAULT_KEY_ID))
+            .map(|key| &key.kek)
+    }
+
+    /// The master key itself, needed to read entries written before envelope encryption
+    pub fn master_key(&self, key_id: Option<&str>) -> Option<&[u8]> {
+        self.keys
+            .get(key_id.unwrap_or(DEFAULT_KEY_ID))
+            .map(|key| key.secret.expose().as_bytes())
+    }
+}
+
+/// Parses `id=key,id=key`. Keys are base64 and may end with `=` padding.
+fn parse_retired_keys(value: &str) -> Result<Vec<(String, String)>, KeyringError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((id, key)) if !id.trim().is_empty() && !key.trim().is_empty() => {
+                Ok((id.trim().to_string(), key.trim().to_string()))
+            }
+            _ => Err(KeyringError::InvalidEntry(
+                entry.split('=').next().unwrap_or_default().to_string(),
+            )),
+        })
+        .collect()
+}
+
+/// Master keys are used as the input of the KEK derivation, see utils::envelope
+fn validate_key(id: &str, key: &str) -> Result<(), KeyringError> {
+    match general_purpose::STANDARD.decode(key).map(SecretBytes::new) {
+        Ok(decoded) if decoded.expose().len() == KEY_LENGTH => Ok(()),
+        _ => Err(KeyringError::InvalidKey(id.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::key_provider::StaticKeyProvider;
+
+    #[test]
+    fn retired_keys_are_parsed() {
+        let keys = parse_retired_keys("2024=IRwTgHBtmblSfAXpYOuvf4ZI=, 2023=abc").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                ("2024".to_string(), "IRwTgHBtmblSfAXpYOuvf4ZI=".to_string()),
+                ("2023".to_string(), "abc".to_string()),
+            ]
+        );
+        assert!(parse_retired_keys("2024").is_err());
+        assert!(parse_retired_keys("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn entries_without_a_key_id_use_the_default_key() {
+        let keyring = Keyring::new(
+            "2025",
+            "new",
+            vec![(DEFAULT_KEY_ID.to_string(), "old".to_string())],
+        )
+        .unwrap();
+
+        assert!(keyring.is_active(Some("2025")));
+        assert!(!keyring.is_active(None));
+        assert_eq!(keyring.master_key(None), Some(&b"old"[..]));
+        assert!(keyring.kek(Some("2019")).is_none());
+        assert!(Keyring::new("a", "x", vec![("a".to_string(), "y".to_string())]).is_err());
+    }
+
+    #[test]
+    fn the_active_key_comes_from_the_provider() {
+        let provided = "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=";
+        let keyring = Keyring::from_provider(&StaticKeyProvider::new(Some(provided)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            keyring.master_key(Some(keyring.active_id())),
+            Some(provided.as_bytes())
+        );
+        assert!(matches!(
+            Keyring::from_provider(&StaticKeyProvider::new(Some("provided"))),
+            Err(KeyringError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            Keyring::from_provider(&StaticKeyProvider::new(Some("IRwTgHBtmblSfAXpYOuvf4ZI="))),
+            Err(KeyringError::InvalidKey(_))
+        ));
+        assert!(Keyring::from_provider(&StaticKeyProvider::new(None))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+/*---------------------------------------------------------------------------
+    Secrets carry free-form `name=value` labels. Names may only contain
+    ASCII letters, digits, `_`, `-` and `/` so they can be used as document
+    field names by every storage backend; values may also contain `.`.
+
+    Listings filter on label selectors: comma separated requirements such
+    as `env=prod,team!=legacy`. `name` requires the label to be present,
+    `!name` requires it to be absent, and `name!=value` also matches
+    secrets without the label.
+---------------------------------------------------------------------------*/
+
+/// Longest accepted label name or value
+pub const MAX_LABEL_LENGTH: usize = 63;
+/// Largest number of labels on a single secret
+pub const MAX_LABELS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+    DoesNotExist(String),
+}
+
+impl Requirement {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            Requirement::Equals(name, value) => labels.get(name) == Some(value),
+            Requirement::NotEquals(name, value) => labels.get(name) != Some(value),
+            Requirement::Exists(name) => labels.contains_key(name),
+            Requirement::DoesNotExist(name) => !labels.contains_key(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector {
+    pub requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    /// Parses a selector such as `env=prod,team!=legacy`
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        let mut requirements = Vec::new();
+
+        for requirement in selector.split(',').map(str::trim) {
+            if requirement.is_empty() {
+                continue;
+            }
+
+            let parsed = if let Some((name, value)) = requirement.split_once("!=") {
+                Requirement::NotEquals(name.trim().to_string(), value.trim().to_string())
+            } else if let Some((name, value)) = requirement
+                .split_once("==")
+                .or_else(|| requirement.split_once('='))
+            {
+                Requirement::Equals(name.trim().to_string(), value.trim().to_string())
+            } else if let Some(name) = requirement.strip_prefix('!') {
+                Requirement::DoesNotExist(name.trim().to_string())
+            } else {
+                Requirement::Exists(requirement.to_string())
+            };
+
+            let (name, value) = match &parsed {
+                Requirement::Equals(name, value) | Requirement::NotEquals(name, value) => {
+                    (name, Some(value))
+                }
+                Requirement::Exists(name) | Requirement::DoesNotExist(name) => (name, None),
+            };
+            if !is_valid_name(name) || !value.is_none_or(|value| is_valid_value(value)) {
+                return Err(format!("Invalid label selector: {}", requirement));
+            }
+
+            requirements.push(parsed);
+        }
+
+        Ok(Self { requirements })
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.matches(labels))
+    }
+}
+
+/// Checks the labels supplied for a secret, returning a message describing the first problem
+pub fn validate_labels(labels: &BTreeMap<String, String>) -> Result<(), String> {
+    if labels.len() > MAX_LABELS {
+        return Err(format!("A secret may carry at most {} labels", MAX_LABELS));
+    }
+
+    for (name, value) in labels {
+        if !is_valid_name(name) {
+            return Err(format!("Invalid label name: {}", name));
+        }
+        if !is_valid_value(value) {
+            return Err(format!("Invalid value for label {}: {}", name, value));
+        }
+    }
+
+    Ok(())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_LABEL_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '/'))
+}
+
+fn is_valid_value(value: &str) -> bool {
+    value.len() <= MAX_LABEL_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '/' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn selectors_are_parsed_and_matched() {
+        let selector = LabelSelector::parse("env=prod, team!=legacy,owner,!deprecated").unwrap();
+        assert_eq!(
+            selector.requirements,
+            vec![
+                Requirement::Equals("env".to_string(), "prod".to_string()),
+                Requirement::NotEquals("team".to_string(), "legacy".to_string()),
+                Requirement::Exists("owner".to_string()),
+                Requirement::DoesNotExist("deprecated".to_string()),
+            ]
+        );
+
+        assert!(selector.matches(&labels(&[("env", "prod"), ("owner", "data")])));
+        assert!(selector.matches(&labels(&[
+            ("env", "prod"),
+            ("owner", "data"),
+            ("team", "data")
+        ])));
+        assert!(!selector.matches(&labels(&[
+            ("env", "prod"),
+            ("owner", "data"),
+            ("team", "legacy")
+        ])));
+        assert!(!selector.matches(&labels(&[("env", "staging"), ("owner", "data")])));
+        assert!(!selector.matches(&labels(&[
+            ("env", "prod"),
+            ("owner", "data"),
+            ("deprecated", "")
+        ])));
+
+        assert!(LabelSelector::parse("").unwrap().requirements.is_empty());
+        assert!(LabelSelector::parse("env=pr od").is_err());
+        assert!(LabelSelector::parse("$where=1").is_err());
+    }
+
+    #[test]
+    fn labels_are_validated() {
+        assert!(validate_labels(&labels(&[("env", "prod"), ("team", "data.eng")])).is_ok());
+        assert!(validate_labels(&labels(&[("env.name", "prod")])).is_err());
+        assert!(validate_labels(&labels(&[("", "prod")])).is_err());
+        assert!(validate_labels(&labels(&[("env", "prod!")])).is_err());
+    }
+}
@@ -1,3 +1,14 @@
+pub mod envelope;
+pub mod generator;
 pub mod hashing;
+pub mod kdf;
+pub mod key_provider;
+pub mod keyring;
+pub mod labels;
+pub mod pagination;
+pub mod paths;
+pub mod secret;
+pub mod shamir;
+pub mod stream;
 pub mod token;
 pub mod vault;
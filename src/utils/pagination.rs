@@ -0,0 +1,80 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::storage::PageRequest;
+
+/// Page size used when a listing request does not specify a limit
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+/// Largest page a single listing request may ask for
+pub const MAX_PAGE_SIZE: u64 = 500;
+
+/*---------------------------------------------------------------------------
+    Listings are paginated by offset. The offset is handed to clients as an
+    opaque page token so the scheme can change without breaking them.
+---------------------------------------------------------------------------*/
+
+/// Builds the page to fetch from the optional `limit` and `page_token` of a request
+pub fn page_request(limit: Option<u64>, page_token: Option<&str>) -> Option<PageRequest> {
+    let offset = match page_token {
+        Some(token) => decode_token(token)?,
+        None => 0,
+    };
+
+    Some(PageRequest {
+        offset,
+        limit: limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+    })
+}
+
+/// The token of the page following `page`, if any records remain
+pub fn next_page_token(page: PageRequest, returned: usize, total: u64) -> Option<String> {
+    let next = page.offset.saturating_add(returned as u64);
+    (returned > 0 && next < total).then(|| URL_SAFE_NO_PAD.encode(next.to_string()))
+}
+
+fn decode_token(token: &str) -> Option<u64> {
+    let bytes = URL_SAFE_NO_PAD.decode(token).ok()?;
+    String::from_utf8(bytes).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_tokens_round_trip() {
+        let first = page_request(Some(2), None).unwrap();
+        assert_eq!(
+            first,
+            PageRequest {
+                offset: 0,
+                limit: 2
+            }
+        );
+
+        let token = next_page_token(first, 2, 5).unwrap();
+        let second = page_request(Some(2), Some(&token)).unwrap();
+        assert_eq!(
+            second,
+            PageRequest {
+                offset: 2,
+                limit: 2
+            }
+        );
+
+        assert!(next_page_token(
+            PageRequest {
+                offset: 4,
+                limit: 2
+            },
+            1,
+            5
+        )
+        .is_none());
+        assert!(page_request(None, Some("not a token")).is_none());
+        assert_eq!(page_request(Some(0), None).unwrap().limit, 1);
+        assert_eq!(
+            page_request(Some(10_000), None).unwrap().limit,
+            MAX_PAGE_SIZE
+        );
+    }
+}
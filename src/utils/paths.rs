@@ -0,0 +1,113 @@
+/*---------------------------------------------------------------------------
+    Secrets are addressed by hierarchical, path-style keys such as
+    `payments/prod/stripe_api_key`. Segments are separated by `/` and may
+    only contain ASCII letters, digits, `_`, `-` and `.` (but may not be
+    `.` or `..`). Every path is unique per owner.
+---------------------------------------------------------------------------*/
+
+/// Validates a secret path, returning it without leading or trailing slashes
+pub fn normalize_path(path: &str) -> Option<String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    if trimmed.split('/').all(is_valid_segment) {
+        Some(trimmed.to_string())
+    } else {
+        None
+    }
+}
+
+/// Validates a listing prefix. An empty prefix lists the root of the namespace.
+/// The returned prefix is either empty or ends with a `/`.
+pub fn normalize_prefix(prefix: &str) -> Option<String> {
+    if prefix.trim_matches('/').is_empty() {
+        return Some(String::new());
+    }
+
+    normalize_path(prefix).map(|prefix| format!("{}/", prefix))
+}
+
+/// Longest accepted name of a transit or signing key
+pub const MAX_NAME_LENGTH: usize = 128;
+
+/// Whether `name` can name a transit or signing key. Names appear in URLs, so they follow
+/// the rules of a single path segment.
+pub fn is_valid_name(name: &str) -> bool {
+    name.len() <= MAX_NAME_LENGTH && is_valid_segment(name)
+}
+
+fn is_valid_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment != "."
+        && segment != ".."
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Splits the keys found under `prefix` into its direct child folders and keys
+pub fn children<'a>(
+    prefix: &str,
+    keys: impl IntoIterator<Item = &'a str>,
+) -> (Vec<String>, Vec<String>) {
+    let mut folders = Vec::new();
+    let mut leaves = Vec::new();
+
+    for key in keys {
+        let Some(rest) = key.strip_prefix(prefix) else {
+            continue;
+        };
+
+        match rest.split_once('/') {
+            Some((folder, _)) => folders.push(folder.to_string()),
+            None => leaves.push(rest.to_string()),
+        }
+    }
+
+    folders.sort();
+    folders.dedup();
+    leaves.sort();
+    leaves.dedup();
+
+    (folders, leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_validated() {
+        assert_eq!(
+            normalize_path("/payments/prod/stripe_api_key/").as_deref(),
+            Some("payments/prod/stripe_api_key")
+        );
+        assert_eq!(normalize_path("test").as_deref(), Some("test"));
+        assert!(normalize_path("").is_none());
+        assert!(normalize_path("payments//prod").is_none());
+        assert!(normalize_path("payments/../prod").is_none());
+        assert!(normalize_path("payments/prod key").is_none());
+        assert_eq!(normalize_prefix("/").as_deref(), Some(""));
+        assert_eq!(normalize_prefix("payments").as_deref(), Some("payments/"));
+    }
+
+    #[test]
+    fn children_are_split_into_folders_and_keys() {
+        let keys = [
+            "payments/prod/stripe_api_key",
+            "payments/prod/db/password",
+            "payments/prod/db/user",
+            "payments/staging/stripe_api_key",
+        ];
+
+        let (folders, leaves) = children("payments/prod/", keys);
+        assert_eq!(folders, vec!["db"]);
+        assert_eq!(leaves, vec!["stripe_api_key"]);
+
+        let (folders, leaves) = children("", keys);
+        assert_eq!(folders, vec!["payments"]);
+        assert!(leaves.is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use crate::utils::vault::KEY_LENGTH;
+
+/*---------------------------------------------------------------------------
+    Secret-bearing values.
+
+    Keys, Argon2 outputs and decrypted values are held in a [Secret],
+    which overwrites its memory with zeroes when dropped and never prints
+    its contents through `Debug`. Borrow the value with [Secret::expose]
+    for as short as possible: copies taken out of it are not wiped.
+---------------------------------------------------------------------------*/
+
+/// A value wiped from memory on drop and redacted from `Debug` output
+pub struct Secret<T: Zeroize>(T);
+
+/// A raw 256-bit key
+pub type SecretKey = Secret<[u8; KEY_LENGTH]>;
+pub type SecretBytes = Secret<Vec<u8>>;
+pub type SecretString = Secret<String>;
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn expose_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize + Default> Secret<T> {
+    /// Moves the value out without copying it; the caller becomes responsible for it
+    pub fn into_exposed(mut self) -> T {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl SecretBytes {
+    /// Converts decrypted bytes to a string in place, replacing invalid UTF-8 sequences
+    /// like `String::from_utf8_lossy`
+    pub fn into_string(self) -> SecretString {
+        match String::from_utf8(self.into_exposed()) {
+            Ok(string) => Secret::new(string),
+            Err(e) => {
+                let mut bytes = e.into_bytes();
+                let string = String::from_utf8_lossy(&bytes).into_owned();
+                bytes.zeroize();
+                Secret::new(string)
+            }
+        }
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Deserializes straight into a secret, for keys received from a key service
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret::new)
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted_and_converted_without_loss() {
+        let secret = SecretString::new("hunter2".to_string());
+        assert_eq!(format!("{:?}", secret), "Secret([REDACTED])");
+        assert_eq!(secret.expose(), "hunter2");
+
+        let bytes = SecretBytes::new(b"caf\xc3\xa9".to_vec());
+        assert_eq!(bytes.into_string().expose(), "café");
+        let invalid = SecretBytes::new(b"ok\xff".to_vec());
+        assert_eq!(invalid.into_string().expose(), "ok\u{fffd}");
+    }
+}
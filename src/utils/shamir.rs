@@ -0,0 +1,190 @@
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use thiserror::Error;
+use zeroize::Zeroize;
+
+/*---------------------------------------------------------------------------
+    Shamir's secret sharing over GF(256).
+
+    Every byte of the secret is the constant term of its own random
+    polynomial of degree `threshold - 1`. A share holds the x coordinate
+    (1 to 255) followed by the polynomials evaluated at x, so any
+    `threshold` shares recover the secret by Lagrange interpolation at 0
+    and fewer reveal nothing about it.
+
+    Field arithmetic uses the AES polynomial x^8 + x^4 + x^3 + x + 1 and
+    avoids lookup tables so that timings do not depend on the shares.
+---------------------------------------------------------------------------*/
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShamirError {
+    #[error("the threshold must be between 1 and the number of shares, at most 255")]
+    InvalidThreshold,
+    #[error("the secret to split is empty")]
+    EmptySecret,
+    #[error("a share is malformed")]
+    InvalidShare,
+    #[error("the same share was supplied twice")]
+    DuplicateShare,
+    #[error("the shares do not belong to the same secret")]
+    MismatchedShares,
+}
+
+/// Splits `secret` into `shares` shares, any `threshold` of which recover it
+pub fn split(secret: &[u8], shares: u8, threshold: u8) -> Result<Vec<Vec<u8>>, ShamirError> {
+    if threshold == 0 || threshold > shares {
+        return Err(ShamirError::InvalidThreshold);
+    }
+    if secret.is_empty() {
+        return Err(ShamirError::EmptySecret);
+    }
+
+    let mut result: Vec<Vec<u8>> = (1..=shares)
+        .map(|x| {
+            let mut share = Vec::with_capacity(secret.len() + 1);
+            share.push(x);
+            share
+        })
+        .collect();
+
+    let mut coefficients = vec![0u8; usize::from(threshold)];
+    for &byte in secret {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+
+        for share in result.iter_mut() {
+            let x = share[0];
+            // Horner's method, highest degree first
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0u8, |acc, &coefficient| mul(acc, x) ^ coefficient);
+            share.push(y);
+        }
+    }
+    coefficients.zeroize();
+
+    Ok(result)
+}
+
+/// Recovers the secret from at least `threshold` shares. With fewer shares the
+/// result is a wrong secret rather than an error, so callers must verify it.
+pub fn combine(shares: &[Vec<u8>]) -> Result<Vec<u8>, ShamirError> {
+    let Some(first) = shares.first() else {
+        return Err(ShamirError::InvalidShare);
+    };
+    if first.len() < 2 {
+        return Err(ShamirError::InvalidShare);
+    }
+    for (index, share) in shares.iter().enumerate() {
+        if share.len() != first.len() {
+            return Err(ShamirError::MismatchedShares);
+        }
+        if share[0] == 0 {
+            return Err(ShamirError::InvalidShare);
+        }
+        if shares[..index].iter().any(|other| other[0] == share[0]) {
+            return Err(ShamirError::DuplicateShare);
+        }
+    }
+
+    // Lagrange basis polynomials evaluated at 0
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            let (numerator, denominator) = shares.iter().filter(|other| other[0] != share[0]).fold(
+                (1u8, 1u8),
+                |(numerator, denominator), other| {
+                    (
+                        mul(numerator, other[0]),
+                        mul(denominator, other[0] ^ share[0]),
+                    )
+                },
+            );
+            mul(numerator, inverse(denominator))
+        })
+        .collect();
+
+    Ok((1..first.len())
+        .map(|position| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0u8, |acc, (share, &weight)| {
+                    acc ^ mul(share[position], weight)
+                })
+        })
+        .collect())
+}
+
+fn mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// a^254, the multiplicative inverse of a non-zero element
+fn inverse(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut power = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul(result, power);
+        }
+        power = mul(power, power);
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_threshold_shares_recover_the_secret() {
+        let secret = b"0123456789abcdef0123456789abcdef".to_vec();
+        let shares = split(&secret, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<Vec<u8>> = picked.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine(&subset).unwrap(), secret);
+        }
+        assert_eq!(combine(&shares).unwrap(), secret);
+        assert_ne!(combine(&shares[..2]).unwrap(), secret);
+
+        let single = split(&secret, 1, 1).unwrap();
+        assert_eq!(combine(&single).unwrap(), secret);
+    }
+
+    #[test]
+    fn invalid_input_is_rejected() {
+        assert_eq!(split(b"key", 2, 3), Err(ShamirError::InvalidThreshold));
+        assert_eq!(split(b"key", 2, 0), Err(ShamirError::InvalidThreshold));
+        assert_eq!(split(b"", 2, 2), Err(ShamirError::EmptySecret));
+
+        let shares = split(b"key", 3, 2).unwrap();
+        assert_eq!(
+            combine(&[shares[0].clone(), shares[0].clone()]),
+            Err(ShamirError::DuplicateShare)
+        );
+        assert_eq!(
+            combine(&[shares[0].clone(), shares[1][..2].to_vec()]),
+            Err(ShamirError::MismatchedShares)
+        );
+        assert_eq!(combine(&[]), Err(ShamirError::InvalidShare));
+    }
+
+    #[test]
+    fn field_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, inverse(a)), 1);
+        }
+    }
+}
@@ -0,0 +1,729 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::Aes256Gcm;
+use bincode::Options;
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, rand_core::RngCore, Aead, OsRng, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use log::{info, trace};
+use serde_derive::{Deserialize, Serialize};
+use tar::{Archive, Builder};
+use thiserror::Error;
+
+use crate::utils::kdf;
+use crate::utils::vault::{
+    CipherId, DecryptError, EncryptError, EncryptOptions, Header, KdfId, KEY_LENGTH, MAGIC,
+    STREAM_FORMAT_VERSION,
+};
+
+/*---------------------------------------------------------------------------
+    Streaming encryption.
+
+    The functions of utils::vault hold the whole plaintext and ciphertext
+    in memory. Here the data is cut into chunks of [CHUNK_SIZE] bytes,
+    each encrypted and authenticated on its own, so files of any size are
+    processed with constant memory (the STREAM construction):
+
+    [MAGIC] [STREAM_FORMAT_VERSION] [StreamHeader] [chunk] [chunk] ...
+
+    The nonce of a chunk is a random prefix drawn per stream, the index
+    of the chunk and a flag set on the last chunk only. Chunks therefore
+    cannot be reordered, dropped or moved to another stream, and a stream
+    cut at a chunk boundary fails to decrypt instead of yielding a valid
+    prefix. The last chunk is always shorter than [CHUNK_SIZE], possibly
+    empty, which is how the decryptor recognizes it.
+
+    Plaintext is released chunk by chunk: a consumer of [StreamDecryptor]
+    may have seen the first chunks of a stream that later turns out to be
+    truncated. The file functions below write to a temporary file and only
+    move it into place once the whole stream has been authenticated.
+---------------------------------------------------------------------------*/
+
+/// Plaintext bytes per chunk of new streams
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Largest chunk accepted when decrypting, bounds the memory a stream can claim
+const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+const TAG_LENGTH: usize = 16;
+const NONCE_PREFIX_LENGTH: usize = 7;
+/// Upper bound of the encoded [StreamHeader]
+const MAX_HEADER_LENGTH: u64 = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct StreamHeader {
+    header: Header,
+    chunk_size: u32,
+    nonce_prefix: [u8; NONCE_PREFIX_LENGTH],
+}
+
+#[derive(Error, Debug)]
+pub enum StreamError {
+    #[error("error reading or writing the stream: {0}")]
+    Io(io::Error),
+    #[error("error encrypting the stream: {0}")]
+    Encrypt(EncryptError),
+    #[error("error decrypting the stream: {0}")]
+    Decrypt(DecryptError),
+    #[error("no filename found for path")]
+    NoFilename,
+}
+
+impl From<io::Error> for StreamError {
+    /// Unwraps the cipher errors surfaced through [Read] and [Write]
+    fn from(e: io::Error) -> Self {
+        let is_cipher_error = e
+            .get_ref()
+            .is_some_and(|inner| inner.is::<DecryptError>() || inner.is::<EncryptError>());
+        if !is_cipher_error {
+            return StreamError::Io(e);
+        }
+        match e
+            .into_inner()
+            .expect("checked above")
+            .downcast::<DecryptError>()
+        {
+            Ok(inner) => StreamError::Decrypt(*inner),
+            Err(inner) => {
+                StreamError::Encrypt(*inner.downcast::<EncryptError>().expect("checked above"))
+            }
+        }
+    }
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Whether the data starts like a stream, as opposed to a ciphertext of utils::vault
+pub fn is_stream(data: &[u8]) -> bool {
+    data.strip_prefix(MAGIC.as_slice())
+        .and_then(|rest| rest.first())
+        == Some(&STREAM_FORMAT_VERSION)
+}
+
+/// Whether the file holds a stream, reading only its first bytes
+pub fn is_stream_file(path: &Path) -> io::Result<bool> {
+    let mut prefix = Vec::with_capacity(MAGIC.len() + 1);
+    File::open(path)?
+        .take(MAGIC.len() as u64 + 1)
+        .read_to_end(&mut prefix)?;
+    Ok(is_stream(&prefix))
+}
+
+/// The bincode configuration of `bincode::serialize`, with a size limit for untrusted input
+fn header_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(MAX_HEADER_LENGTH)
+}
+
+enum ChunkCipher {
+    ChaCha20Poly1305(ChaCha20Poly1305),
+    Aes256Gcm(Box<Aes256Gcm>),
+}
+
+/// Encrypts and decrypts the chunks of one stream, in order
+struct Chunks {
+    cipher: ChunkCipher,
+    aad: Vec<u8>,
+    nonce_prefix: [u8; NONCE_PREFIX_LENGTH],
+    // None once every index has been used
+    index: Option<u32>,
+}
+
+impl Chunks {
+    fn new(header: &StreamHeader, key: &[u8; KEY_LENGTH]) -> Result<Self, bincode::Error> {
+        let key = GenericArray::from_slice(key);
+        let cipher = match header.header.cipher {
+            CipherId::ChaCha20Poly1305 => ChunkCipher::ChaCha20Poly1305(ChaCha20Poly1305::new(key)),
+            CipherId::Aes256Gcm => ChunkCipher::Aes256Gcm(Box::new(Aes256Gcm::new(key))),
+        };
+        // Every chunk authenticates the magic, version and header of its stream
+        let mut aad = MAGIC.to_vec();
+        aad.push(STREAM_FORMAT_VERSION);
+        aad.extend(bincode::serialize(header)?);
+        Ok(Self {
+            cipher,
+            aad,
+            nonce_prefix: header.nonce_prefix,
+            index: Some(0),
+        })
+    }
+
+    fn nonce(&mut self, last: bool) -> Result<[u8; 12], chacha20poly1305::Error> {
+        let index = self.index.ok_or(chacha20poly1305::Error)?;
+        self.index = index.checked_add(1);
+
+        let mut nonce = [0u8; 12];
+        nonce[..NONCE_PREFIX_LENGTH].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LENGTH..11].copy_from_slice(&index.to_be_bytes());
+        nonce[11] = u8::from(last);
+        Ok(nonce)
+    }
+
+    fn seal(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, chacha20poly1305::Error> {
+        let nonce = self.nonce(last)?;
+        let payload = Payload {
+            msg: chunk,
+            aad: &self.aad,
+        };
+        let nonce = Nonce::from_slice(&nonce);
+        match &self.cipher {
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.encrypt(nonce, payload),
+            ChunkCipher::Aes256Gcm(cipher) => cipher.encrypt(nonce, payload),
+        }
+    }
+
+    fn open(&mut self, chunk: &[u8], last: bool) -> Result<Vec<u8>, chacha20poly1305::Error> {
+        let nonce = self.nonce(last)?;
+        let payload = Payload {
+            msg: chunk,
+            aad: &self.aad,
+        };
+        let nonce = Nonce::from_slice(&nonce);
+        match &self.cipher {
+            ChunkCipher::ChaCha20Poly1305(cipher) => cipher.decrypt(nonce, payload),
+            ChunkCipher::Aes256Gcm(cipher) => cipher.decrypt(nonce, payload),
+        }
+    }
+}
+
+/// Encrypts everything written to it into the inner writer. [StreamEncryptor::finish]
+/// must be called once all the data is written, otherwise the stream is truncated.
+pub struct StreamEncryptor<W: Write> {
+    inner: W,
+    chunks: Chunks,
+    chunk_size: usize,
+    // Plaintext not yet encrypted, always shorter than a chunk
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> StreamEncryptor<W> {
+    /// Derives the key from `encryption_key` and writes the header of the stream
+    pub fn new(
+        inner: W,
+        encryption_key: &[u8],
+        options: &EncryptOptions,
+    ) -> Result<Self, StreamError> {
+        Self::with_chunk_size(inner, encryption_key, options, CHUNK_SIZE)
+    }
+
+    fn with_chunk_size(
+        mut inner: W,
+        encryption_key: &[u8],
+        options: &EncryptOptions,
+        chunk_size: usize,
+    ) -> Result<Self, StreamError> {
+        trace!("Generating salt");
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LENGTH];
+        OsRng.fill_bytes(&mut nonce_prefix);
+
+        trace!("Generating key");
+        let key = kdf::derive_key(encryption_key, &salt, &options.params)
+            .map_err(|e| StreamError::Encrypt(EncryptError::Hashing(e)))?;
+
+        let header = StreamHeader {
+            header: Header {
+                cipher: options.cipher,
+                kdf: KdfId::Argon2id {
+                    params: options.params,
+                    salt,
+                },
+                key_id: None,
+            },
+            chunk_size: chunk_size as u32,
+            nonce_prefix,
+        };
+        let chunks = Chunks::new(&header, key.expose())
+            .map_err(|e| StreamError::Encrypt(EncryptError::Serialize(e)))?;
+
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[STREAM_FORMAT_VERSION])?;
+        bincode::serialize_into(&mut inner, &header)
+            .map_err(|e| StreamError::Encrypt(EncryptError::Serialize(e)))?;
+
+        Ok(Self {
+            inner,
+            chunks,
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size),
+        })
+    }
+
+    /// Encrypts the remaining data as the last chunk and returns the inner writer
+    pub fn finish(mut self) -> Result<W, StreamError> {
+        let chunk = self
+            .chunks
+            .seal(&self.buffer, true)
+            .map_err(|e| StreamError::Encrypt(EncryptError::Cipher(e)))?;
+        self.inner.write_all(&chunk)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for StreamEncryptor<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let taken = data.len().min(self.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..taken]);
+        // A full chunk is never the last one, finish seals the remainder
+        if self.buffer.len() == self.chunk_size {
+            let chunk = self
+                .chunks
+                .seal(&self.buffer, false)
+                .map_err(|e| invalid_data(EncryptError::Cipher(e)))?;
+            self.inner.write_all(&chunk)?;
+            self.buffer.clear();
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a stream read from the inner reader. Reading returns an error of kind
+/// [io::ErrorKind::InvalidData] wrapping a [DecryptError] when a chunk fails to
+/// authenticate or the stream is truncated.
+pub struct StreamDecryptor<R: Read> {
+    inner: R,
+    chunks: Chunks,
+    ciphertext: Vec<u8>,
+    // Plaintext of the current chunk and how much of it was read
+    plaintext: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> StreamDecryptor<R> {
+    /// Reads the header of the stream and decrypts the first chunk, so a wrong
+    /// encryption key is reported here rather than on the first read
+    pub fn new(mut inner: R, encryption_key: &[u8]) -> Result<Self, StreamError> {
+        let mut prefix = [0u8; 5];
+        inner.read_exact(&mut prefix).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => StreamError::Decrypt(DecryptError::Truncated),
+            _ => StreamError::Io(e),
+        })?;
+        if !prefix.starts_with(MAGIC) {
+            return Err(StreamError::Decrypt(DecryptError::NotAStream));
+        }
+        if prefix[MAGIC.len()] != STREAM_FORMAT_VERSION {
+            return Err(StreamError::Decrypt(DecryptError::UnsupportedVersion(
+                prefix[MAGIC.len()],
+            )));
+        }
+
+        trace!("Decoding");
+        let header: StreamHeader = header_options()
+            .deserialize_from(&mut inner)
+            .map_err(|e| StreamError::Decrypt(DecryptError::Deserialize(e)))?;
+        if header.chunk_size == 0 || header.chunk_size > MAX_CHUNK_SIZE {
+            return Err(StreamError::Decrypt(DecryptError::InvalidChunkSize(
+                header.chunk_size,
+            )));
+        }
+        let KdfId::Argon2id { params, salt } = &header.header.kdf else {
+            return Err(StreamError::Decrypt(DecryptError::RawKeyExpected));
+        };
+        if !params.is_acceptable() {
+            return Err(StreamError::Decrypt(DecryptError::ExcessiveCost(*params)));
+        }
+
+        trace!("Generating key");
+        let key = kdf::derive_key(encryption_key, salt, params)
+            .map_err(|e| StreamError::Decrypt(DecryptError::Hashing(e)))?;
+        let chunks = Chunks::new(&header, key.expose())
+            .map_err(|e| StreamError::Decrypt(DecryptError::Deserialize(e)))?;
+
+        let mut decryptor = Self {
+            inner,
+            chunks,
+            ciphertext: vec![0u8; header.chunk_size as usize + TAG_LENGTH],
+            plaintext: Vec::new(),
+            position: 0,
+            finished: false,
+        };
+        decryptor.next_chunk()?;
+        Ok(decryptor)
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let read = read_full(&mut self.inner, &mut self.ciphertext)?;
+        if read < TAG_LENGTH {
+            return Err(invalid_data(DecryptError::Truncated));
+        }
+        // Only the last chunk is shorter than a full one
+        let last = read < self.ciphertext.len();
+        self.plaintext = self
+            .chunks
+            .open(&self.ciphertext[..read], last)
+            .map_err(|e| invalid_data(DecryptError::Cipher(e)))?;
+        self.position = 0;
+        self.finished = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StreamDecryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Every chunk but the last holds data, so one refill is enough
+        if self.position == self.plaintext.len() && !self.finished {
+            self.next_chunk()?;
+        }
+        let read = buf.len().min(self.plaintext.len() - self.position);
+        buf[..read].copy_from_slice(&self.plaintext[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Reads until the buffer is full or the reader is exhausted
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+/// Encrypts everything read from `reader` into `writer` as a stream
+///
+/// # Examples
+///
+/// ```
+/// use ec_secrets_management::utils::stream::{decrypt_stream, encrypt_stream};
+/// use ec_secrets_management::utils::vault::EncryptOptions;
+///
+/// let mut encrypted_data = Vec::new();
+/// encrypt_stream(&mut &b"example text"[..], &mut encrypted_data, b"encryption key", &EncryptOptions::configured())
+///     .expect("Failed to encrypt");
+///
+/// let mut data = Vec::new();
+/// decrypt_stream(&encrypted_data[..], &mut data, b"encryption key").expect("Failed to decrypt");
+/// assert_eq!(data, b"example text");
+/// ```
+///
+pub fn encrypt_stream<R: Read, W: Write>(
+    reader: &mut R,
+    writer: W,
+    encryption_key: &[u8],
+    options: &EncryptOptions,
+) -> Result<W, StreamError> {
+    let mut encryptor = StreamEncryptor::new(writer, encryption_key, options)?;
+    info!("Encrypting");
+    io::copy(reader, &mut encryptor)?;
+    encryptor.finish()
+}
+
+/// Decrypts a stream read from `reader` into `writer`
+pub fn decrypt_stream<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    encryption_key: &[u8],
+) -> Result<(), StreamError> {
+    let mut decryptor = StreamDecryptor::new(reader, encryption_key)?;
+    info!("Decrypting");
+    io::copy(&mut decryptor, writer)?;
+    Ok(())
+}
+
+/// Sibling of `output_path` the output is written to before being moved into place,
+/// which also allows encrypting or decrypting a file in place
+fn partial_path(output_path: &Path) -> PathBuf {
+    let mut name = output_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    output_path.with_file_name(name)
+}
+
+/// Runs `write` against a temporary file and moves it to `output_path` on success
+fn write_atomically<F>(output_path: &Path, write: F) -> Result<(), StreamError>
+where
+    F: FnOnce(BufWriter<File>) -> Result<BufWriter<File>, StreamError>,
+{
+    let partial = partial_path(output_path);
+    let result = File::create(&partial)
+        .map_err(StreamError::Io)
+        .and_then(|file| write(BufWriter::new(file)))
+        .and_then(|writer| {
+            writer
+                .into_inner()
+                .map_err(|e| StreamError::Io(e.into_error()))?
+                .sync_all()
+                .map_err(StreamError::Io)
+        })
+        .and_then(|()| fs::rename(&partial, output_path).map_err(StreamError::Io));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Encrypts a file of any size as a stream, with constant memory
+///
+/// # Examples
+///
+/// ```no_run
+/// use ec_secrets_management::utils::stream::encrypt_file;
+/// use ec_secrets_management::utils::vault::EncryptOptions;
+/// use std::path::Path;
+///
+/// encrypt_file(Path::new("dump.sql"), Path::new("dump.sql.enc"), b"encryption key", &EncryptOptions::configured())
+///     .expect("Failed to encrypt the file");
+/// // utils::vault::decrypt_file and this module's decrypt_file both decrypt it
+/// ```
+///
+pub fn encrypt_file(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+    options: &EncryptOptions,
+) -> Result<(), StreamError> {
+    let mut reader = BufReader::new(File::open(path).map_err(StreamError::Io)?);
+    write_atomically(output_path, |writer| {
+        encrypt_stream(&mut reader, writer, encryption_key, options)
+    })
+}
+
+/// Decrypts a file produced by [encrypt_file]. The output only appears once the whole
+/// stream has been authenticated.
+pub fn decrypt_file(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+) -> Result<(), StreamError> {
+    let reader = BufReader::new(File::open(path).map_err(StreamError::Io)?);
+    write_atomically(output_path, |mut writer| {
+        decrypt_stream(reader, &mut writer, encryption_key)?;
+        Ok(writer)
+    })
+}
+
+/// Encrypts a directory as a stream of its tar archive, with constant memory
+pub fn encrypt_directory(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+    options: &EncryptOptions,
+) -> Result<(), StreamError> {
+    let name = path.file_name().ok_or(StreamError::NoFilename)?;
+    write_atomically(output_path, |writer| {
+        let encryptor = StreamEncryptor::new(writer, encryption_key, options)?;
+        let mut archive = Builder::new(encryptor);
+
+        trace!("Adding folder to file");
+        archive.append_dir_all(name, path)?;
+        archive.into_inner()?.finish()
+    })
+}
+
+/// Decrypts a directory produced by [encrypt_directory] and extracts it into `output_path`
+///
+/// Entries are extracted as they are decrypted: if the stream turns out to be truncated
+/// or tampered with, an error is returned but the entries extracted so far remain.
+pub fn decrypt_directory(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+) -> Result<(), StreamError> {
+    let reader = BufReader::new(File::open(path).map_err(StreamError::Io)?);
+    let mut decryptor = StreamDecryptor::new(reader, encryption_key)?;
+
+    trace!("Extracting file");
+    let mut archive = Archive::new(&mut decryptor);
+    archive.unpack(output_path)?;
+    // The archive ends before the stream does, read the rest to authenticate it
+    io::copy(&mut decryptor, &mut io::sink())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::vault::{self, Argon2Params};
+
+    fn options(cipher: CipherId) -> EncryptOptions {
+        EncryptOptions {
+            cipher,
+            params: Argon2Params::default(),
+        }
+    }
+
+    fn encrypt_chunked(data: &[u8], chunk_size: usize, cipher: CipherId) -> Vec<u8> {
+        let mut encryptor =
+            StreamEncryptor::with_chunk_size(Vec::new(), b"test", &options(cipher), chunk_size)
+                .unwrap();
+        encryptor.write_all(data).unwrap();
+        encryptor.finish().unwrap()
+    }
+
+    fn decrypt(data: &[u8]) -> Result<Vec<u8>, StreamError> {
+        let mut plaintext = Vec::new();
+        decrypt_stream(data, &mut plaintext, b"test")?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn streams_round_trip_across_chunk_boundaries() {
+        for cipher in [CipherId::ChaCha20Poly1305, CipherId::Aes256Gcm] {
+            for length in [0, 1, 15, 16, 17, 48, 100] {
+                let data: Vec<u8> = (0..length).map(|i| i as u8).collect();
+                let encrypted_data = encrypt_chunked(&data, 16, cipher);
+                assert!(is_stream(&encrypted_data));
+                assert_eq!(decrypt(&encrypted_data).unwrap(), data);
+            }
+        }
+
+        let mut encrypted_data = Vec::new();
+        let data = vec![7u8; 3 * CHUNK_SIZE + 1];
+        encrypt_stream(
+            &mut data.as_slice(),
+            &mut encrypted_data,
+            b"test",
+            &options(CipherId::ChaCha20Poly1305),
+        )
+        .unwrap();
+        assert_eq!(decrypt(&encrypted_data).unwrap(), data);
+        assert!(matches!(
+            decrypt_stream(&encrypted_data[..], &mut Vec::new(), b"wrong"),
+            Err(StreamError::Decrypt(DecryptError::Cipher(_)))
+        ));
+    }
+
+    #[test]
+    fn truncated_or_reordered_streams_are_rejected() {
+        let data = [1u8; 64];
+        let encrypted_data = encrypt_chunked(&data, 16, CipherId::ChaCha20Poly1305);
+        // Four full chunks and an empty last one
+        let chunk = 16 + TAG_LENGTH;
+        let body = encrypted_data.len() - 4 * chunk - TAG_LENGTH;
+
+        // Dropping the last chunk leaves a stream ending on a chunk boundary
+        let truncated = &encrypted_data[..encrypted_data.len() - TAG_LENGTH];
+        assert!(matches!(
+            decrypt(truncated),
+            Err(StreamError::Decrypt(DecryptError::Truncated))
+        ));
+        // A full chunk cannot pass for the last one
+        let truncated = &encrypted_data[..body + 2 * chunk];
+        assert!(decrypt(truncated).is_err());
+        assert!(decrypt(&encrypted_data[..encrypted_data.len() - 1]).is_err());
+
+        let mut extended = encrypted_data.clone();
+        extended.push(0);
+        assert!(decrypt(&extended).is_err());
+
+        let mut swapped = encrypted_data.clone();
+        let (first, second) = swapped[body..body + 2 * chunk].split_at_mut(chunk);
+        first.swap_with_slice(second);
+        assert!(decrypt(&swapped).is_err());
+
+        // Chunks are bound to their stream
+        let other = encrypt_chunked(&data, 16, CipherId::ChaCha20Poly1305);
+        let mut spliced = encrypted_data.clone();
+        spliced[body..body + chunk].copy_from_slice(&other[body..body + chunk]);
+        assert!(decrypt(&spliced).is_err());
+
+        assert!(decrypt(&encrypted_data).is_ok());
+    }
+
+    #[test]
+    fn streams_are_told_apart_from_ciphertexts() {
+        let encrypted_data = encrypt_chunked(b"test", 16, CipherId::ChaCha20Poly1305);
+        assert!(matches!(
+            vault::decrypt(&encrypted_data, b"test"),
+            Err(DecryptError::Streamed)
+        ));
+
+        let ciphertext = vault::encrypt(b"test", b"test").unwrap();
+        assert!(!is_stream(&ciphertext));
+        assert!(matches!(
+            decrypt(&ciphertext),
+            Err(StreamError::Decrypt(DecryptError::UnsupportedVersion(_)))
+        ));
+    }
+
+    #[test]
+    fn file_and_directory() {
+        fs::write("stream_test.txt", "test").expect("Failed to write to file");
+        encrypt_file(
+            Path::new("stream_test.txt"),
+            Path::new("stream_test.txt"),
+            b"test",
+            &EncryptOptions::configured(),
+        )
+        .expect("Failed to encrypt the file");
+        assert!(is_stream_file(Path::new("stream_test.txt")).unwrap());
+        decrypt_file(
+            Path::new("stream_test.txt"),
+            Path::new("stream_test.txt"),
+            b"test",
+        )
+        .expect("Failed to decrypt the file");
+        assert_eq!(fs::read("stream_test.txt").unwrap(), b"test");
+
+        // The in-memory API recognizes streams
+        encrypt_file(
+            Path::new("stream_test.txt"),
+            Path::new("stream_test.enc"),
+            b"test",
+            &EncryptOptions::configured(),
+        )
+        .unwrap();
+        assert!(decrypt_file(
+            Path::new("stream_test.enc"),
+            Path::new("stream_test.out"),
+            b"wrong"
+        )
+        .is_err());
+        assert!(!Path::new("stream_test.out").exists());
+        vault::decrypt_file(
+            Path::new("stream_test.enc"),
+            Path::new("stream_test.out"),
+            b"test",
+        )
+        .expect("Failed to decrypt the file");
+        assert_eq!(fs::read("stream_test.out").unwrap(), b"test");
+        for file in ["stream_test.txt", "stream_test.enc", "stream_test.out"] {
+            fs::remove_file(file).expect("Failed to remove the test file");
+        }
+
+        fs::create_dir_all("stream_test/nested").expect("Failed to create directory");
+        fs::write("stream_test/nested/test.txt", "test").expect("Failed to write to file");
+        encrypt_directory(
+            Path::new("stream_test"),
+            Path::new("stream_test.dir"),
+            b"test",
+            &EncryptOptions::configured(),
+        )
+        .expect("Failed to encrypt directory");
+        vault::decrypt_directory(
+            Path::new("stream_test.dir"),
+            Path::new("stream_test_out"),
+            b"test",
+        )
+        .expect("Failed to decrypt directory");
+        assert_eq!(
+            fs::read("stream_test_out/stream_test/nested/test.txt").unwrap(),
+            b"test"
+        );
+        fs::remove_file("stream_test.dir").expect("Failed to remove file");
+        fs::remove_dir_all("stream_test").expect("Failed to remove test directory");
+        fs::remove_dir_all("stream_test_out").expect("Failed to remove test directory");
+    }
+}
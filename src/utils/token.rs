@@ -5,7 +5,7 @@ use bcrypt::verify;
 use chrono::{Duration, Utc};
 use pasetors::{
     claims::Claims,
-    keys::{Generate, SymmetricKey},
+    keys::SymmetricKey,
     local,
     version4::V4,
 };
@@ -14,15 +14,19 @@ use sha2::{Digest, Sha256};
 
 use crate::{
     models::{User, UserCredentials},
-    repositories::{self, key::KeyRepository},
+    repositories::key::KeyRepository,
+    utils::secret::SecretBytes,
 };
 
 pub async fn decode_keys(repo: &State<Arc<KeyRepository>>) -> Result<SymmetricKey<V4>, String> {
     let kp = repo.get_or_create_key_pair().await?;
-    let private_key_bytes = general_purpose::STANDARD
-        .decode(kp.private_key)
-        .map_err(|e| e.to_string())?;
-    let private_key = SymmetricKey::<V4>::from(&private_key_bytes).map_err(|e| e.to_string())?;
+    let private_key_bytes = SecretBytes::new(
+        general_purpose::STANDARD
+            .decode(kp.private_key)
+            .map_err(|e| e.to_string())?,
+    );
+    let private_key =
+        SymmetricKey::<V4>::from(private_key_bytes.expose()).map_err(|e| e.to_string())?;
     Ok(private_key)
 }
 
@@ -43,17 +47,25 @@ pub async fn authorize_user(
     let expiration = Utc::now()
         .checked_add_signed(Duration::minutes(480)) // Moderate expiry (8 hrs)
         .expect("valid timestamp")
-        .timestamp() as usize;
+        .to_rfc3339();
 
     let mut hasher = Sha256::new();
     hasher.update(format!("{}{}", user.email, ecs_authentication_key)); // Unique to current system
     let nonce = format!("{:x}", hasher.finalize());
 
-    claims.subject(&credentials.email);
-    claims.expiration(&expiration.to_string());
-    claims.issuer("https://www.embraconnect.com");
-    claims.add_additional("nonce", nonce);
-    claims.add_additional("aud", vec!["https://www.embraconnect.com".to_string()]);
+    claims
+        .subject(&credentials.email)
+        .map_err(|e| e.to_string())?;
+    claims.expiration(&expiration).map_err(|e| e.to_string())?;
+    claims
+        .issuer("https://www.embraconnect.com")
+        .map_err(|e| e.to_string())?;
+    claims
+        .add_additional("nonce", nonce)
+        .map_err(|e| e.to_string())?;
+    claims
+        .audience("https://www.embraconnect.com")
+        .map_err(|e| e.to_string())?;
     let kp = decode_keys(repo).await?;
     let token = local::encrypt(&kp, &claims, None, None).map_err(|e| e.to_string())?;
     Ok(token)
@@ -1,36 +1,245 @@
-use std::{fs, io, path::Path};
+use std::{fs, io, path::Path, str::FromStr, sync::OnceLock};
 
-use argon2::Config;
+use aes_gcm::Aes256Gcm;
 use chacha20poly1305::{
-    aead::{generic_array::GenericArray, rand_core::RngCore, Aead, OsRng},
-    AeadCore, ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{generic_array::GenericArray, rand_core::RngCore, Aead, OsRng, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
 };
-use log::{info, trace};
+use log::{info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 use tar::{Archive, Builder};
 use thiserror::Error;
 
+use crate::utils::kdf;
+pub use crate::utils::kdf::Argon2Params;
+use crate::utils::secret::SecretKey;
+use crate::utils::stream::{self, StreamError};
+
+/// Length in bytes of the raw keys taken by [encrypt_with_key] and [decrypt_with_key]
+pub const KEY_LENGTH: usize = 32;
+
+/*---------------------------------------------------------------------------
+    Ciphertext format.
+
+    Every ciphertext starts with [MAGIC] and a one byte format version,
+    followed by the bincode encoded [Header] describing how it was
+    produced, then the nonce and the encrypted data. The magic, version
+    and header are authenticated as associated data, so they cannot be
+    altered without failing decryption. Callers may bind a ciphertext
+    to its context (such as the record holding it) by supplying extra
+    associated data, which must be supplied again to decrypt.
+
+    Ciphertexts written before the header existed are a bare bincode
+    [PrecryptorFile] (password based) or [SealedData] (raw key). They
+    start with the little endian length of the data, which would have to
+    exceed a gigabyte to collide with the magic, and are still decrypted
+    with the parameters in use at the time.
+
+    Large inputs are better encrypted in chunks with utils::stream, whose
+    output starts with [MAGIC] and [STREAM_FORMAT_VERSION] instead.
+---------------------------------------------------------------------------*/
+
+/// First bytes of every ciphertext carrying a [Header]
+pub const MAGIC: &[u8; 4] = b"ECSV";
+/// Version of the format written by this build
+pub const FORMAT_VERSION: u8 = 1;
+/// Version byte of ciphertexts encrypted in chunks, see utils::stream
+pub const STREAM_FORMAT_VERSION: u8 = 2;
+
+/// Cipher used to encrypt the data
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CipherId {
+    #[default]
+    ChaCha20Poly1305,
+    Aes256Gcm,
+}
+
+impl FromStr for CipherId {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "chacha20-poly1305" | "chacha20poly1305" => Ok(CipherId::ChaCha20Poly1305),
+            "aes-256-gcm" | "aes256gcm" => Ok(CipherId::Aes256Gcm),
+            _ => Err(format!("unknown cipher {}", name)),
+        }
+    }
+}
+
+impl CipherId {
+    /// The cipher new ciphertexts are encrypted with, read once from ECS_CIPHER
+    /// (`chacha20-poly1305`, the default, or `aes-256-gcm`)
+    pub fn configured() -> Self {
+        static CONFIGURED: OnceLock<CipherId> = OnceLock::new();
+        *CONFIGURED.get_or_init(|| match std::env::var("ECS_CIPHER") {
+            Ok(name) => name.parse().unwrap_or_else(|e| {
+                warn!("Invalid ECS_CIPHER, using ChaCha20-Poly1305: {}", e);
+                CipherId::default()
+            }),
+            Err(_) => CipherId::default(),
+        })
+    }
+}
+
+/// How [encrypt_with_options] encrypts data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptOptions {
+    pub cipher: CipherId,
+    pub params: Argon2Params,
+}
+
+impl EncryptOptions {
+    /// The cipher and Argon2 parameters configured for this deployment
+    pub fn configured() -> Self {
+        Self {
+            cipher: CipherId::configured(),
+            params: Argon2Params::configured(),
+        }
+    }
+}
+
+/// How the encryption key was turned into the cipher key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum KdfId {
+    /// A raw key was supplied, see [encrypt_with_key]
+    None,
+    Argon2id {
+        params: Argon2Params,
+        salt: [u8; 32],
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub cipher: CipherId,
+    pub kdf: KdfId,
+    /// Id of the key the data was encrypted with, when the caller named it
+    pub key_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    header: Header,
+    nonce: [u8; 12],
+    data: Vec<u8>,
+}
+
+/// Headerless output of [encrypt] before the format was versioned
 #[derive(Serialize, Deserialize)]
 struct PrecryptorFile {
     data: Vec<u8>,
     nonce: [u8; 12],
     salt: [u8; 32],
 }
+
+/// Headerless output of [encrypt_with_key] before the format was versioned
+#[derive(Serialize, Deserialize)]
+struct SealedData {
+    data: Vec<u8>,
+    nonce: [u8; 12],
+}
 #[derive(Error, Debug)]
 pub enum EncryptError {
     #[error("failed to generate key from encryption key")]
     Hashing(argon2::Error),
-    #[error("error running chacha20poly1305 on data")]
+    #[error("error running the cipher on data")]
     Cipher(chacha20poly1305::Error),
     #[error("error serializing data to binary format: {0}")]
     Serialize(bincode::Error),
 }
+
+/// Returns the header of a ciphertext, or `None` for a headerless ciphertext
+pub fn read_header(data: &[u8]) -> Result<Option<Header>, DecryptError> {
+    Ok(open_envelope(data)?.map(|envelope| envelope.header))
+}
+
+fn open_envelope(data: &[u8]) -> Result<Option<Envelope>, DecryptError> {
+    let Some(rest) = data.strip_prefix(MAGIC.as_slice()) else {
+        return Ok(None);
+    };
+    match rest.split_first() {
+        Some((&FORMAT_VERSION, body)) => bincode::deserialize(body)
+            .map(Some)
+            .map_err(DecryptError::Deserialize),
+        Some((&STREAM_FORMAT_VERSION, _)) => Err(DecryptError::Streamed),
+        Some((&version, _)) => Err(DecryptError::UnsupportedVersion(version)),
+        None => Err(DecryptError::Truncated),
+    }
+}
+
+/// The bytes authenticated along with the data: magic, version, header and the caller's context
+fn associated_data(header: &Header, context: &[u8]) -> Result<Vec<u8>, bincode::Error> {
+    let mut aad = MAGIC.to_vec();
+    aad.push(FORMAT_VERSION);
+    aad.extend(bincode::serialize(header)?);
+    aad.extend(context);
+    Ok(aad)
+}
+
+fn seal(
+    header: Header,
+    data: &[u8],
+    key: &[u8; KEY_LENGTH],
+    context: &[u8],
+) -> Result<Vec<u8>, EncryptError> {
+    let aad = associated_data(&header, context).map_err(EncryptError::Serialize)?;
+    // Both ciphers take a 96-bit nonce
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let payload = Payload {
+        msg: data,
+        aad: &aad,
+    };
+    let nonce_ref = Nonce::from_slice(&nonce);
+    let ciphertext = match header.cipher {
+        CipherId::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(GenericArray::from_slice(key)).encrypt(nonce_ref, payload)
+        }
+        CipherId::Aes256Gcm => {
+            Aes256Gcm::new(GenericArray::from_slice(key)).encrypt(nonce_ref, payload)
+        }
+    }
+    .map_err(EncryptError::Cipher)?;
+    let envelope = Envelope {
+        header,
+        nonce,
+        data: ciphertext,
+    };
+
+    let mut encoded = MAGIC.to_vec();
+    encoded.push(FORMAT_VERSION);
+    bincode::serialize_into(&mut encoded, &envelope).map_err(EncryptError::Serialize)?;
+    Ok(encoded)
+}
+
+fn open(
+    envelope: &Envelope,
+    key: &[u8; KEY_LENGTH],
+    context: &[u8],
+) -> Result<Vec<u8>, DecryptError> {
+    let aad = associated_data(&envelope.header, context).map_err(DecryptError::Deserialize)?;
+    let payload = Payload {
+        msg: &envelope.data,
+        aad: &aad,
+    };
+    let nonce = Nonce::from_slice(&envelope.nonce);
+    match envelope.header.cipher {
+        CipherId::ChaCha20Poly1305 => {
+            ChaCha20Poly1305::new(GenericArray::from_slice(key)).decrypt(nonce, payload)
+        }
+        CipherId::Aes256Gcm => {
+            Aes256Gcm::new(GenericArray::from_slice(key)).decrypt(nonce, payload)
+        }
+    }
+    .map_err(DecryptError::Cipher)
+}
 /// Encrypts some data and returns the result
 ///
 /// # Examples
 ///
 /// ```no_run
-/// use ec_vault::encrypt;
+/// use ec_secrets_management::utils::vault::encrypt;
 ///
 /// let encrypted_data = encrypt(b"example text", b"encryption key").expect("Failed to encrypt");
 /// // and now you can write it to a file:
@@ -38,37 +247,33 @@ pub enum EncryptError {
 /// ```
 ///
 pub fn encrypt(data: &[u8], encryption_key: &[u8]) -> Result<Vec<u8>, EncryptError> {
+    encrypt_with_options(data, encryption_key, &EncryptOptions::configured())
+}
+
+/// Encrypts some data with the given cipher and Argon2 parameters instead of the configured ones
+pub fn encrypt_with_options(
+    data: &[u8],
+    encryption_key: &[u8],
+    options: &EncryptOptions,
+) -> Result<Vec<u8>, EncryptError> {
     trace!("Generating salt");
     let mut salt = [0u8; 32];
     OsRng.fill_bytes(&mut salt);
-    let config = Config {
-        hash_length: 32,
-        ..Default::default()
-    };
 
     trace!("Generating key");
-    let encryption_key =
-        argon2::hash_raw(encryption_key, &salt, &config).map_err(EncryptError::Hashing)?;
-    let key = GenericArray::from_slice(&encryption_key);
-    let cipher = ChaCha20Poly1305::new(key);
-
-    trace!("Generating nonce");
-    let nonce = ChaCha20Poly1305::generate_nonce(OsRng);
+    let key =
+        kdf::derive_key(encryption_key, &salt, &options.params).map_err(EncryptError::Hashing)?;
 
     info!("Encrypting");
-    let ciphertext = cipher
-        .encrypt(&nonce, data.as_ref())
-        .map_err(EncryptError::Cipher)?;
-    let file = PrecryptorFile {
-        data: ciphertext,
-        nonce: nonce.into(),
-        salt,
+    let header = Header {
+        cipher: options.cipher,
+        kdf: KdfId::Argon2id {
+            params: options.params,
+            salt,
+        },
+        key_id: None,
     };
-
-    trace!("Encoding");
-    let encoded: Vec<u8> = bincode::serialize(&file).map_err(EncryptError::Serialize)?;
-
-    Ok(encoded)
+    seal(header, data, key.expose(), &[])
 }
 #[derive(Error, Debug)]
 pub enum DecryptError {
@@ -76,14 +281,33 @@ pub enum DecryptError {
     Hashing(argon2::Error),
     #[error("failed to deserialize encrypted file from binary format")]
     Deserialize(bincode::Error),
-    #[error("error decrypting with chacha20poly1305 (possibly invalid encryption key)")]
+    #[error("error decrypting data (possibly invalid encryption key)")]
     Cipher(chacha20poly1305::Error),
+    #[error("unsupported ciphertext format version {0}")]
+    UnsupportedVersion(u8),
+    #[error("ciphertext is truncated")]
+    Truncated,
+    #[error("ciphertext was encrypted with a raw key, not a password")]
+    RawKeyExpected,
+    #[error("ciphertext was encrypted with a password, not a raw key")]
+    PasswordExpected,
+    #[error("ciphertext predates associated data and cannot be bound to a context")]
+    Unbound,
+    #[error("ciphertext was encrypted in chunks, decrypt it with utils::stream")]
+    Streamed,
+    #[error("ciphertext was not encrypted in chunks")]
+    NotAStream,
+    #[error("unsupported chunk size {0}")]
+    InvalidChunkSize(u32),
+    #[error("Argon2 parameters {0:?} exceed the allowed cost")]
+    ExcessiveCost(Argon2Params),
 }
 /// Decrypts some data and returns the result
 ///
 /// # Examples
 ///
 /// ```no_run
+/// use ec_secrets_management::utils::vault::{decrypt, encrypt};
 ///
 /// let encrypted_data = encrypt(b"example text", b"encryption key").expect("Failed to encrypt");
 ///
@@ -97,18 +321,28 @@ pub enum DecryptError {
 ///
 pub fn decrypt(data: &[u8], encryption_key: &[u8]) -> Result<Vec<u8>, DecryptError> {
     trace!("Decoding");
-    let decoded: PrecryptorFile = bincode::deserialize(data).map_err(DecryptError::Deserialize)?;
+    if let Some(envelope) = open_envelope(data)? {
+        let KdfId::Argon2id { params, salt } = &envelope.header.kdf else {
+            return Err(DecryptError::RawKeyExpected);
+        };
+        if !params.is_acceptable() {
+            return Err(DecryptError::ExcessiveCost(*params));
+        }
 
-    let config = Config {
-        hash_length: 32,
-        ..Default::default()
-    };
+        trace!("Generating key");
+        let key = kdf::derive_key(encryption_key, salt, params).map_err(DecryptError::Hashing)?;
+
+        info!("Decrypting");
+        return open(&envelope, key.expose(), &[]);
+    }
+
+    let decoded: PrecryptorFile = bincode::deserialize(data).map_err(DecryptError::Deserialize)?;
 
     trace!("Generating key");
     let encryption_key =
-        argon2::hash_raw(encryption_key, &decoded.salt, &config).map_err(DecryptError::Hashing)?;
+        derive_key(encryption_key, &decoded.salt).map_err(DecryptError::Hashing)?;
 
-    let key = GenericArray::from_slice(&encryption_key);
+    let key = GenericArray::from_slice(encryption_key.expose());
     let cipher = ChaCha20Poly1305::new(key);
     let nonce = Nonce::from_slice(&decoded.nonce);
 
@@ -118,6 +352,109 @@ pub fn decrypt(data: &[u8], encryption_key: &[u8]) -> Result<Vec<u8>, DecryptErr
         .map_err(DecryptError::Cipher)?;
     Ok(text)
 }
+/// Derives a raw key from an encryption key with Argon2id and the default parameters
+///
+/// Deriving a key is deliberately slow; derive once and use [encrypt_with_key]
+/// and [decrypt_with_key] when many values are encrypted under the same key.
+pub fn derive_key(encryption_key: &[u8], salt: &[u8]) -> Result<SecretKey, argon2::Error> {
+    kdf::derive_key(encryption_key, salt, &Argon2Params::default())
+}
+
+/// Generates a random key for [encrypt_with_key]. Keep long-lived keys in a
+/// [SecretKey](crate::utils::secret::SecretKey) so they are wiped once dropped.
+pub fn generate_key() -> [u8; KEY_LENGTH] {
+    let mut key = [0u8; KEY_LENGTH];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Encrypts some data with a raw key, skipping key derivation
+///
+/// # Examples
+///
+/// ```
+/// use ec_secrets_management::utils::vault::{decrypt_with_key, encrypt_with_key, generate_key};
+///
+/// let key = generate_key();
+/// let encrypted_data = encrypt_with_key(b"example text", &key).expect("Failed to encrypt");
+/// let data = decrypt_with_key(&encrypted_data, &key).expect("Failed to decrypt");
+/// assert_eq!(data, b"example text");
+/// ```
+///
+pub fn encrypt_with_key(data: &[u8], key: &[u8; KEY_LENGTH]) -> Result<Vec<u8>, EncryptError> {
+    encrypt_with_key_id(data, key, None)
+}
+
+/// Encrypts some data with a raw key, recording the id of the key in the header
+pub fn encrypt_with_key_id(
+    data: &[u8],
+    key: &[u8; KEY_LENGTH],
+    key_id: Option<&str>,
+) -> Result<Vec<u8>, EncryptError> {
+    let header = Header {
+        cipher: CipherId::configured(),
+        kdf: KdfId::None,
+        key_id: key_id.map(str::to_string),
+    };
+    seal(header, data, key, &[])
+}
+
+/// Encrypts some data with a raw key, binding it to `context`: decryption fails unless
+/// the same context is given to [decrypt_with_key_and_context]
+///
+/// ```
+/// use ec_secrets_management::utils::vault::{
+///     decrypt_with_key_and_context, encrypt_with_key_and_context, generate_key,
+/// };
+///
+/// let key = generate_key();
+/// let encrypted_data = encrypt_with_key_and_context(b"example text", &key, b"record 1")
+///     .expect("Failed to encrypt");
+/// assert!(decrypt_with_key_and_context(&encrypted_data, &key, b"record 2").is_err());
+/// ```
+///
+pub fn encrypt_with_key_and_context(
+    data: &[u8],
+    key: &[u8; KEY_LENGTH],
+    context: &[u8],
+) -> Result<Vec<u8>, EncryptError> {
+    let header = Header {
+        cipher: CipherId::configured(),
+        kdf: KdfId::None,
+        key_id: None,
+    };
+    seal(header, data, key, context)
+}
+
+/// Decrypts data produced by [encrypt_with_key]
+pub fn decrypt_with_key(data: &[u8], key: &[u8; KEY_LENGTH]) -> Result<Vec<u8>, DecryptError> {
+    decrypt_with_key_and_context(data, key, &[])
+}
+
+/// Decrypts data produced by [encrypt_with_key_and_context]
+pub fn decrypt_with_key_and_context(
+    data: &[u8],
+    key: &[u8; KEY_LENGTH],
+    context: &[u8],
+) -> Result<Vec<u8>, DecryptError> {
+    if let Some(envelope) = open_envelope(data)? {
+        if envelope.header.kdf != KdfId::None {
+            return Err(DecryptError::PasswordExpected);
+        }
+        return open(&envelope, key, context);
+    }
+    if !context.is_empty() {
+        return Err(DecryptError::Unbound);
+    }
+
+    let decoded: SealedData = bincode::deserialize(data).map_err(DecryptError::Deserialize)?;
+
+    let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(&decoded.nonce), decoded.data.as_ref())
+        .map_err(DecryptError::Cipher)
+}
+
 #[derive(Error, Debug)]
 pub enum FsEncryptError {
     #[error("error writing data to file system: {0}")]
@@ -130,7 +467,7 @@ pub enum FsEncryptError {
 /// # Examples
 ///
 /// ```no_run
-/// use ec_vault::encrypt_file;
+/// use ec_secrets_management::utils::vault::encrypt_file;
 /// use std::path::Path;
 ///
 /// encrypt_file(Path::new("example.txt"), Path::new("encrypted_example.txt"), b"encryption key").expect("Failed to encrypt the file");
@@ -141,39 +478,64 @@ pub fn encrypt_file(
     path: &Path,
     output_path: &Path,
     encryption_key: &[u8],
+) -> Result<(), FsEncryptError> {
+    encrypt_file_with_options(
+        path,
+        output_path,
+        encryption_key,
+        &EncryptOptions::configured(),
+    )
+}
+
+/// Encrypts file data like [encrypt_file], with the given cipher and Argon2 parameters
+pub fn encrypt_file_with_options(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+    options: &EncryptOptions,
 ) -> Result<(), FsEncryptError> {
     trace!("Reading file");
     let data = fs::read(path).map_err(FsEncryptError::Fs)?;
-    let encrypted_data = encrypt(&data, encryption_key).map_err(FsEncryptError::Encrypt)?;
+    let encrypted_data =
+        encrypt_with_options(&data, encryption_key, options).map_err(FsEncryptError::Encrypt)?;
     trace!("Writing to file");
     fs::write(output_path, encrypted_data).map_err(FsEncryptError::Fs)?;
     Ok(())
 }
 
+#[derive(Error, Debug)]
+pub enum FsDecryptError {
+    #[error("error writing encrypted data to file system")]
+    Fs(io::Error),
+    #[error("error decrypting file contents")]
+    Decrypt(DecryptError),
+    #[error("error decrypting file contents in chunks: {0}")]
+    Stream(StreamError),
+}
 /// Decrypts file data and output it to the specified output file
 ///
+/// Files encrypted in chunks by utils::stream are decrypted in chunks as well
+///
 /// # Examples
 ///
 /// ```no_run
-/// use ec_vault::decrypt_file;
+/// use ec_secrets_management::utils::vault::decrypt_file;
 /// use std::path::Path;
 ///
 /// decrypt_file(Path::new("encrypted_example.txt"), Path::new("example.txt"), b"encryption key").expect("Failed to decrypt the file");
 /// // Now the example.txt is decrypted
 /// ```
 ///
-#[derive(Error, Debug)]
-pub enum FsDecryptError {
-    #[error("error writing encrypted data to file system")]
-    Fs(io::Error),
-    #[error("error decrypting file contents")]
-    Decrypt(DecryptError),
-}
 pub fn decrypt_file(
     path: &Path,
     output_path: &Path,
     encryption_key: &[u8],
 ) -> Result<(), FsDecryptError> {
+    if stream::is_stream_file(path).map_err(FsDecryptError::Fs)? {
+        return stream::decrypt_file(path, output_path, encryption_key)
+            .map_err(FsDecryptError::Stream);
+    }
+
     trace!("Reading file");
     let encrypted_data = fs::read(path).map_err(FsDecryptError::Fs)?;
     let data = decrypt(&encrypted_data, encryption_key).map_err(FsDecryptError::Decrypt)?;
@@ -199,7 +561,7 @@ pub enum EncryptDirectoryError {
 /// # Examples
 ///
 /// ```no_run
-/// use ec_vault::encrypt_directory;
+/// use ec_secrets_management::utils::vault::encrypt_directory;
 /// use std::path::Path;
 ///
 /// encrypt_directory(Path::new("example"), Path::new("example.dir"), b"encryption key").expect("Failed to encrypt directory");
@@ -210,6 +572,21 @@ pub fn encrypt_directory(
     path: &Path,
     output_path: &Path,
     encryption_key: &[u8],
+) -> Result<(), EncryptDirectoryError> {
+    encrypt_directory_with_options(
+        path,
+        output_path,
+        encryption_key,
+        &EncryptOptions::configured(),
+    )
+}
+
+/// Encrypts a directory like [encrypt_directory], with the given cipher and Argon2 parameters
+pub fn encrypt_directory_with_options(
+    path: &Path,
+    output_path: &Path,
+    encryption_key: &[u8],
+    options: &EncryptOptions,
 ) -> Result<(), EncryptDirectoryError> {
     let mut archive_output = Vec::new();
     let mut archive = Builder::new(&mut archive_output);
@@ -225,7 +602,8 @@ pub fn encrypt_directory(
     let data = archive
         .into_inner()
         .map_err(EncryptDirectoryError::Archive)?;
-    let encrypted_data = encrypt(data, encryption_key).map_err(EncryptDirectoryError::Encrypt)?;
+    let encrypted_data = encrypt_with_options(data, encryption_key, options)
+        .map_err(EncryptDirectoryError::Encrypt)?;
     trace!("Writing to file");
     fs::write(output_path, encrypted_data).map_err(EncryptDirectoryError::Fs)?;
     Ok(())
@@ -238,15 +616,19 @@ pub enum DecryptDirectoryError {
     Decrypt(DecryptError),
     #[error("error unpacking archive: {0}")]
     Archive(io::Error),
+    #[error("error decrypting archive in chunks: {0}")]
+    Stream(StreamError),
 }
 /// Decrypts a directory and extracts it to the specified output directory
 ///
 /// note: the encrypted directory is a file but when its decrypted it will be a directory and the output path is not what the folder name should be its where to extract the file
 ///
+/// Directories encrypted in chunks by utils::stream are decrypted in chunks as well
+///
 /// # Examples
 ///
 /// ```no_run
-/// use ec_vault::decrypt_directory;
+/// use ec_secrets_management::utils::vault::decrypt_directory;
 /// use std::path::Path;
 ///
 /// decrypt_directory(Path::new("example.dir"), Path::new("example"), b"encryption key").expect("Failed to decrypt directory");
@@ -258,6 +640,11 @@ pub fn decrypt_directory(
     output_path: &Path,
     encryption_key: &[u8],
 ) -> Result<(), DecryptDirectoryError> {
+    if stream::is_stream_file(path).map_err(DecryptDirectoryError::Fs)? {
+        return stream::decrypt_directory(path, output_path, encryption_key)
+            .map_err(DecryptDirectoryError::Stream);
+    }
+
     trace!("Reading from file");
     let encrypted_data = fs::read(path).map_err(DecryptDirectoryError::Fs)?;
     let data = decrypt(&encrypted_data, encryption_key).map_err(DecryptDirectoryError::Decrypt)?;
@@ -275,6 +662,16 @@ pub fn decrypt_directory(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chacha20poly1305::AeadCore;
+
+    const CIPHERS: [CipherId; 2] = [CipherId::ChaCha20Poly1305, CipherId::Aes256Gcm];
+
+    fn options(cipher: CipherId) -> EncryptOptions {
+        EncryptOptions {
+            cipher,
+            params: Argon2Params::default(),
+        }
+    }
 
     #[test]
     fn data() {
@@ -283,6 +680,222 @@ mod tests {
         assert_eq!(data, b"test");
     }
 
+    #[test]
+    fn ciphertexts_carry_a_versioned_header() {
+        let encrypted_data = encrypt(b"test", b"test").expect("Failed to encrypt");
+        assert!(encrypted_data.starts_with(MAGIC));
+        let header = read_header(&encrypted_data).unwrap().expect("header");
+        assert_eq!(header.cipher, CipherId::configured());
+        assert!(
+            matches!(header.kdf, KdfId::Argon2id { params, .. } if params == Argon2Params::default())
+        );
+
+        let key = generate_key();
+        let wrapped = encrypt_with_key_id(b"test", &key, Some("2025")).unwrap();
+        let header = read_header(&wrapped).unwrap().expect("header");
+        assert_eq!(
+            (header.kdf, header.key_id.as_deref()),
+            (KdfId::None, Some("2025"))
+        );
+        assert!(matches!(
+            decrypt(&wrapped, b"test"),
+            Err(DecryptError::RawKeyExpected)
+        ));
+
+        // The header is authenticated
+        let mut tampered = wrapped.clone();
+        let at = tampered.windows(4).position(|w| w == b"2025").unwrap();
+        tampered[at] = b'1';
+        assert!(decrypt_with_key(&tampered, &key).is_err());
+
+        let mut future = wrapped;
+        future[MAGIC.len()] = STREAM_FORMAT_VERSION + 1;
+        assert!(matches!(
+            decrypt_with_key(&future, &key),
+            Err(DecryptError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn argon2_parameters_are_read_from_the_header() {
+        let params = Argon2Params {
+            mem_cost: 64,
+            time_cost: 1,
+            lanes: 1,
+        };
+        let options = EncryptOptions {
+            cipher: CipherId::ChaCha20Poly1305,
+            params,
+        };
+        let encrypted_data = encrypt_with_options(b"test", b"test", &options).unwrap();
+        let header = read_header(&encrypted_data).unwrap().expect("header");
+        assert!(matches!(header.kdf, KdfId::Argon2id { params: p, .. } if p == params));
+        assert_eq!(decrypt(&encrypted_data, b"test").unwrap(), b"test");
+    }
+
+    #[test]
+    fn oversized_argon2_parameters_are_rejected() {
+        let params = Argon2Params {
+            mem_cost: u32::MAX,
+            ..Argon2Params::default()
+        };
+        let header = Header {
+            cipher: CipherId::ChaCha20Poly1305,
+            kdf: KdfId::Argon2id {
+                params,
+                salt: [0u8; 32],
+            },
+            key_id: None,
+        };
+        let encrypted_data = seal(header, b"test", &generate_key(), &[]).unwrap();
+        assert!(matches!(
+            decrypt(&encrypted_data, b"test"),
+            Err(DecryptError::ExcessiveCost(p)) if p == params
+        ));
+    }
+
+    #[test]
+    fn context_is_authenticated() {
+        let key = generate_key();
+        let encrypted_data = encrypt_with_key_and_context(b"test", &key, b"record 1").unwrap();
+        assert_eq!(
+            decrypt_with_key_and_context(&encrypted_data, &key, b"record 1").unwrap(),
+            b"test"
+        );
+        assert!(decrypt_with_key_and_context(&encrypted_data, &key, b"record 2").is_err());
+        assert!(decrypt_with_key(&encrypted_data, &key).is_err());
+
+        let unbound = encrypt_with_key(b"test", &key).unwrap();
+        assert!(decrypt_with_key_and_context(&unbound, &key, b"record 1").is_err());
+    }
+
+    #[test]
+    fn headerless_ciphertexts_are_still_decrypted() {
+        let key = generate_key();
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&key));
+        let nonce = ChaCha20Poly1305::generate_nonce(OsRng);
+        let sealed = SealedData {
+            data: cipher.encrypt(&nonce, b"test".as_ref()).unwrap(),
+            nonce: nonce.into(),
+        };
+        let legacy = bincode::serialize(&sealed).unwrap();
+        assert_eq!(read_header(&legacy).unwrap(), None);
+        assert_eq!(decrypt_with_key(&legacy, &key).unwrap(), b"test");
+        assert!(matches!(
+            decrypt_with_key_and_context(&legacy, &key, b"record"),
+            Err(DecryptError::Unbound)
+        ));
+
+        let salt = [7u8; 32];
+        let derived = derive_key(b"test", &salt).unwrap();
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(derived.expose()));
+        let file = PrecryptorFile {
+            data: cipher.encrypt(&nonce, b"test".as_ref()).unwrap(),
+            nonce: nonce.into(),
+            salt,
+        };
+        let legacy = bincode::serialize(&file).unwrap();
+        assert_eq!(decrypt(&legacy, b"test").unwrap(), b"test");
+    }
+
+    #[test]
+    fn data_with_key() {
+        let key = generate_key();
+        let encrypted_data = encrypt_with_key(b"test", &key).expect("Failed to encrypt");
+        let data = decrypt_with_key(&encrypted_data, &key).expect("Failed to decrypt");
+        assert_eq!(data, b"test");
+        assert!(decrypt_with_key(&encrypted_data, &generate_key()).is_err());
+    }
+
+    #[test]
+    fn data_with_each_cipher() {
+        for cipher in CIPHERS {
+            let encrypted_data = encrypt_with_options(b"test", b"test", &options(cipher))
+                .expect("Failed to encrypt");
+            assert_eq!(
+                read_header(&encrypted_data)
+                    .unwrap()
+                    .expect("header")
+                    .cipher,
+                cipher
+            );
+            assert_eq!(decrypt(&encrypted_data, b"test").unwrap(), b"test");
+            assert!(decrypt(&encrypted_data, b"wrong").is_err());
+
+            let key = generate_key();
+            let header = Header {
+                cipher,
+                kdf: KdfId::None,
+                key_id: None,
+            };
+            let sealed = seal(header, b"test", &key, &[]).unwrap();
+            assert_eq!(decrypt_with_key(&sealed, &key).unwrap(), b"test");
+        }
+
+        // The ciphers are not interchangeable: the recorded one must be used
+        let key = generate_key();
+        let mut sealed = seal(
+            Header {
+                cipher: CipherId::Aes256Gcm,
+                kdf: KdfId::None,
+                key_id: None,
+            },
+            b"test",
+            &key,
+            &[],
+        )
+        .unwrap();
+        let mut envelope = open_envelope(&sealed).unwrap().unwrap();
+        envelope.header.cipher = CipherId::ChaCha20Poly1305;
+        sealed.truncate(MAGIC.len() + 1);
+        bincode::serialize_into(&mut sealed, &envelope).unwrap();
+        assert!(decrypt_with_key(&sealed, &key).is_err());
+
+        assert_eq!("aes-256-gcm".parse(), Ok(CipherId::Aes256Gcm));
+        assert!("des".parse::<CipherId>().is_err());
+    }
+
+    #[test]
+    fn file_and_directory_with_each_cipher() {
+        for cipher in CIPHERS {
+            let name = format!("test_{:?}", cipher);
+            let file = format!("{}.txt", name);
+            fs::write(&file, "test").expect("Failed to write to file");
+            encrypt_file_with_options(
+                Path::new(&file),
+                Path::new(&file),
+                b"test",
+                &options(cipher),
+            )
+            .expect("Failed to encrypt the file");
+            decrypt_file(Path::new(&file), Path::new(&file), b"test")
+                .expect("Failed to decrypt the file");
+            assert_eq!(fs::read(&file).expect("Failed to read file"), b"test");
+            fs::remove_file(&file).expect("Failed to remove the test file");
+
+            let archive = format!("{}.dir", name);
+            let output = format!("{}_out", name);
+            fs::create_dir(&name).expect("Failed to create directory");
+            fs::write(format!("{}/test.txt", name), "test").expect("Failed to write to file");
+            encrypt_directory_with_options(
+                Path::new(&name),
+                Path::new(&archive),
+                b"test",
+                &options(cipher),
+            )
+            .expect("Failed to encrypt directory");
+            decrypt_directory(Path::new(&archive), Path::new(&output), b"test")
+                .expect("Failed to decrypt directory");
+            assert_eq!(
+                fs::read(format!("{}/{}/test.txt", output, name)).expect("Failed to read file"),
+                b"test"
+            );
+            fs::remove_file(&archive).expect("Failed to remove file");
+            fs::remove_dir_all(&name).expect("Failed to remove test directory");
+            fs::remove_dir_all(&output).expect("Failed to remove test directory");
+        }
+    }
+
     #[test]
     fn file() {
         fs::write("test.txt", "test").expect("Failed to write to file");
@@ -0,0 +1,895 @@
+use ec_secrets_management::{
+    custom_catchers::{conflict, service_unavailable},
+    db,
+    repositories::{
+        backup::BackupRepository, generator::GeneratorRepository, key::KeyRepository,
+        seal::SealRepository, signing::SigningRepository, transit::TransitRepository,
+        users::UserRepository, vault::VaultRepository,
+    },
+    routes::{
+        admin::admin_routes, backup::backup_routes, generator::generator_routes, seal::seal_routes,
+        signing::signing_routes, transit::transit_routes, users::user_routes, vault::vault_routes,
+    },
+    storage::memory::MemoryStore,
+};
+use rocket::{
+    catchers,
+    http::{ContentType, Header, Status},
+    local::asynchronous::Client,
+    serde::json::Value,
+    Build, Rocket,
+};
+use std::sync::Arc;
+
+fn configure() {
+    std::env::set_var("ECS_STORAGE_BACKEND", "memory");
+    std::env::set_var("ECS_ENCRYPTION_KEY", "IRwTgHBtmblSfAXpYOuvf4ZIhSY32JoP8TLIxeLuCrg=");
+    std::env::set_var(
+        "ECS_AUTHENTICATION_KEY",
+        "HEJpH886G0gArUNIYK7CLXfvOSKHBAnlJM3rVw/Tfdg=",
+    );
+    std::env::set_var("ECS_ADMIN_USERS", "admin@example.com");
+}
+
+fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
+    rocket
+        .mount("/", user_routes())
+        .mount("/", vault_routes())
+        .mount("/", admin_routes())
+        .mount("/", seal_routes())
+        .mount("/", transit_routes())
+        .mount("/", signing_routes())
+        .mount("/", generator_routes())
+        .mount("/", backup_routes())
+        .register("/", catchers![conflict, service_unavailable])
+}
+
+async fn client() -> Client {
+    configure();
+
+    Client::tracked(mount(rocket::build().attach(db::init()))).await.expect("valid rocket instance")
+}
+
+/// A vault started without a master key
+async fn sealed_client() -> Client {
+    configure();
+
+    let store = Arc::new(MemoryStore::new());
+    let vault = Arc::new(VaultRepository::with_keyring(store.clone(), None));
+    let rocket = rocket::build()
+        .manage(Arc::new(UserRepository::new(store.clone())))
+        .manage(Arc::new(KeyRepository::new(store.clone())))
+        .manage(Arc::new(SealRepository::new(store.clone(), vault.clone())))
+        .manage(Arc::new(TransitRepository::new(
+            store.clone(),
+            vault.clone(),
+        )))
+        .manage(Arc::new(SigningRepository::new(
+            store.clone(),
+            vault.clone(),
+        )))
+        .manage(Arc::new(GeneratorRepository::new(
+            store.clone(),
+            vault.clone(),
+        )))
+        .manage(Arc::new(BackupRepository::new(store, vault.clone())))
+        .manage(vault);
+
+    Client::tracked(mount(rocket)).await.expect("valid rocket instance")
+}
+
+async fn login(client: &Client, email: &str) -> Header<'static> {
+    let credentials = format!(r#"{{"email": "{}", "password": "password"}}"#, email);
+
+    let response = client
+        .post("/setup")
+        .header(ContentType::JSON)
+        .body(&credentials)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(&credentials)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("login response");
+    let token = body["token"].as_str().expect("token").to_string();
+
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+#[rocket::async_test]
+async fn vault_entry_lifecycle() {
+    let client = client().await;
+    let auth = login(&client, "user@example.com").await;
+
+    let response = client
+        .post("/create/vault/entry")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"key": "test", "value": "ThisShouldBeKeptSecret"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("entries");
+    assert_eq!(page["total"], 1);
+    assert!(page["next_page_token"].is_null());
+    let entries = page["entries"].as_array().expect("array of entries");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["value"], "ThisShouldBeKeptSecret");
+    let id = entries[0]["_id"]["$oid"].as_str().expect("entry id");
+
+    let response = client
+        .get(format!("/retrieve/vault/entries/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let value: Value = response.into_json().await.expect("entry value");
+    assert_eq!(value, "ThisShouldBeKeptSecret");
+
+    let response = client
+        .delete(format!("/delete/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("delete response");
+    assert_eq!(body["status"], 200);
+
+    let response = client
+        .get("/retrieve/vault/trash")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let trash: Value = response.into_json().await.expect("trash");
+    assert_eq!(trash[0]["_id"], id);
+    assert!(trash[0].get("value").is_none());
+
+    let response = client
+        .post(format!("/restore/vault/entry/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("restore response");
+    assert_eq!(body["status"], 200);
+
+    let response = client
+        .get(format!("/retrieve/vault/entries/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let value: Value = response.into_json().await.expect("entry value");
+    assert_eq!(value, "ThisShouldBeKeptSecret");
+
+    client
+        .delete(format!("/delete/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let response = client
+        .delete(format!("/purge/vault/entry/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("purge response");
+    assert_eq!(body["status"], 200);
+
+    let response = client
+        .post(format!("/restore/vault/entry/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("restore response");
+    assert_eq!(body["status"], 404);
+}
+
+#[rocket::async_test]
+async fn entries_require_a_token() {
+    let client = client().await;
+
+    let response = client.get("/retrieve/vault/entries").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn malformed_ids_are_bad_requests() {
+    let client = client().await;
+    let auth = login(&client, "malformed@example.com").await;
+
+    let response = client
+        .get("/retrieve/vault/entries/not-an-id")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 400);
+
+    let response = client
+        .post("/restore/vault/entry/not-an-id")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 400);
+}
+
+#[rocket::async_test]
+async fn stale_updates_are_rejected_with_conflict() {
+    let client = client().await;
+    let auth = login(&client, "writer@example.com").await;
+
+    client
+        .post("/create/vault/entry")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"key": "test", "value": "first"}"#)
+        .dispatch()
+        .await;
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("entries");
+    let entries = &page["entries"];
+    let id = entries[0]["_id"]["$oid"].as_str().expect("entry id");
+    assert_eq!(entries[0]["version"], 1);
+
+    let response = client
+        .put(format!("/update/vault/entry/{}", id))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"value": "second", "version": 1}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("update response");
+    assert_eq!(body["version"], 2);
+
+    let response = client
+        .put(format!("/update/vault/entry/{}", id))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"value": "third", "version": 1}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn restoring_over_a_live_key_is_rejected_with_conflict() {
+    let client = client().await;
+    let auth = login(&client, "restorer@example.com").await;
+
+    let create = || {
+        client
+            .post("/create/vault/entry")
+            .header(ContentType::JSON)
+            .header(auth.clone())
+            .body(r#"{"key": "shared", "value": "secret"}"#)
+            .dispatch()
+    };
+    create().await;
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("entries");
+    let id = page["entries"][0]["_id"]["$oid"]
+        .as_str()
+        .expect("entry id")
+        .to_string();
+
+    client
+        .delete(format!("/delete/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    create().await;
+
+    let response = client
+        .post(format!("/restore/vault/entry/{}", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+}
+
+#[rocket::async_test]
+async fn entries_are_addressable_by_path() {
+    let client = client().await;
+    let auth = login(&client, "paths@example.com").await;
+
+    for path in ["payments/prod/stripe_api_key", "payments/prod/db/password"] {
+        let response = client
+            .put(format!("/update/vault/path/{}", path))
+            .header(ContentType::JSON)
+            .header(auth.clone())
+            .body(r#"{"value": "secret"}"#)
+            .dispatch()
+            .await;
+        let body: Value = response.into_json().await.expect("put response");
+        assert_eq!(body["version"], 1);
+    }
+
+    let response = client
+        .put("/update/vault/path/payments/prod/stripe_api_key")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"value": "secret"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+
+    let response = client
+        .get("/retrieve/vault/path/payments/prod/stripe_api_key")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let value: Value = response.into_json().await.expect("entry value");
+    assert_eq!(value, "secret");
+
+    let response = client
+        .get("/list/vault/path/payments/prod/")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let listing: Value = response.into_json().await.expect("listing");
+    assert_eq!(listing["folders"], serde_json::json!(["db"]));
+    assert_eq!(listing["keys"], serde_json::json!(["stripe_api_key"]));
+
+    let response = client
+        .delete("/delete/vault/path/payments/prod/stripe_api_key")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("delete response");
+    assert_eq!(body["status"], 200);
+}
+
+#[rocket::async_test]
+async fn binary_entries_are_retrieved_raw() {
+    let client = client().await;
+    let auth = login(&client, "binary@example.com").await;
+
+    let response = client
+        .post("/create/vault/entry")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"key": "keystore", "value": "not base64!", "encoding": "base64"}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("create response");
+    assert_eq!(body["status"], 400);
+
+    client
+        .post("/create/vault/entry")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"key": "keystore", "value": "MIL/AP4=", "encoding": "base64"}"#)
+        .dispatch()
+        .await;
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("entries");
+    let entry = &page["entries"][0];
+    assert_eq!(entry["value"], "MIL/AP4=");
+    assert_eq!(entry["encoding"], "base64");
+    let id = entry["_id"]["$oid"].as_str().expect("entry id");
+
+    let response = client
+        .get(format!("/retrieve/vault/entries/{}/raw", id))
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::Binary));
+    let body = response.into_bytes().await.expect("raw value");
+    assert_eq!(body, vec![0x30, 0x82, 0xff, 0x00, 0xfe]);
+}
+
+#[rocket::async_test]
+async fn entries_are_paginated() {
+    let client = client().await;
+    let auth = login(&client, "pages@example.com").await;
+
+    for key in ["b", "a", "c", "other/d"] {
+        client
+            .post("/create/vault/entry")
+            .header(ContentType::JSON)
+            .header(auth.clone())
+            .body(serde_json::json!({ "key": key, "value": "secret" }).to_string())
+            .dispatch()
+            .await;
+    }
+
+    let mut keys = Vec::new();
+    let mut uri = "/retrieve/vault/entries?limit=2&sort=key&order=desc".to_string();
+    loop {
+        let response = client.get(&uri).header(auth.clone()).dispatch().await;
+        let page: Value = response.into_json().await.expect("page");
+        assert_eq!(page["total"], 4);
+        for entry in page["entries"].as_array().expect("array of entries") {
+            keys.push(entry["key"].as_str().expect("key").to_string());
+        }
+        match page["next_page_token"].as_str() {
+            Some(token) => {
+                uri = format!(
+                    "/retrieve/vault/entries?limit=2&sort=key&order=desc&page_token={}",
+                    token
+                )
+            }
+            None => break,
+        }
+    }
+    assert_eq!(keys, vec!["other/d", "c", "b", "a"]);
+
+    let response = client
+        .get("/retrieve/vault/entries?prefix=other/")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("page");
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["entries"][0]["key"], "other/d");
+
+    client
+        .post("/create/vault/entry")
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(
+            serde_json::json!({
+                "key": "labelled",
+                "value": "secret",
+                "description": "Warehouse password",
+                "labels": { "env": "prod", "team": "data" }
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    let response = client
+        .get("/retrieve/vault/entries?labels=env%3Dprod,team!%3Dlegacy")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("page");
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["entries"][0]["key"], "labelled");
+    assert_eq!(page["entries"][0]["description"], "Warehouse password");
+    assert_eq!(page["entries"][0]["labels"]["team"], "data");
+
+    let response = client
+        .get("/retrieve/vault/entries?sort=name")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 400);
+}
+
+#[rocket::async_test]
+async fn transit_keys_encrypt_for_allowed_subjects() {
+    let client = client().await;
+    let job = login(&client, "etl-job@example.com").await;
+    let owner = login(&client, "data-owner@example.com").await;
+
+    let response = client
+        .post("/transit/keys")
+        .header(ContentType::JSON)
+        .header(owner.clone())
+        .body(r#"{"name": "customers", "allowed_subjects": ["etl-job@example.com"]}"#)
+        .dispatch()
+        .await;
+    let key: Value = response.into_json().await.expect("transit key");
+    assert_eq!(key["latest_version"], 1);
+
+    // "NDExMQ==" and "NDI0Mg==" are "4111" and "4242"
+    let response = client
+        .post("/transit/encrypt/customers")
+        .header(ContentType::JSON)
+        .header(job.clone())
+        .body(r#"{"batch": [{"plaintext": "NDExMQ=="}, {"plaintext": "NDI0Mg=="}, {"plaintext": "*"}]}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("encrypt response");
+    let results = body["batch_results"].as_array().expect("batch results");
+    assert!(results[2]["error"].is_string());
+    let ciphertext = results[0]["ciphertext"].as_str().expect("ciphertext");
+    assert!(ciphertext.starts_with("ecs:v1:"));
+
+    let response = client
+        .post("/transit/keys/customers/rotate")
+        .header(owner.clone())
+        .dispatch()
+        .await;
+    let key: Value = response.into_json().await.expect("rotated key");
+    assert_eq!(key["latest_version"], 2);
+
+    let response = client
+        .post("/transit/rewrap/customers")
+        .header(ContentType::JSON)
+        .header(job.clone())
+        .body(format!(r#"{{"ciphertext": "{}"}}"#, ciphertext))
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("rewrap response");
+    assert_eq!(body["key_version"], 2);
+    let rewrapped = body["ciphertext"].as_str().expect("ciphertext");
+
+    let response = client
+        .post("/transit/decrypt/customers")
+        .header(ContentType::JSON)
+        .header(owner.clone())
+        .body(format!(r#"{{"ciphertext": "{}"}}"#, rewrapped))
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("decrypt response");
+    assert_eq!(body["plaintext"], "NDExMQ==");
+
+    let outsider = login(&client, "outsider@example.com").await;
+    let response = client
+        .post("/transit/decrypt/customers")
+        .header(ContentType::JSON)
+        .header(outsider)
+        .body(format!(r#"{{"ciphertext": "{}"}}"#, rewrapped))
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 403);
+}
+
+#[rocket::async_test]
+async fn signing_keys_sign_verify_and_audit() {
+    let client = client().await;
+    let owner = login(&client, "release-owner@example.com").await;
+    let ci = login(&client, "ci@example.com").await;
+
+    let response = client
+        .post("/signing/keys")
+        .header(ContentType::JSON)
+        .header(owner.clone())
+        .body(r#"{"name": "artifacts", "algorithm": "ed25519", "allowed_subjects": ["ci@example.com"]}"#)
+        .dispatch()
+        .await;
+    let key: Value = response.into_json().await.expect("signing key");
+    assert_eq!(key["algorithm"], "ed25519");
+    assert_eq!(key["latest_version"], 1);
+
+    // "cmVsZWFzZQ==" is "release"
+    let response = client
+        .post("/signing/sign/artifacts")
+        .header(ContentType::JSON)
+        .header(ci.clone())
+        .body(r#"{"input": "cmVsZWFzZQ=="}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("sign response");
+    assert_eq!(body["key_version"], 1);
+    let signature = body["signature"].as_str().expect("signature");
+    assert!(signature.starts_with("ecs:v1:"));
+
+    for (input, valid) in [("cmVsZWFzZQ==", true), ("cmVsZWFzZTI=", false)] {
+        let response = client
+            .post("/signing/verify/artifacts")
+            .header(ContentType::JSON)
+            .header(owner.clone())
+            .body(format!(
+                r#"{{"input": "{}", "signature": "{}"}}"#,
+                input, signature
+            ))
+            .dispatch()
+            .await;
+        let body: Value = response.into_json().await.expect("verify response");
+        assert_eq!(body["valid"], valid);
+    }
+
+    let response = client
+        .get("/signing/keys/artifacts/public")
+        .header(ci.clone())
+        .dispatch()
+        .await;
+    let exported: Value = response.into_json().await.expect("public keys");
+    assert!(exported["keys"][0]["pem"]
+        .as_str()
+        .expect("pem")
+        .starts_with("-----BEGIN PUBLIC KEY-----"));
+
+    let outsider = login(&client, "outsider@example.com").await;
+    let response = client
+        .post("/signing/sign/artifacts")
+        .header(ContentType::JSON)
+        .header(outsider)
+        .body(r#"{"input": "cmVsZWFzZQ=="}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 403);
+
+    let response = client
+        .get("/signing/keys/artifacts/audit?limit=2")
+        .header(ci)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 403);
+
+    let response = client
+        .get("/signing/keys/artifacts/audit?limit=2")
+        .header(owner)
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("audit page");
+    assert_eq!(page["total"], 6);
+    assert_eq!(page["events"][1]["operation"], "sign");
+    assert_eq!(page["events"][1]["subject"], "ci@example.com");
+    assert!(page["next_page_token"].is_string());
+}
+
+#[rocket::async_test]
+async fn generated_secrets_follow_shared_policies() {
+    let client = client().await;
+    let user = login(&client, "developer@example.com").await;
+    let policy = r#"{"name": "db-passwords", "spec": {"kind": "password", "length": 32, "symbols": false, "min_digits": 4}}"#;
+
+    let response = client
+        .post("/generate/policies")
+        .header(ContentType::JSON)
+        .header(user.clone())
+        .body(policy)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let admin = login(&client, "admin@example.com").await;
+    let response = client
+        .post("/generate/policies")
+        .header(ContentType::JSON)
+        .header(admin)
+        .body(policy)
+        .dispatch()
+        .await;
+    let created: Value = response.into_json().await.expect("policy");
+    assert_eq!(created["spec"]["kind"], "password");
+
+    let response = client
+        .post("/generate")
+        .header(ContentType::JSON)
+        .header(user.clone())
+        .body(r#"{"policy": "db-passwords"}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("generated value");
+    let value = body["value"].as_str().expect("value");
+    assert_eq!(value.len(), 32);
+    assert!(value.chars().filter(char::is_ascii_digit).count() >= 4);
+
+    let response = client
+        .post("/generate")
+        .header(ContentType::JSON)
+        .header(user.clone())
+        .body(r#"{"policy": "db-passwords", "store": {"key": "orders/db-password", "labels": {"team": "orders"}}}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("stored entry");
+    assert!(body.get("value").is_none());
+    assert_eq!(body["key"], "orders/db-password");
+    let id = body["entry_id"].as_str().expect("entry id");
+
+    let response = client
+        .get(format!("/retrieve/vault/entries/{}", id))
+        .header(user.clone())
+        .dispatch()
+        .await;
+    let stored: String = response.into_json().await.expect("entry value");
+    assert_eq!(stored.len(), 32);
+
+    let response = client
+        .post("/generate")
+        .header(ContentType::JSON)
+        .header(user)
+        .body(r#"{"spec": {"kind": "password", "length": 4}}"#)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 400);
+}
+
+#[rocket::async_test]
+async fn backups_restore_the_vault_as_it_was() {
+    let client = client().await;
+    let user = login(&client, "user@example.com").await;
+    let create = |key: &'static str| {
+        client
+            .post("/create/vault/entry")
+            .header(ContentType::JSON)
+            .header(user.clone())
+            .body(format!(
+                r#"{{"key": "{}", "value": "ThisShouldBeKeptSecret"}}"#,
+                key
+            ))
+            .dispatch()
+    };
+    let passphrase = Header::new("X-Backup-Passphrase", "a long backup passphrase");
+    assert_eq!(create("before").await.status(), Status::Ok);
+
+    let response = client
+        .post("/admin/backup")
+        .header(user.clone())
+        .header(passphrase.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let admin = login(&client, "admin@example.com").await;
+    let response = client
+        .post("/admin/backup")
+        .header(admin.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let response = client
+        .post("/admin/backup")
+        .header(admin.clone())
+        .header(passphrase.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.content_type(), Some(ContentType::Binary));
+    let bundle = response.into_bytes().await.expect("backup bundle");
+    assert_eq!(create("after").await.status(), Status::Ok);
+
+    let response = client
+        .post("/admin/restore?dry_run=true")
+        .header(admin.clone())
+        .header(passphrase.clone())
+        .body(&bundle)
+        .dispatch()
+        .await;
+    let report: Value = response.into_json().await.expect("restore report");
+    assert_eq!(report["mode"], "merge");
+    assert_eq!(report["collections"]["vault"]["skipped"], 1);
+    assert_eq!(report["missing_key_ids"], serde_json::json!([]));
+
+    let response = client
+        .post("/admin/restore?mode=replace")
+        .header(admin.clone())
+        .header(Header::new("X-Backup-Passphrase", "not the passphrase"))
+        .body(&bundle)
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.expect("error response");
+    assert_eq!(body["status"], 400);
+
+    let response = client
+        .post("/admin/restore?mode=replace")
+        .header(admin)
+        .header(passphrase)
+        .body(&bundle)
+        .dispatch()
+        .await;
+    let report: Value = response.into_json().await.expect("restore report");
+    assert_eq!(report["status"], 200);
+    assert_eq!(report["collections"]["vault"]["removed"], 2);
+
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(user)
+        .dispatch()
+        .await;
+    let page: Value = response.into_json().await.expect("entries");
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["entries"][0]["key"], "before");
+    assert_eq!(page["entries"][0]["value"], "ThisShouldBeKeptSecret");
+}
+
+#[rocket::async_test]
+async fn keyring_administration_requires_an_admin() {
+    let client = client().await;
+    let auth = login(&client, "user@example.com").await;
+    let response = client.get("/admin/keyring").header(auth).dispatch().await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let auth = login(&client, "admin@example.com").await;
+    let response = client
+        .get("/admin/keyring")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    let keyring: Value = response.into_json().await.expect("keyring");
+    assert_eq!(keyring["active_key_id"], "default");
+
+    let response = client
+        .get("/admin/keyring/rotation")
+        .header(auth)
+        .dispatch()
+        .await;
+    let progress: Value = response.into_json().await.expect("progress");
+    assert_eq!(progress["state"], "idle");
+    assert_eq!(progress["remaining"], 0);
+}
+
+#[rocket::async_test]
+async fn a_sealed_vault_is_unsealed_with_key_shares() {
+    let client = sealed_client().await;
+    let auth = login(&client, "admin@example.com").await;
+    let create = |auth: Header<'static>| {
+        client
+            .post("/create/vault/entry")
+            .header(ContentType::JSON)
+            .header(auth)
+            .body(r#"{"key": "sealed", "value": "ThisShouldBeKeptSecret"}"#)
+            .dispatch()
+    };
+
+    let response = create(auth.clone()).await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    let response = client.get("/sys/seal-status").dispatch().await;
+    let status: Value = response.into_json().await.expect("seal status");
+    assert_eq!(status["initialized"], false);
+    assert_eq!(status["sealed"], true);
+
+    let response = client
+        .post("/sys/init")
+        .header(ContentType::JSON)
+        .body(r#"{"shares": 3, "threshold": 2}"#)
+        .dispatch()
+        .await;
+    let init: Value = response.into_json().await.expect("init response");
+    let shares: Vec<String> = init["shares"]
+        .as_array()
+        .expect("shares")
+        .iter()
+        .map(|share| share.as_str().expect("share").to_string())
+        .collect();
+    assert_eq!(shares.len(), 3);
+
+    let response = client
+        .post("/sys/init")
+        .header(ContentType::JSON)
+        .body(r#"{"shares": 3, "threshold": 2}"#)
+        .dispatch()
+        .await;
+    let error: Value = response.into_json().await.expect("error");
+    assert_eq!(error["status"], 409);
+
+    for (share, sealed) in [(&shares[1], true), (&shares[2], false)] {
+        let response = client
+            .post("/sys/unseal")
+            .header(ContentType::JSON)
+            .body(format!(r#"{{"share": "{}"}}"#, share))
+            .dispatch()
+            .await;
+        let status: Value = response.into_json().await.expect("seal status");
+        assert_eq!(status["sealed"], sealed);
+    }
+
+    let response = create(auth.clone()).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/sys/seal")
+        .header(auth.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .get("/retrieve/vault/entries")
+        .header(auth)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+}